@@ -0,0 +1,216 @@
+use super::huffman::{
+    DecodedSymbol, DecompressOptions, HuffmanCoding, HuffmanDecoding, HuffmanEncoding, TreeFormat,
+};
+use crate::errors::huffman_error::HuffmanError;
+use crate::models::adaptive_huffman_tree::{AdaptiveHuffmanTree, AdaptiveSymbol};
+use crate::models::huffman_tree::HuffmanTree;
+use crate::utils::bit_io::{BitWriter, PackedBits};
+use crate::utils::checksum;
+
+/// One-pass coding: the tree starts empty and is updated after every symbol
+/// (Vitter's algorithm), so no frequency count is needed up front and no tree
+/// is stored in the container. The `huffman_tree` passed to `decode` and
+/// `decode_bytes` is ignored.
+pub struct AdaptiveHuffmanCoding {}
+
+impl AdaptiveHuffmanCoding {
+    fn encode_symbols<T>(symbols: &[T], original: &[u8]) -> Result<HuffmanEncoding<T>, HuffmanError>
+    where
+        T: AdaptiveSymbol,
+    {
+        let mut tree: AdaptiveHuffmanTree<T> = AdaptiveHuffmanTree::new();
+        let mut writer = BitWriter::new();
+
+        match symbols
+            .iter()
+            .try_for_each(|&symbol| tree.encode(symbol, &mut writer))
+        {
+            Ok(()) => Ok(HuffmanEncoding::new(
+                HuffmanTree::default(),
+                TreeFormat::Adaptive,
+                writer.finish(),
+                original.len() as u64,
+                checksum::crc32(original),
+            )),
+            Err(e) => Err(e),
+        }
+    }
+
+    /// Decodes like `decode`, failing as soon as the output grows past the
+    /// limits in `options`.
+    pub fn decode_with_options(
+        encoded_bits: &PackedBits,
+        options: &DecompressOptions,
+    ) -> Result<HuffmanDecoding, HuffmanError> {
+        match Self::decode_symbols::<char>(encoded_bits, options) {
+            Ok(chars) => Ok(HuffmanDecoding::new(chars.into_iter().collect())),
+            Err(e) => Err(e),
+        }
+    }
+
+    /// Decodes like `decode_bytes`, failing as soon as the output grows past
+    /// the limits in `options`.
+    pub fn decode_bytes_with_options(
+        encoded_bits: &PackedBits,
+        options: &DecompressOptions,
+    ) -> Result<Vec<u8>, HuffmanError> {
+        Self::decode_symbols(encoded_bits, options)
+    }
+
+    fn decode_symbols<T>(
+        encoded_bits: &PackedBits,
+        options: &DecompressOptions,
+    ) -> Result<Vec<T>, HuffmanError>
+    where
+        T: AdaptiveSymbol + DecodedSymbol,
+    {
+        let limit = options.output_limit(encoded_bits.as_bytes().len());
+        let mut output_len: usize = 0;
+        let mut tree: AdaptiveHuffmanTree<T> = AdaptiveHuffmanTree::new();
+        let mut reader = encoded_bits.reader();
+        let mut decoded: Vec<T> = Vec::new();
+
+        while reader.remaining() > 0 {
+            let start = reader.position();
+
+            match tree.decode(&mut reader) {
+                Ok(symbol) if output_len + symbol.output_len() > limit => {
+                    let e = HuffmanError::output_limit_exceeded(limit).at_bit(start);
+                    return Err(options.decode_failure(e, &decoded, output_len));
+                }
+                Ok(symbol) => {
+                    output_len += symbol.output_len();
+                    decoded.push(symbol);
+                }
+                Err(e) => {
+                    return Err(options.decode_failure(e.at_bit(start), &decoded, output_len))
+                }
+            }
+        }
+
+        Ok(decoded)
+    }
+}
+
+impl HuffmanCoding for AdaptiveHuffmanCoding {
+    fn encode(text: &str) -> Result<HuffmanEncoding<char>, HuffmanError> {
+        let char_vec: Vec<char> = text.chars().collect();
+
+        Self::encode_symbols(&char_vec, text.as_bytes())
+    }
+
+    fn decode(
+        _huffman_tree: HuffmanTree<char>,
+        encoded_bits: PackedBits,
+    ) -> Result<HuffmanDecoding, HuffmanError> {
+        Self::decode_with_options(&encoded_bits, &DecompressOptions::default())
+    }
+
+    fn encode_bytes(bytes: &[u8]) -> Result<HuffmanEncoding<u8>, HuffmanError> {
+        Self::encode_symbols(bytes, bytes)
+    }
+
+    fn decode_bytes(
+        _huffman_tree: HuffmanTree<u8>,
+        encoded_bits: PackedBits,
+    ) -> Result<Vec<u8>, HuffmanError> {
+        Self::decode_bytes_with_options(&encoded_bits, &DecompressOptions::default())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::errors::huffman_error::DecodeProgress;
+
+    #[test]
+    fn test_roundtrip() {
+        let text = "Welcome to my world!!! Xin chào thế giới ✓✓✓";
+        let serialized = AdaptiveHuffmanCoding::encode(text)
+            .unwrap()
+            .serialize()
+            .unwrap();
+
+        assert_eq!(serialized[5], 0b101);
+        assert_eq!(serialized[19..23], [0, 0, 0, 0]);
+
+        let encoding = HuffmanEncoding::<char>::deserialize(&serialized).unwrap();
+        let tree = encoding.get_huffman_tree().clone();
+        let encoded_bits = encoding.get_encoded_bits().clone();
+        let decoded = AdaptiveHuffmanCoding::decode(tree, encoded_bits).unwrap();
+
+        assert_eq!(encoding.get_tree_format(), TreeFormat::Adaptive);
+        assert_eq!(decoded.get_decoded_text(), text);
+        assert!(encoding.verify(text.as_bytes()).is_ok());
+    }
+
+    #[test]
+    fn test_bytes_roundtrip() {
+        let bytes: Vec<u8> = (0..=255).chain([0, 0, 0, 7, 7, 255]).collect();
+        let serialized = AdaptiveHuffmanCoding::encode_bytes(&bytes)
+            .unwrap()
+            .serialize()
+            .unwrap();
+        let encoding = HuffmanEncoding::<u8>::deserialize(&serialized).unwrap();
+        let tree = encoding.get_huffman_tree().clone();
+        let encoded_bits = encoding.get_encoded_bits().clone();
+
+        assert_eq!(serialized[5], 0b100);
+        assert_eq!(
+            AdaptiveHuffmanCoding::decode_bytes(tree, encoded_bits).unwrap(),
+            bytes
+        );
+    }
+
+    #[test]
+    fn test_compresses_skewed_input() {
+        let text = "aaaaaaaaaaaaaaaabbbbbbbbccccdd".repeat(20);
+        let encoding = AdaptiveHuffmanCoding::encode(&text).unwrap();
+
+        assert!(encoding.get_encoded_bits().len() < text.len() * 2);
+    }
+
+    #[test]
+    fn test_decode_with_options() {
+        let encoding = AdaptiveHuffmanCoding::encode_bytes(&[7; 100]).unwrap();
+        let encoded_bits = encoding.get_encoded_bits();
+        let options = DecompressOptions::new().with_max_output_len(99);
+
+        assert_eq!(
+            AdaptiveHuffmanCoding::decode_bytes_with_options(encoded_bits, &options),
+            Err(HuffmanError::output_limit_exceeded(99)
+                .at_bit(106)
+                .with_progress(DecodeProgress {
+                    symbols: 99,
+                    output_len: 99,
+                    partial_output: None,
+                }))
+        );
+        assert_eq!(
+            AdaptiveHuffmanCoding::decode_bytes_with_options(
+                encoded_bits,
+                &options.with_max_output_len(100)
+            )
+            .unwrap(),
+            vec![7; 100]
+        );
+    }
+
+    #[test]
+    fn test_decode_failed() {
+        let encoding = AdaptiveHuffmanCoding::encode("Welcome to my world!!!").unwrap();
+        let bits = encoding.get_encoded_bits();
+        let truncated: PackedBits = {
+            let mut reader = bits.reader();
+            std::iter::from_fn(|| reader.read_bit())
+                .take(bits.len() - 1)
+                .collect()
+        };
+
+        assert!(AdaptiveHuffmanCoding::decode(HuffmanTree::default(), truncated).is_err());
+
+        let mut serialized = encoding.serialize().unwrap();
+        serialized[5] = 0b111;
+        assert!(HuffmanEncoding::<char>::deserialize(&serialized).is_err());
+    }
+}
@@ -0,0 +1,379 @@
+use std::{
+    collections::HashMap,
+    fs,
+    io::{self, ErrorKind},
+    path::Path,
+};
+
+use crate::errors::huffman_error::HuffmanError;
+use crate::models::decoding_table::DecodingTable;
+use crate::models::frequency_table::FrequencyTable;
+use crate::models::huffman_tree::HuffmanTree;
+use crate::utils::bit_io::{BitWriter, Code, PackedBits};
+use crate::utils::byte_reader::ByteReader;
+use crate::utils::checksum;
+
+/// A `HuffmanTree<char>` trained once on a sample corpus and shared by both
+/// sides, so short messages can be compressed without carrying a tree.
+///
+/// Characters missing from the training data are written as the escape code
+/// followed by the raw 21-bit scalar value.
+///
+/// Dictionary file layout:
+///
+/// | field        | size     | description                               |
+/// |--------------|----------|-------------------------------------------|
+/// | magic        | 4 bytes  | `0x89 'H' 'U' 'D'`                        |
+/// | version      | `u8`     | currently `1`                             |
+/// | id           | `u32`    | CRC-32 of the fields below                |
+/// | name len     | `u8`     |                                           |
+/// | name         | variable | UTF-8                                     |
+/// | code lengths | variable | as in `serialize_code_lengths`            |
+///
+/// Message layout: one tag byte (`0xd8` with the number of padding bits in
+/// the low 3 bits), the dictionary id as a big-endian `u32`, then the payload.
+#[derive(Clone)]
+pub struct HuffmanDictionary {
+    name: String,
+    id: u32,
+    huffman_tree: HuffmanTree<char>,
+    /// The tree as written by `serialize_code_lengths`.
+    code_lengths: Vec<u8>,
+    decoding_table: DecodingTable<char>,
+    encoding_map: HashMap<char, Code>,
+    escape_code: Code,
+}
+
+impl HuffmanDictionary {
+    /// U+10FFFF is a permanent noncharacter, so it stands in for the escape
+    /// symbol in the tree. Real occurrences of it are escaped like any other
+    /// unknown character.
+    pub const ESCAPE: char = '\u{10FFFF}';
+    const ESCAPED_BITS: usize = 21;
+    const MAGIC: [u8; 4] = [0x89, b'H', b'U', b'D'];
+    const VERSION: u8 = 1;
+    const MESSAGE_TAG: u8 = 0xd8;
+    const PADDING_MASK: u8 = 0b0000_0111;
+
+    /// Builds a dictionary from the character counts of `samples`.
+    pub fn train<'a, I>(name: &str, samples: I) -> Result<Self, HuffmanError>
+    where
+        I: IntoIterator<Item = &'a str>,
+    {
+        let mut frequency_table = FrequencyTable::new();
+        samples.into_iter().for_each(|sample| {
+            sample
+                .chars()
+                .for_each(|symbol| frequency_table.add(symbol, 1))
+        });
+        Self::from_frequencies(name, frequency_table)
+    }
+
+    /// Builds a dictionary from precomputed character counts.
+    pub fn from_frequencies(
+        name: &str,
+        mut frequency_table: FrequencyTable<char>,
+    ) -> Result<Self, HuffmanError> {
+        frequency_table.add(Self::ESCAPE, 1);
+
+        match frequency_table
+            .into_tree_with_max_length(HuffmanTree::<char>::DEFAULT_MAX_CODE_LENGTH)
+            .and_then(|tree| tree.to_canonical())
+        {
+            Ok(tree) => Self::new(name, tree),
+            Err(e) => Err(e),
+        }
+    }
+
+    fn new(name: &str, huffman_tree: HuffmanTree<char>) -> Result<Self, HuffmanError> {
+        if name.is_empty() || name.len() > u8::MAX as usize {
+            return Err(HuffmanError::invalid_dictionary_name(name));
+        }
+
+        match (
+            huffman_tree.get_encoding_map(),
+            DecodingTable::from_tree(&huffman_tree),
+            huffman_tree.serialize_code_lengths(),
+        ) {
+            (Ok(encoding_map), Ok(decoding_table), Ok(code_lengths)) => {
+                match encoding_map.get(&Self::ESCAPE) {
+                    Some(&escape_code) => Ok(Self {
+                        name: name.to_string(),
+                        id: Self::calculate_id(name, &code_lengths),
+                        huffman_tree,
+                        code_lengths,
+                        decoding_table,
+                        encoding_map,
+                        escape_code,
+                    }),
+                    None => Err(HuffmanError::invalid_huffman_tree()),
+                }
+            }
+            (Err(e), _, _) | (_, Err(e), _) | (_, _, Err(e)) => Err(e),
+        }
+    }
+
+    /// Covers the name as well as the code lengths, so dictionaries that
+    /// only differ in name do not accept each other's messages.
+    fn calculate_id(name: &str, code_lengths: &[u8]) -> u32 {
+        let mut bytes: Vec<u8> = Vec::with_capacity(1 + name.len() + code_lengths.len());
+
+        bytes.push(name.len() as u8);
+        bytes.extend_from_slice(name.as_bytes());
+        bytes.extend_from_slice(code_lengths);
+        checksum::crc32(&bytes)
+    }
+
+    pub fn get_name(&self) -> &str {
+        &self.name
+    }
+
+    pub fn get_id(&self) -> u32 {
+        self.id
+    }
+
+    pub fn get_huffman_tree(&self) -> &HuffmanTree<char> {
+        &self.huffman_tree
+    }
+
+    pub fn serialize(&self) -> Vec<u8> {
+        let mut result: Vec<u8> = Vec::new();
+
+        result.extend_from_slice(&Self::MAGIC);
+        result.push(Self::VERSION);
+        result.extend_from_slice(&self.id.to_be_bytes());
+        result.push(self.name.len() as u8);
+        result.extend_from_slice(self.name.as_bytes());
+        result.extend_from_slice(&self.code_lengths);
+        result
+    }
+
+    pub fn deserialize(input: &[u8]) -> Result<Self, HuffmanError> {
+        let error = HuffmanError::cannot_deserialize_tree();
+        let mut reader = ByteReader::new(input);
+
+        match reader.read_bytes(Self::MAGIC.len()) {
+            Some(magic) if magic == Self::MAGIC => {}
+            _ => return Err(HuffmanError::bad_magic().at_byte(0)),
+        }
+        match reader.read_u8() {
+            Some(Self::VERSION) => {}
+            Some(version) => {
+                return Err(HuffmanError::unsupported_version(version).at_byte(Self::MAGIC.len()))
+            }
+            None => return Err(error),
+        }
+
+        let (id, name) = match (reader.read_u32(), reader.read_u8()) {
+            (Some(id), Some(name_len)) => match reader.read_bytes(name_len as usize) {
+                Some(name) => match std::str::from_utf8(name) {
+                    Ok(name) => (id, name),
+                    Err(_) => return Err(error),
+                },
+                None => return Err(error),
+            },
+            _ => return Err(error),
+        };
+
+        match HuffmanTree::<char>::deserialize_code_lengths(reader.read_to_end()) {
+            Ok(tree) => match Self::new(name, tree) {
+                Ok(dictionary) if dictionary.id == id => Ok(dictionary),
+                Ok(_) => Err(HuffmanError::checksum_mismatch()),
+                Err(e) => Err(e),
+            },
+            Err(e) => Err(e),
+        }
+    }
+
+    /// Writes the dictionary to `path`.
+    pub fn save<P: AsRef<Path>>(&self, path: P) -> io::Result<()> {
+        fs::write(path, self.serialize())
+    }
+
+    /// Reads a dictionary written by `save`.
+    pub fn load<P: AsRef<Path>>(path: P) -> io::Result<Self> {
+        match fs::read(path) {
+            Ok(bytes) => {
+                Self::deserialize(&bytes).map_err(|e| io::Error::new(ErrorKind::InvalidData, e))
+            }
+            Err(e) => Err(e),
+        }
+    }
+
+    pub fn compress(&self, text: &str) -> Vec<u8> {
+        let mut writer = BitWriter::new();
+
+        text.chars()
+            .for_each(|symbol| match self.encoding_map.get(&symbol) {
+                Some(code) if symbol != Self::ESCAPE => writer.write_code(code),
+                _ => {
+                    writer.write_code(&self.escape_code);
+                    writer.write_bits(symbol as u64, Self::ESCAPED_BITS);
+                }
+            });
+
+        let encoded_bits = writer.finish();
+        let mut result: Vec<u8> = Vec::with_capacity(5 + encoded_bits.as_bytes().len());
+
+        result.push(Self::MESSAGE_TAG | encoded_bits.padding() as u8);
+        result.extend_from_slice(&self.id.to_be_bytes());
+        result.extend_from_slice(encoded_bits.as_bytes());
+        result
+    }
+
+    pub fn decompress(&self, input: &[u8]) -> Result<String, HuffmanError> {
+        let encoded_bits = match Self::parse_message(input) {
+            Some((id, encoded_bits)) if id == self.id => encoded_bits,
+            Some((id, _)) => return Err(HuffmanError::unknown_dictionary(id)),
+            None => return Err(HuffmanError::cannot_deserialize_tree()),
+        };
+        let mut reader = encoded_bits.reader();
+        let mut text = String::new();
+
+        while reader.remaining() > 0 {
+            let start = reader.position();
+
+            match self.decoding_table.decode_next(&mut reader) {
+                Ok(Self::ESCAPE) if reader.remaining() >= Self::ESCAPED_BITS => {
+                    let value = reader.peek(Self::ESCAPED_BITS) as u32;
+                    reader.consume(Self::ESCAPED_BITS);
+
+                    match char::from_u32(value) {
+                        Some(symbol) => text.push(symbol),
+                        None => return Err(HuffmanError::decoding_error().at_bit(start)),
+                    }
+                }
+                Ok(Self::ESCAPE) => return Err(HuffmanError::decoding_error().at_bit(start)),
+                Ok(symbol) => text.push(symbol),
+                Err(e) => return Err(e),
+            }
+        }
+
+        Ok(text)
+    }
+
+    /// Returns the id of the dictionary a message was compressed with, or
+    /// `None` if `input` is not a dictionary message.
+    pub fn message_id(input: &[u8]) -> Option<u32> {
+        Self::parse_message(input).map(|(id, _)| id)
+    }
+
+    fn parse_message(input: &[u8]) -> Option<(u32, PackedBits)> {
+        let mut reader = ByteReader::new(input);
+
+        match (reader.read_u8(), reader.read_u32()) {
+            (Some(tag), Some(id)) if tag & !Self::PADDING_MASK == Self::MESSAGE_TAG => {
+                let payload = reader.read_to_end();
+                let padding = (tag & Self::PADDING_MASK) as usize;
+
+                match (payload.len() * 8).checked_sub(padding) {
+                    Some(len) => PackedBits::new(payload.to_vec(), len).map(|bits| (id, bits)),
+                    _ => None,
+                }
+            }
+            _ => None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const CORPUS: [&str; 3] = [
+        "Huffman coding is a lossless data compression algorithm.",
+        "The idea is to assign variable-length codes to input characters.",
+        "Lengths of the assigned codes are based on how frequently every character occurs.",
+    ];
+
+    #[test]
+    fn test_roundtrip() {
+        let dictionary = HuffmanDictionary::train("english", CORPUS).unwrap();
+
+        for text in [
+            "Huffman-ft-uyen",
+            "",
+            "a",
+            "Xin chào thế giới ✓",
+            "\u{10FFFF}",
+        ] {
+            let compressed = dictionary.compress(text);
+            assert_eq!(dictionary.decompress(&compressed).unwrap(), text);
+            assert_eq!(
+                HuffmanDictionary::message_id(&compressed),
+                Some(dictionary.get_id())
+            );
+        }
+    }
+
+    #[test]
+    fn test_smaller_than_input() {
+        let dictionary = HuffmanDictionary::train("english", CORPUS).unwrap();
+        let text = "the codes are assigned to characters";
+
+        assert!(dictionary.compress(text).len() < text.len());
+        assert!(
+            dictionary.compress("Huffman-ft-uyen").len()
+                < crate::compress("Huffman-ft-uyen").unwrap().len() / 2
+        );
+    }
+
+    #[test]
+    fn test_serialize_roundtrip() {
+        let dictionary = HuffmanDictionary::train("english", CORPUS).unwrap();
+        let loaded = HuffmanDictionary::deserialize(&dictionary.serialize()).unwrap();
+        let compressed = dictionary.compress("Huffman-ft-uyen");
+
+        assert_eq!(loaded.get_name(), "english");
+        assert_eq!(loaded.get_id(), dictionary.get_id());
+        assert_eq!(loaded.decompress(&compressed).unwrap(), "Huffman-ft-uyen");
+    }
+
+    #[test]
+    fn test_save_and_load() {
+        let dictionary = HuffmanDictionary::train("english", CORPUS).unwrap();
+        let path = std::env::temp_dir().join(format!("huffman-dictionary-{}", std::process::id()));
+
+        dictionary.save(&path).unwrap();
+        let loaded = HuffmanDictionary::load(&path);
+        fs::remove_file(&path).unwrap();
+
+        assert_eq!(loaded.unwrap().get_id(), dictionary.get_id());
+    }
+
+    #[test]
+    fn test_failed() {
+        let dictionary = HuffmanDictionary::train("english", CORPUS).unwrap();
+        let other = HuffmanDictionary::train("other", ["0123456789"]).unwrap();
+        let compressed = other.compress("42");
+
+        assert_eq!(
+            dictionary.decompress(&compressed),
+            Err(HuffmanError::unknown_dictionary(other.get_id()))
+        );
+        assert!(dictionary.decompress(&compressed[..3]).is_err());
+
+        // Same training data, so the same code lengths, under another name.
+        let renamed = HuffmanDictionary::train("english-v2", CORPUS).unwrap();
+        assert_eq!(
+            renamed.get_huffman_tree().serialize_code_lengths(),
+            dictionary.get_huffman_tree().serialize_code_lengths()
+        );
+        assert_ne!(renamed.get_id(), dictionary.get_id());
+        assert_eq!(
+            dictionary.decompress(&renamed.compress("42")),
+            Err(HuffmanError::unknown_dictionary(renamed.get_id()))
+        );
+        assert!(HuffmanDictionary::train("", CORPUS).is_err());
+        assert!(HuffmanDictionary::train(&"x".repeat(256), CORPUS).is_err());
+
+        let mut serialized = dictionary.serialize();
+        let last = serialized.len() - 1;
+        serialized[last] += 1;
+        assert!(HuffmanDictionary::deserialize(&serialized).is_err());
+        assert_eq!(
+            HuffmanDictionary::deserialize(b"HUFD").err(),
+            Some(HuffmanError::bad_magic().at_byte(0))
+        );
+    }
+}
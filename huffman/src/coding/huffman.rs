@@ -1,102 +1,494 @@
-use crate::errors::huffman_error::HuffmanError;
-use crate::models::huffman_tree::HuffmanTree;
-use crate::utils::type_converting;
+use std::hash::Hash;
+
+use super::adaptive_huffman::AdaptiveHuffmanCoding;
+use super::interleaved::InterleavedHuffmanCoding;
+use super::single_symbol::SingleSymbolCoding;
+use super::stored::StoredCoding;
+use crate::errors::huffman_error::{DecodeProgress, HuffmanError};
+use crate::models::decoding_table::DecodingTable;
+use crate::models::huffman_tree::{HuffmanNode, HuffmanTree};
+use crate::utils::bit_io::{BitWriter, PackedBits};
+use crate::utils::{byte_reader::ByteReader, checksum, type_converting};
+
+/// How the tree is written into the serialized container.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum TreeFormat {
+    /// The whole tree shape in preorder.
+    Shape,
+    /// Only the `(symbol, code length)` pairs. The tree must be canonical
+    /// (see `HuffmanTree::to_canonical`) so the decoder can rebuild it.
+    CodeLengths,
+    /// No tree at all: it is rebuilt while decoding (see `AdaptiveHuffmanCoding`).
+    Adaptive,
+    /// No tree and no coding: the payload is the original data (see `StoredCoding`).
+    Stored,
+    /// One symbol repeated: the tree is just that symbol and the payload a
+    /// `u64` run count (see `SingleSymbolCoding`).
+    Run,
+    /// Code lengths as in `CodeLengths`, with the payload split into
+    /// interleaved streams (see `InterleavedHuffmanCoding`).
+    Interleaved,
+}
 
 #[derive(Clone)]
-pub struct HuffmanEncoding {
-    huffman_tree: HuffmanTree<char>,
-    encoded_vec: Vec<bool>,
+pub struct HuffmanEncoding<T> {
+    huffman_tree: HuffmanTree<T>,
+    tree_format: TreeFormat,
+    encoded_bits: PackedBits,
+    original_len: u64,
+    checksum: u32,
 }
 
-impl HuffmanEncoding {
-    const ASCII_FORM: usize = 7;
-    const DELIMITER: &'static str = "-";
+impl<T> HuffmanEncoding<T> {
+    const MAGIC: [u8; 4] = [0x89, b'H', b'U', b'F'];
+    const VERSION: u8 = 1;
+    const FLAG_TEXT: u8 = 0b0000_0001;
+    const FLAG_CODE_LENGTHS: u8 = 0b0000_0010;
+    const FLAG_ADAPTIVE: u8 = 0b0000_0100;
+    const FLAG_STORED: u8 = 0b0000_1000;
+    const FLAG_RUN: u8 = 0b0001_0000;
+    const FLAG_INTERLEAVED: u8 = 0b0010_0000;
+    /// Size of the container without the tree and the payload.
+    pub const HEADER_LEN: usize = 23;
+    const FLAGS_OFFSET: usize = 5;
+    const ORIGINAL_LEN_OFFSET: usize = 6;
+    const PADDING_OFFSET: usize = 18;
+    const TREE_LEN_OFFSET: usize = 19;
+    const BYTE_FORM: usize = 8;
+    /// Larger than the serialized tree of every possible `char`, so anything
+    /// above it is rejected before the tree is parsed.
+    const MAX_TREE_LEN: u32 = 8 << 20;
 
-    pub fn new(huffman_tree: HuffmanTree<char>, encoded_vec: Vec<bool>) -> Self {
+    pub fn new(
+        huffman_tree: HuffmanTree<T>,
+        tree_format: TreeFormat,
+        encoded_bits: PackedBits,
+        original_len: u64,
+        checksum: u32,
+    ) -> Self {
         Self {
-            huffman_tree: huffman_tree,
-            encoded_vec: encoded_vec,
+            huffman_tree,
+            tree_format,
+            encoded_bits,
+            original_len,
+            checksum,
         }
     }
 
-    pub fn get_encoded_vec(&self) -> &Vec<bool> {
-        &self.encoded_vec
+    pub fn get_encoded_bits(&self) -> &PackedBits {
+        &self.encoded_bits
     }
 
-    pub fn get_huffman_tree(&self) -> &HuffmanTree<char> {
+    pub fn get_huffman_tree(&self) -> &HuffmanTree<T> {
         &self.huffman_tree
     }
 
-    pub fn serialize(&self) -> String {
-        let mut encoded_vec = self.encoded_vec.clone();
-        let filled_bits = Self::fill_bits(&mut encoded_vec);
-        let tree_str = self.huffman_tree.serialize();
-        let tree_size = tree_str.len();
-        let encoded_vec_str =
-            type_converting::vec_bool_to_string(&mut encoded_vec, Self::ASCII_FORM);
+    pub fn get_tree_format(&self) -> TreeFormat {
+        self.tree_format
+    }
 
-        let components_list = vec![
-            filled_bits.to_string(),
-            tree_size.to_string(),
-            tree_str + &encoded_vec_str,
-        ];
+    pub fn get_original_len(&self) -> u64 {
+        self.original_len
+    }
+
+    pub fn get_checksum(&self) -> u32 {
+        self.checksum
+    }
+
+    /// Checks decoded data against the length and CRC32 recorded when it was encoded.
+    pub fn verify(&self, decoded: &[u8]) -> Result<(), HuffmanError> {
+        if decoded.len() as u64 == self.original_len && checksum::crc32(decoded) == self.checksum {
+            Ok(())
+        } else {
+            Err(HuffmanError::checksum_mismatch())
+        }
+    }
+
+    /// Fails early when the recorded length already exceeds the limits in
+    /// `options`, before any decoding is done.
+    fn check_output_limit(&self, options: &DecompressOptions) -> Result<(), HuffmanError> {
+        let limit = options.output_limit(self.encoded_bits.as_bytes().len());
+
+        if self.original_len > limit as u64 {
+            Err(HuffmanError::output_limit_exceeded(limit))
+        } else {
+            Ok(())
+        }
+    }
 
-        return components_list.join(Self::DELIMITER);
+    /// Lays out the container:
+    ///
+    /// | field        | size     |                                         |
+    /// |--------------|----------|-----------------------------------------|
+    /// | magic        | 4 bytes  | `0x89 'H' 'U' 'F'`                      |
+    /// | version      | `u8`     | currently `1`                           |
+    /// | flags        | `u8`     | bit 0: text (`char`) encoding           |
+    /// |              |          | bit 1: tree stored as code lengths      |
+    /// |              |          | bit 2: adaptive, no tree stored         |
+    /// |              |          | bit 3: stored, payload is the original  |
+    /// |              |          | bit 4: one symbol and its run count     |
+    /// |              |          | bit 5: code lengths, interleaved streams |
+    /// | original len | `u64`    | length of the original data in bytes    |
+    /// | checksum     | `u32`    | CRC32 of the original data              |
+    /// | padding      | `u8`     | unused bits at the end of the payload   |
+    /// | tree len     | `u32`    | length of the serialized tree           |
+    /// | tree         | variable |                                         |
+    /// | payload      | variable | encoded bits packed 8 per byte          |
+    ///
+    /// All integers are big-endian.
+    fn serialize_with_tree(&self, symbol_flags: u8, mut tree_bytes: Vec<u8>) -> Vec<u8> {
+        let mut result: Vec<u8> = Vec::new();
+        let flags = match self.tree_format {
+            TreeFormat::Shape => symbol_flags,
+            TreeFormat::CodeLengths => symbol_flags | Self::FLAG_CODE_LENGTHS,
+            TreeFormat::Adaptive => symbol_flags | Self::FLAG_ADAPTIVE,
+            TreeFormat::Stored => symbol_flags | Self::FLAG_STORED,
+            TreeFormat::Run => symbol_flags | Self::FLAG_RUN,
+            TreeFormat::Interleaved => symbol_flags | Self::FLAG_INTERLEAVED,
+        };
+
+        result.extend_from_slice(&Self::MAGIC);
+        result.push(Self::VERSION);
+        result.push(flags);
+        result.extend_from_slice(&self.original_len.to_be_bytes());
+        result.extend_from_slice(&self.checksum.to_be_bytes());
+        result.push(self.encoded_bits.padding() as u8);
+        result.extend_from_slice(&(tree_bytes.len() as u32).to_be_bytes());
+        result.append(&mut tree_bytes);
+        result.extend_from_slice(self.encoded_bits.as_bytes());
+        result
     }
 
-    pub fn deserialize(input: String) -> Result<HuffmanEncoding, HuffmanError> {
+    /// Validates the container header and rebuilds the encoding, leaving the
+    /// tree itself to `deserialize_tree`.
+    fn deserialize_with_tree<F>(
+        input: &[u8],
+        symbol_flags: u8,
+        deserialize_tree: F,
+    ) -> Result<HuffmanEncoding<T>, HuffmanError>
+    where
+        F: FnOnce(&[u8], TreeFormat) -> Result<HuffmanTree<T>, HuffmanError>,
+    {
         let error = HuffmanError::cannot_deserialize_tree();
+        let mut reader = ByteReader::new(input);
+
+        match reader.read_bytes(Self::MAGIC.len()) {
+            Some(magic) if magic == Self::MAGIC => {}
+            _ => return Err(HuffmanError::bad_magic().at_byte(0)),
+        }
+        match reader.read_u8() {
+            Some(Self::VERSION) => {}
+            Some(version) => {
+                return Err(HuffmanError::unsupported_version(version).at_byte(Self::MAGIC.len()))
+            }
+            None => return Err(error.at_byte(reader.position())),
+        }
 
-        match input.split_once(HuffmanEncoding::DELIMITER) {
-            Some((filled_bits_str, remaining)) => {
-                match remaining.split_once(HuffmanEncoding::DELIMITER) {
-                    Some((tree_size_str, remaining)) => {
-                        match type_converting::str_to_usize(tree_size_str) {
-                            Ok(tree_size) => {
-                                let (tree_str, encoded_str) =
-                                    remaining.split_at(tree_size as usize);
-
-                                match (
-                                    type_converting::str_to_usize(filled_bits_str),
-                                    HuffmanTree::deserialize(tree_str.to_string()),
-                                ) {
-                                    (Ok(filled_bits), Ok(huffman_tree)) => {
-                                        let encoded_vec = HuffmanEncoding::calculate_encoded_vec(
-                                            encoded_str,
-                                            filled_bits as usize,
-                                        );
-
-                                        Ok(HuffmanEncoding::new(huffman_tree, encoded_vec))
-                                    }
-                                    _ => Err(error.clone()),
-                                }
-                            }
-                            Err(_) => Err(error.clone()),
+        match (
+            reader.read_u8(),
+            reader.read_u64(),
+            reader.read_u32(),
+            reader.read_u8(),
+            reader.read_u32(),
+        ) {
+            (
+                Some(stored_flags),
+                Some(original_len),
+                Some(checksum),
+                Some(filled_bits),
+                Some(tree_size),
+            ) if stored_flags
+                & !(Self::FLAG_CODE_LENGTHS
+                    | Self::FLAG_ADAPTIVE
+                    | Self::FLAG_STORED
+                    | Self::FLAG_RUN
+                    | Self::FLAG_INTERLEAVED)
+                == symbol_flags =>
+            {
+                if tree_size > Self::MAX_TREE_LEN {
+                    return Err(HuffmanError::invalid_declared_length(
+                        "tree length",
+                        tree_size as u64,
+                    )
+                    .at_byte(Self::TREE_LEN_OFFSET));
+                }
+                let tree_format = match stored_flags & !symbol_flags {
+                    0 => TreeFormat::Shape,
+                    Self::FLAG_CODE_LENGTHS => TreeFormat::CodeLengths,
+                    Self::FLAG_ADAPTIVE if tree_size == 0 => TreeFormat::Adaptive,
+                    Self::FLAG_STORED if tree_size == 0 && filled_bits == 0 => TreeFormat::Stored,
+                    Self::FLAG_RUN if filled_bits == 0 => TreeFormat::Run,
+                    Self::FLAG_INTERLEAVED => TreeFormat::Interleaved,
+                    _ => return Err(error.at_byte(Self::FLAGS_OFFSET)),
+                };
+
+                match reader.read_bytes(tree_size as usize) {
+                    Some(tree_bytes) => match (
+                        deserialize_tree(tree_bytes, tree_format),
+                        Self::calculate_encoded_bits(reader.read_to_end(), filled_bits as usize),
+                    ) {
+                        (Ok(_), Some(encoded_bits))
+                            if !Self::is_plausible_original_len(
+                                original_len,
+                                tree_format,
+                                symbol_flags,
+                                tree_bytes.len(),
+                                &encoded_bits,
+                            ) =>
+                        {
+                            Err(HuffmanError::invalid_declared_length(
+                                "original length",
+                                original_len,
+                            )
+                            .at_byte(Self::ORIGINAL_LEN_OFFSET))
                         }
-                    }
-                    None => Err(error),
+                        (Ok(huffman_tree), Some(encoded_bits)) => Ok(HuffmanEncoding::new(
+                            huffman_tree,
+                            tree_format,
+                            encoded_bits,
+                            original_len,
+                            checksum,
+                        )),
+                        (Err(e), _) => Err(e.at_byte(Self::HEADER_LEN)),
+                        _ => Err(error.at_byte(Self::PADDING_OFFSET)),
+                    },
+                    None => Err(error.at_byte(input.len())),
+                }
+            }
+            (Some(_), Some(_), Some(_), Some(_), Some(_)) => Err(error.at_byte(Self::FLAGS_OFFSET)),
+            _ => Err(error.at_byte(input.len())),
+        }
+    }
+
+    /// Whether `encoded_bits` can decode to `original_len` bytes. Every symbol
+    /// takes at least one bit and at most 4 bytes, except in a run, which
+    /// decodes to exactly its count times the symbol stored as the tree.
+    fn is_plausible_original_len(
+        original_len: u64,
+        tree_format: TreeFormat,
+        symbol_flags: u8,
+        tree_len: usize,
+        encoded_bits: &PackedBits,
+    ) -> bool {
+        match tree_format {
+            TreeFormat::Run => match SingleSymbolCoding::run_count(encoded_bits) {
+                Some(count) => count.checked_mul(tree_len as u64) == Some(original_len),
+                None => false,
+            },
+            _ => original_len <= encoded_bits.len() as u64 * Self::max_symbol_len(symbol_flags),
+        }
+    }
+
+    /// Longest encoding in bytes of a single symbol of the original data.
+    fn max_symbol_len(symbol_flags: u8) -> u64 {
+        if symbol_flags & Self::FLAG_TEXT != 0 {
+            4
+        } else {
+            1
+        }
+    }
+
+    fn calculate_encoded_bits(input: &[u8], filled_bits: usize) -> Option<PackedBits> {
+        if filled_bits >= Self::BYTE_FORM || filled_bits > input.len() * Self::BYTE_FORM {
+            return None;
+        }
+
+        PackedBits::new(input.to_vec(), input.len() * Self::BYTE_FORM - filled_bits)
+    }
+}
+
+impl<T> HuffmanEncoding<T>
+where
+    T: Eq + Hash + Copy + Ord + ToString,
+    HuffmanNode<T>: ToString,
+{
+    /// The only symbol of a `TreeFormat::Run` encoding.
+    fn run_symbol(&self) -> Option<T> {
+        match self.huffman_tree.get_code_lengths() {
+            Ok(lengths) if lengths.len() == 1 => Some(lengths[0].0),
+            _ => None,
+        }
+    }
+}
+
+impl HuffmanEncoding<char> {
+    /// Fails if the tree cannot be written in its format: code lengths need
+    /// a non-empty canonical tree, and a run a single-symbol one.
+    pub fn serialize(&self) -> Result<Vec<u8>, HuffmanError> {
+        let tree_bytes = match self.tree_format {
+            TreeFormat::Shape => self.huffman_tree.serialize(),
+            TreeFormat::CodeLengths | TreeFormat::Interleaved => {
+                self.huffman_tree.serialize_code_lengths()
+            }
+            TreeFormat::Adaptive | TreeFormat::Stored => Ok(Vec::new()),
+            TreeFormat::Run => match self.run_symbol() {
+                Some(symbol) => Ok(symbol.to_string().into_bytes()),
+                None => Err(HuffmanError::cannot_serialize_tree()),
+            },
+        };
+
+        match tree_bytes {
+            Ok(tree_bytes) => Ok(self.serialize_with_tree(Self::FLAG_TEXT, tree_bytes)),
+            Err(e) => Err(e),
+        }
+    }
+
+    /// Serializes the encoding, or `text` as a stored container when coding
+    /// it made the container larger than the text itself.
+    pub fn serialize_or_stored(&self, text: &str) -> Result<Vec<u8>, HuffmanError> {
+        match self.serialize() {
+            Ok(serialized) if serialized.len() > Self::HEADER_LEN + text.len() => {
+                StoredCoding::encode_text(text).serialize()
+            }
+            result => result,
+        }
+    }
+
+    pub fn deserialize(input: &[u8]) -> Result<HuffmanEncoding<char>, HuffmanError> {
+        Self::deserialize_with_tree(input, Self::FLAG_TEXT, |tree_bytes, tree_format| {
+            match tree_format {
+                TreeFormat::Shape => HuffmanTree::<char>::deserialize(tree_bytes),
+                TreeFormat::CodeLengths | TreeFormat::Interleaved => {
+                    HuffmanTree::<char>::deserialize_code_lengths(tree_bytes)
                 }
+                TreeFormat::Adaptive | TreeFormat::Stored => Ok(HuffmanTree::default()),
+                TreeFormat::Run => match type_converting::read_utf8_char(tree_bytes) {
+                    Some((symbol, width)) if width == tree_bytes.len() => {
+                        HuffmanTree::from_code_lengths(&[(symbol, 1)])
+                    }
+                    _ => Err(HuffmanError::cannot_deserialize_tree()),
+                },
             }
-            None => Err(error),
+        })
+    }
+
+    pub fn decode(&self) -> Result<String, HuffmanError> {
+        self.decode_with_options(&DecompressOptions::default())
+    }
+
+    /// Decodes the payload with the coding its tree format calls for, then
+    /// checks the result against the recorded length and checksum.
+    pub fn decode_with_options(&self, options: &DecompressOptions) -> Result<String, HuffmanError> {
+        let tree = &self.huffman_tree;
+        let encoded_bits = &self.encoded_bits;
+
+        self.check_output_limit(options)?;
+        let decoding_result = match self.tree_format {
+            TreeFormat::Shape | TreeFormat::CodeLengths => {
+                StandardHuffmanCoding::decode_with_options(tree, encoded_bits, options)
+            }
+            TreeFormat::Adaptive => {
+                AdaptiveHuffmanCoding::decode_with_options(encoded_bits, options)
+            }
+            TreeFormat::Stored => StoredCoding::decode_with_options(encoded_bits, options),
+            TreeFormat::Run => SingleSymbolCoding::decode_with_options(
+                tree,
+                encoded_bits,
+                self.original_len,
+                options,
+            ),
+            TreeFormat::Interleaved => {
+                InterleavedHuffmanCoding::decode_with_options(tree, encoded_bits, options)
+            }
+        };
+
+        match decoding_result {
+            Ok(decoding) => match self.verify(decoding.get_decoded_text().as_bytes()) {
+                Ok(()) => Ok(decoding.get_decoded_text().clone()),
+                Err(e) => Err(e),
+            },
+            Err(e) => Err(e),
         }
     }
+}
+
+impl HuffmanEncoding<u8> {
+    /// Fails if the tree cannot be written in its format: code lengths need
+    /// a non-empty canonical tree, and a run a single-symbol one.
+    pub fn serialize(&self) -> Result<Vec<u8>, HuffmanError> {
+        let tree_bytes = match self.tree_format {
+            TreeFormat::Shape => self.huffman_tree.serialize(),
+            TreeFormat::CodeLengths | TreeFormat::Interleaved => {
+                self.huffman_tree.serialize_code_lengths()
+            }
+            TreeFormat::Adaptive | TreeFormat::Stored => Ok(Vec::new()),
+            TreeFormat::Run => match self.run_symbol() {
+                Some(symbol) => Ok(vec![symbol]),
+                None => Err(HuffmanError::cannot_serialize_tree()),
+            },
+        };
 
-    fn fill_bits(bits: &mut Vec<bool>) -> usize {
-        let bits_should_fill =
-            HuffmanEncoding::ASCII_FORM - (bits.len() % HuffmanEncoding::ASCII_FORM);
-        for _ in 0..bits_should_fill {
-            bits.push(false);
+        match tree_bytes {
+            Ok(tree_bytes) => Ok(self.serialize_with_tree(0, tree_bytes)),
+            Err(e) => Err(e),
         }
+    }
 
-        bits_should_fill
+    /// Serializes the encoding, or `bytes` as a stored container when coding
+    /// them made the container larger than the bytes themselves.
+    pub fn serialize_or_stored(&self, bytes: &[u8]) -> Result<Vec<u8>, HuffmanError> {
+        match self.serialize() {
+            Ok(serialized) if serialized.len() > Self::HEADER_LEN + bytes.len() => {
+                StoredCoding::encode_raw(bytes).serialize()
+            }
+            result => result,
+        }
+    }
+
+    pub fn deserialize(input: &[u8]) -> Result<HuffmanEncoding<u8>, HuffmanError> {
+        Self::deserialize_with_tree(input, 0, |tree_bytes, tree_format| match tree_format {
+            TreeFormat::Shape => HuffmanTree::<u8>::deserialize(tree_bytes),
+            TreeFormat::CodeLengths | TreeFormat::Interleaved => {
+                HuffmanTree::<u8>::deserialize_code_lengths(tree_bytes)
+            }
+            TreeFormat::Adaptive | TreeFormat::Stored => Ok(HuffmanTree::default()),
+            TreeFormat::Run => match tree_bytes {
+                &[symbol] => HuffmanTree::from_code_lengths(&[(symbol, 1)]),
+                _ => Err(HuffmanError::cannot_deserialize_tree()),
+            },
+        })
     }
 
-    fn calculate_encoded_vec(input: &str, filled_bits: usize) -> Vec<bool> {
-        let mut full_vec = type_converting::string_to_vec_bool(input, HuffmanEncoding::ASCII_FORM);
-        let new_len = full_vec.len() - filled_bits;
+    pub fn decode(&self) -> Result<Vec<u8>, HuffmanError> {
+        self.decode_with_options(&DecompressOptions::default())
+    }
+
+    /// Decodes the payload with the coding its tree format calls for, then
+    /// checks the result against the recorded length and checksum.
+    pub fn decode_with_options(
+        &self,
+        options: &DecompressOptions,
+    ) -> Result<Vec<u8>, HuffmanError> {
+        let tree = &self.huffman_tree;
+        let encoded_bits = &self.encoded_bits;
+
+        self.check_output_limit(options)?;
+        let decoding_result = match self.tree_format {
+            TreeFormat::Shape | TreeFormat::CodeLengths => {
+                StandardHuffmanCoding::decode_bytes_with_options(tree, encoded_bits, options)
+            }
+            TreeFormat::Adaptive => {
+                AdaptiveHuffmanCoding::decode_bytes_with_options(encoded_bits, options)
+            }
+            TreeFormat::Stored => StoredCoding::decode_bytes_with_options(encoded_bits, options),
+            TreeFormat::Run => SingleSymbolCoding::decode_bytes_with_options(
+                tree,
+                encoded_bits,
+                self.original_len,
+                options,
+            ),
+            TreeFormat::Interleaved => {
+                InterleavedHuffmanCoding::decode_bytes_with_options(tree, encoded_bits, options)
+            }
+        };
 
-        let _ = full_vec.split_off(new_len);
-        full_vec
+        match decoding_result {
+            Ok(decoded) => match self.verify(&decoded) {
+                Ok(()) => Ok(decoded),
+                Err(e) => Err(e),
+            },
+            Err(e) => Err(e),
+        }
     }
 }
 
@@ -107,9 +499,7 @@ pub struct HuffmanDecoding {
 
 impl HuffmanDecoding {
     pub fn new(decoded_text: String) -> Self {
-        Self {
-            decoded_text: decoded_text,
-        }
+        Self { decoded_text }
     }
 
     pub fn get_decoded_text(&self) -> &String {
@@ -117,34 +507,166 @@ impl HuffmanDecoding {
     }
 }
 
+/// Limits on the decoded output, checked after every symbol so an oversized
+/// payload fails before it has been fully expanded in memory.
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub struct DecompressOptions {
+    max_output_len: Option<usize>,
+    max_expansion_ratio: Option<usize>,
+    partial_output: bool,
+}
+
+impl DecompressOptions {
+    /// No limits at all.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// At most `max_output_len` decoded bytes.
+    pub fn with_max_output_len(mut self, max_output_len: usize) -> Self {
+        self.max_output_len = Some(max_output_len);
+        self
+    }
+
+    /// At most `max_expansion_ratio` decoded bytes per byte of encoded payload.
+    pub fn with_max_expansion_ratio(mut self, max_expansion_ratio: usize) -> Self {
+        self.max_expansion_ratio = Some(max_expansion_ratio);
+        self
+    }
+
+    /// Keeps the output decoded before a failure in the error, so that the
+    /// intact start of a corrupted payload can be salvaged with
+    /// `HuffmanError::partial_output`.
+    pub fn with_partial_output(mut self, partial_output: bool) -> Self {
+        self.partial_output = partial_output;
+        self
+    }
+
+    pub fn get_max_output_len(&self) -> Option<usize> {
+        self.max_output_len
+    }
+
+    pub fn get_max_expansion_ratio(&self) -> Option<usize> {
+        self.max_expansion_ratio
+    }
+
+    pub fn get_partial_output(&self) -> bool {
+        self.partial_output
+    }
+
+    /// Largest output allowed for a payload of `input_len` bytes.
+    pub fn output_limit(&self, input_len: usize) -> usize {
+        let by_ratio = match self.max_expansion_ratio {
+            Some(ratio) => input_len.max(1).saturating_mul(ratio),
+            None => usize::MAX,
+        };
+
+        by_ratio.min(self.max_output_len.unwrap_or(usize::MAX))
+    }
+
+    /// Attaches to `e` how far decoding got, and the `decoded` symbols
+    /// themselves if partial output was asked for.
+    pub(crate) fn decode_failure<T: DecodedSymbol>(
+        &self,
+        e: HuffmanError,
+        decoded: &[T],
+        output_len: usize,
+    ) -> HuffmanError {
+        e.with_progress(DecodeProgress {
+            symbols: decoded.len(),
+            output_len,
+            partial_output: match self.partial_output {
+                true => Some(T::to_bytes(decoded)),
+                false => None,
+            },
+        })
+    }
+}
+
+/// A symbol of decoded output: its size in the output and how a run of them
+/// is written out as bytes.
+pub(crate) trait DecodedSymbol: Copy {
+    fn output_len(self) -> usize;
+    fn to_bytes(symbols: &[Self]) -> Vec<u8>;
+}
+
+impl DecodedSymbol for char {
+    fn output_len(self) -> usize {
+        self.len_utf8()
+    }
+
+    fn to_bytes(symbols: &[Self]) -> Vec<u8> {
+        symbols.iter().collect::<String>().into_bytes()
+    }
+}
+
+impl DecodedSymbol for u8 {
+    fn output_len(self) -> usize {
+        1
+    }
+
+    fn to_bytes(symbols: &[Self]) -> Vec<u8> {
+        symbols.to_vec()
+    }
+}
+
 pub trait HuffmanCoding {
-    fn encode(text: &String) -> Result<HuffmanEncoding, HuffmanError>;
+    fn encode(text: &str) -> Result<HuffmanEncoding<char>, HuffmanError>;
     fn decode(
         huffman_tree: HuffmanTree<char>,
-        encoded_vec: Vec<bool>,
+        encoded_bits: PackedBits,
     ) -> Result<HuffmanDecoding, HuffmanError>;
+    fn encode_bytes(bytes: &[u8]) -> Result<HuffmanEncoding<u8>, HuffmanError>;
+    fn decode_bytes(
+        huffman_tree: HuffmanTree<u8>,
+        encoded_bits: PackedBits,
+    ) -> Result<Vec<u8>, HuffmanError>;
 }
 pub struct StandardHuffmanCoding {}
 
-impl HuffmanCoding for StandardHuffmanCoding {
-    fn encode(text: &String) -> Result<HuffmanEncoding, HuffmanError> {
-        let char_vec: Vec<char> = text.chars().collect();
-        let tree = HuffmanTree::from(&char_vec);
+impl StandardHuffmanCoding {
+    fn encode_symbols<T>(symbols: &[T], original: &[u8]) -> Result<HuffmanEncoding<T>, HuffmanError>
+    where
+        T: Eq + Hash + Copy + Ord + ToString,
+        HuffmanNode<T>: ToString,
+    {
+        if let Some(encoding) = SingleSymbolCoding::try_encode(symbols, original) {
+            return Ok(encoding);
+        }
+        let tree = HuffmanTree::from(symbols);
+
+        Self::encode_with_tree(tree, TreeFormat::Shape, symbols, original)
+    }
+
+    fn encode_with_tree<T>(
+        tree: HuffmanTree<T>,
+        tree_format: TreeFormat,
+        symbols: &[T],
+        original: &[u8],
+    ) -> Result<HuffmanEncoding<T>, HuffmanError>
+    where
+        T: Eq + Hash + Copy + Ord + ToString,
+        HuffmanNode<T>: ToString,
+    {
         let encoding_map = tree.get_encoding_map();
-        let mut encoded_vec: Vec<bool> = Vec::new();
+        let mut writer = BitWriter::new();
 
         match encoding_map {
             Ok(encoding_map) => {
-                match char_vec
-                    .into_iter()
-                    .try_for_each(|c| match encoding_map.get(&c) {
-                        Some(encoded_c) => {
-                            encoded_vec.append(&mut encoded_c.clone());
-                            Ok(())
-                        }
-                        None => Err(HuffmanError::not_found_in_tree()),
-                    }) {
-                    Ok(()) => Ok(HuffmanEncoding::new(tree, encoded_vec)),
+                match symbols.iter().try_for_each(|s| match encoding_map.get(s) {
+                    Some(code) => {
+                        writer.write_code(code);
+                        Ok(())
+                    }
+                    None => Err(HuffmanError::not_found_in_tree()),
+                }) {
+                    Ok(()) => Ok(HuffmanEncoding::new(
+                        tree,
+                        tree_format,
+                        writer.finish(),
+                        original.len() as u64,
+                        checksum::crc32(original),
+                    )),
                     Err(e) => Err(e),
                 }
             }
@@ -152,27 +674,151 @@ impl HuffmanCoding for StandardHuffmanCoding {
         }
     }
 
+    /// Decodes like `decode`, failing as soon as the output grows past the
+    /// limits in `options`.
+    pub fn decode_with_options(
+        huffman_tree: &HuffmanTree<char>,
+        encoded_bits: &PackedBits,
+        options: &DecompressOptions,
+    ) -> Result<HuffmanDecoding, HuffmanError> {
+        match Self::decode_symbols(huffman_tree, encoded_bits, options) {
+            Ok(chars) => Ok(HuffmanDecoding::new(chars.into_iter().collect())),
+            Err(e) => Err(e),
+        }
+    }
+
+    /// Decodes like `decode_bytes`, failing as soon as the output grows past
+    /// the limits in `options`.
+    pub fn decode_bytes_with_options(
+        huffman_tree: &HuffmanTree<u8>,
+        encoded_bits: &PackedBits,
+        options: &DecompressOptions,
+    ) -> Result<Vec<u8>, HuffmanError> {
+        Self::decode_symbols(huffman_tree, encoded_bits, options)
+    }
+
+    fn decode_symbols<T>(
+        huffman_tree: &HuffmanTree<T>,
+        encoded_bits: &PackedBits,
+        options: &DecompressOptions,
+    ) -> Result<Vec<T>, HuffmanError>
+    where
+        T: Eq + Hash + Copy + Ord + ToString + DecodedSymbol,
+        HuffmanNode<T>: ToString,
+    {
+        let limit = options.output_limit(encoded_bits.as_bytes().len());
+        let mut output_len: usize = 0;
+        let mut decoded: Vec<T> = Vec::new();
+
+        match DecodingTable::from_tree(huffman_tree) {
+            Ok(table) => match table.decode_each(encoded_bits, |symbol| {
+                if output_len + symbol.output_len() > limit {
+                    return Err(HuffmanError::output_limit_exceeded(limit));
+                }
+                output_len += symbol.output_len();
+                decoded.push(symbol);
+                Ok(())
+            }) {
+                Ok(()) => Ok(decoded),
+                Err(e) => Err(options.decode_failure(e, &decoded, output_len)),
+            },
+            Err(e) => Err(e),
+        }
+    }
+}
+
+impl HuffmanCoding for StandardHuffmanCoding {
+    fn encode(text: &str) -> Result<HuffmanEncoding<char>, HuffmanError> {
+        let char_vec: Vec<char> = text.chars().collect();
+
+        Self::encode_symbols(&char_vec, text.as_bytes())
+    }
+
     fn decode(
         huffman_tree: HuffmanTree<char>,
-        encoded_vec: Vec<bool>,
+        encoded_bits: PackedBits,
     ) -> Result<HuffmanDecoding, HuffmanError> {
-        let mut iter: std::slice::Iter<'_, bool> = encoded_vec.iter();
-        let mut decoded_text = String::new();
+        Self::decode_with_options(&huffman_tree, &encoded_bits, &DecompressOptions::default())
+    }
 
-        while iter.len() > 0 {
-            match huffman_tree.decode_by_path(&mut iter) {
-                Ok(next_char) => decoded_text.push(next_char),
-                Err(e) => return Err(e),
-            }
+    fn encode_bytes(bytes: &[u8]) -> Result<HuffmanEncoding<u8>, HuffmanError> {
+        Self::encode_symbols(bytes, bytes)
+    }
+
+    fn decode_bytes(
+        huffman_tree: HuffmanTree<u8>,
+        encoded_bits: PackedBits,
+    ) -> Result<Vec<u8>, HuffmanError> {
+        Self::decode_bytes_with_options(&huffman_tree, &encoded_bits, &DecompressOptions::default())
+    }
+}
+
+/// Encodes with canonical codes so only `(symbol, code length)` pairs
+/// need to be stored, the same approach DEFLATE and zstd use. Decoding is
+/// identical to `StandardHuffmanCoding` once the tree has been rebuilt.
+/// Codes are limited to `HuffmanTree::DEFAULT_MAX_CODE_LENGTH` bits.
+pub struct CanonicalHuffmanCoding {}
+
+impl CanonicalHuffmanCoding {
+    fn encode_symbols<T>(symbols: &[T], original: &[u8]) -> Result<HuffmanEncoding<T>, HuffmanError>
+    where
+        T: Eq + Hash + Copy + Ord + ToString,
+        HuffmanNode<T>: ToString,
+    {
+        if let Some(encoding) = SingleSymbolCoding::try_encode(symbols, original) {
+            return Ok(encoding);
+        }
+        let max_length = HuffmanTree::<T>::DEFAULT_MAX_CODE_LENGTH;
+
+        match HuffmanTree::from_with_max_length(symbols, max_length) {
+            Ok(tree) => match tree.to_canonical() {
+                Ok(canonical_tree) => StandardHuffmanCoding::encode_with_tree(
+                    canonical_tree,
+                    TreeFormat::CodeLengths,
+                    symbols,
+                    original,
+                ),
+                Err(e) => Err(e),
+            },
+            Err(e) => Err(e),
         }
+    }
+}
+
+impl HuffmanCoding for CanonicalHuffmanCoding {
+    fn encode(text: &str) -> Result<HuffmanEncoding<char>, HuffmanError> {
+        let char_vec: Vec<char> = text.chars().collect();
+
+        Self::encode_symbols(&char_vec, text.as_bytes())
+    }
+
+    fn decode(
+        huffman_tree: HuffmanTree<char>,
+        encoded_bits: PackedBits,
+    ) -> Result<HuffmanDecoding, HuffmanError> {
+        StandardHuffmanCoding::decode(huffman_tree, encoded_bits)
+    }
+
+    fn encode_bytes(bytes: &[u8]) -> Result<HuffmanEncoding<u8>, HuffmanError> {
+        Self::encode_symbols(bytes, bytes)
+    }
 
-        Ok(HuffmanDecoding::new(decoded_text))
+    fn decode_bytes(
+        huffman_tree: HuffmanTree<u8>,
+        encoded_bits: PackedBits,
+    ) -> Result<Vec<u8>, HuffmanError> {
+        StandardHuffmanCoding::decode_bytes(huffman_tree, encoded_bits)
     }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::errors::huffman_error::{ErrorPosition, HuffmanErrorKind};
+
+    fn bits(bools: &[bool]) -> PackedBits {
+        bools.iter().copied().collect()
+    }
 
     #[test]
     fn test_encode_successful() {
@@ -180,14 +826,14 @@ mod tests {
         let result = StandardHuffmanCoding::encode(&text);
 
         assert!(result.is_ok());
-        let result_encoded_vec = result.unwrap().encoded_vec;
+        let result_encoded_vec = result.unwrap().encoded_bits;
         let l = false;
         let r = true;
-        let expected_encoded_vec = vec![
-            l, r, r, l, l, r, l, r, r, r, r, l, r, r, r, r, r, l, l, r, r, r, l, l, r, l, r, r, l,
-            l, l, l, l, r, l, l, r, r, l, r, r, r, l, l, l, r, l, r, r, l, l, l, r, r, r, l, l, l,
-            l, l, r, r, r, r, r, l, r, r, r, l, r, l, r, r, l, r, r, l, r,
-        ];
+        let expected_encoded_vec = bits(&[
+            l, l, l, l, r, r, l, r, r, r, r, l, l, l, l, r, r, l, r, r, r, r, r, r, r, l, r, l, r,
+            r, l, r, l, l, r, l, r, l, r, r, r, r, r, r, r, r, l, l, l, r, r, l, r, l, r, r, l, r,
+            l, l, r, r, r, r, r, l, l, l, r, l, r, l, l, r, l, l, r, l, l,
+        ]);
         assert_eq!(result_encoded_vec, expected_encoded_vec);
     }
 
@@ -198,37 +844,37 @@ mod tests {
         let r = true;
         let encoded_result = StandardHuffmanCoding::encode(&text).unwrap();
         let tree = encoded_result.huffman_tree.clone();
-        let encoded_vec = encoded_result.encoded_vec.clone();
+        let encoded_vec = encoded_result.encoded_bits.clone();
         let decoded_result = StandardHuffmanCoding::decode(tree, encoded_vec);
 
         assert!(decoded_result.is_ok());
         assert_eq!(decoded_result.unwrap().decoded_text, text);
 
         let input = "Welcome to my world".to_string();
-        let encoded_vec = vec![
-            l, r, r, l, l, r, l, r, r, r, r, l, r, r, r, r, r, l, l, r, r, r, l, l, r, l, r, r, l,
-            l, l, l, l, r, l, l, r, r, l, r, r, r, l, l, l, r, l, r, r, l, l, l, r, r, r, l, l, l,
-            l, l, r, r, r, r, r, l, r, r, r, l,
-        ];
+        let encoded_vec = bits(&[
+            l, l, l, l, r, r, l, r, r, r, r, l, l, l, l, r, r, l, r, r, r, r, r, r, r, l, r, l, r,
+            r, l, r, l, l, r, l, r, l, r, r, r, r, r, r, r, r, l, l, l, r, r, l, r, l, r, r, l, r,
+            l, l, r, r, r, r, r, l, l, l, r, l,
+        ]);
         let tree = encoded_result.huffman_tree.clone();
         let decoded_result = StandardHuffmanCoding::decode(tree, encoded_vec);
 
         assert_eq!(decoded_result.unwrap().decoded_text, input);
 
         let input = "Welcome to my world!!!!!!".to_string();
-        let encoded_vec = vec![
-            l, r, r, l, l, r, l, r, r, r, r, l, r, r, r, r, r, l, l, r, r, r, l, l, r, l, r, r, l,
-            l, l, l, l, r, l, l, r, r, l, r, r, r, l, l, l, r, l, r, r, l, l, l, r, r, r, l, l, l,
-            l, l, r, r, r, r, r, l, r, r, r, l, r, l, r, r, l, r, r, l, r, r, l, r, r, l, r, r, l,
-            r,
-        ];
+        let encoded_vec = bits(&[
+            l, l, l, l, r, r, l, r, r, r, r, l, l, l, l, r, r, l, r, r, r, r, r, r, r, l, r, l, r,
+            r, l, r, l, l, r, l, r, l, r, r, r, r, r, r, r, r, l, l, l, r, r, l, r, l, r, r, l, r,
+            l, l, r, r, r, r, r, l, l, l, r, l, r, l, l, r, l, l, r, l, l, r, l, l, r, l, l, r, l,
+            l,
+        ]);
         let tree = encoded_result.huffman_tree.clone();
         let decoded_result = StandardHuffmanCoding::decode(tree, encoded_vec);
 
         assert_eq!(decoded_result.unwrap().decoded_text, input);
 
         let input = String::new();
-        let a_part_encoded_vec: Vec<bool> = vec![];
+        let a_part_encoded_vec = PackedBits::default();
         let tree = encoded_result.huffman_tree.clone();
         let decoded_result = StandardHuffmanCoding::decode(tree, a_part_encoded_vec);
 
@@ -243,45 +889,68 @@ mod tests {
         let encoded_result = StandardHuffmanCoding::encode(&text).unwrap();
 
         let tree = encoded_result.huffman_tree.clone();
-        let undecodable_vec = vec![
-            l, r, r, l, l, r, l, r, r, r, r, l, r, r, r, r, r, l, l, r, r, r, l, l, r, l, r, r, l,
-            l, l, l, l, r, l, l, r, r, l, r, r, r, l, l, l, r, l, r, r, l, l, l, r, r, r, l, l, l,
-            l, l, r, r, r, r, r, l, r, r, r, l, r,
-        ];
+        let undecodable_vec = bits(&[
+            l, l, l, l, r, r, l, r, r, r, r, l, l, l, l, r, r, l, r, r, r, r, r, r, r, l, r, l, r,
+            r, l, r, l, l, r, l, r, l, r, r, r, r, r, r, r, r, l, l, l, r, r, l, r, l, r, r, l, r,
+            l, l, r, r, r, r, r, l, l, l, r, l, r,
+        ]);
         let decoded_result = StandardHuffmanCoding::decode(tree, undecodable_vec);
 
         assert!(decoded_result.is_err());
-        assert_eq!(decoded_result, Err(HuffmanError::decoding_error()));
+        assert_eq!(
+            decoded_result,
+            Err(HuffmanError::decoding_error()
+                .at_bit(70)
+                .with_progress(DecodeProgress {
+                    symbols: 19,
+                    output_len: 19,
+                    partial_output: None,
+                }))
+        );
 
         let tree = encoded_result.huffman_tree.clone();
-        let undecodable_vec = vec![l];
+        let undecodable_vec = bits(&[l]);
         let decoded_result = StandardHuffmanCoding::decode(tree, undecodable_vec);
 
         assert!(decoded_result.is_err());
-        assert_eq!(decoded_result, Err(HuffmanError::decoding_error()));
+        assert_eq!(
+            decoded_result,
+            Err(HuffmanError::decoding_error()
+                .at_bit(0)
+                .with_progress(DecodeProgress::default()))
+        );
     }
 
     #[test]
     fn test_serialize() {
         let text = "Welcome to my world!!!".to_string();
         let encode_result = StandardHuffmanCoding::encode(&text).unwrap();
-        let serialize_result = encode_result.serialize();
-        assert_eq!(
-            serialize_result,
-            "5-38-00001t1r01y1w01e01W01d1c001o1!01 01m1l2{sK\u{2}7\u{b}\u{e}\u{7}n[ "
-        );
+        let serialize_result = encode_result.serialize().unwrap();
+        let mut expect = vec![
+            0x89, b'H', b'U', b'F', 0x01, 0x01, 0, 0, 0, 0, 0, 0, 0, 0x16, 0x92, 0x7b, 0xea, 0x5c,
+            0x01, 0x00, 0x00, 0x00, 0x26,
+        ];
+        expect.extend_from_slice(b"00001W1c01d1r001t1w1 001!1o001y1e01l1m");
+        expect.extend_from_slice(&[0x0d, 0xe1, 0xbf, 0xad, 0x2b, 0xfc, 0x6b, 0x4f, 0x8a, 0x48]);
+
+        assert_eq!(serialize_result, expect);
     }
 
     #[test]
     fn test_deserialize_successful() {
-        let input = "5-38-00001t1r01y1w01e01W01d1c001o1!01 01m1l2{sK\u{2}7\u{b}\u{e}\u{7}n[ ";
-        let wrapped_encoding_result = HuffmanEncoding::deserialize(input.to_string());
+        let mut input = vec![
+            0x89, b'H', b'U', b'F', 0x01, 0x01, 0, 0, 0, 0, 0, 0, 0, 0x16, 0x92, 0x7b, 0xea, 0x5c,
+            0x01, 0x00, 0x00, 0x00, 0x26,
+        ];
+        input.extend_from_slice(b"00001t1r01y1w01e01W01d1c001o1!01 01m1l");
+        input.extend_from_slice(&[0x65, 0xef, 0x9c, 0xb0, 0x4d, 0xc5, 0x8e, 0x0f, 0xba, 0xda]);
+        let wrapped_encoding_result = HuffmanEncoding::<char>::deserialize(&input);
 
         assert!(wrapped_encoding_result.is_ok());
 
         let encoding_result = wrapped_encoding_result.unwrap();
         let tree = encoding_result.get_huffman_tree().clone();
-        let encoded_vec = encoding_result.get_encoded_vec().clone();
+        let encoded_vec = encoding_result.get_encoded_bits().clone();
         let wrapped_decoding_result = StandardHuffmanCoding::decode(tree, encoded_vec);
 
         assert!(wrapped_decoding_result.is_ok());
@@ -290,4 +959,379 @@ mod tests {
             "Welcome to my world!!!"
         );
     }
+
+    #[test]
+    fn test_encode_bytes_successful() {
+        let bytes = "Welcome to my world!!!".as_bytes();
+        let result = StandardHuffmanCoding::encode_bytes(bytes);
+
+        assert!(result.is_ok());
+        let text_result = StandardHuffmanCoding::encode("Welcome to my world!!!").unwrap();
+        assert_eq!(result.unwrap().encoded_bits, text_result.encoded_bits);
+    }
+
+    #[test]
+    fn test_decode_bytes_successful() {
+        let bytes: Vec<u8> = (0..=255).chain([0, 0, 0, 7, 7, 255]).collect();
+        let encoded_result = StandardHuffmanCoding::encode_bytes(&bytes).unwrap();
+        let tree = encoded_result.huffman_tree.clone();
+        let encoded_vec = encoded_result.encoded_bits.clone();
+        let decoded_result = StandardHuffmanCoding::decode_bytes(tree, encoded_vec);
+
+        assert_eq!(decoded_result.unwrap(), bytes);
+    }
+
+    #[test]
+    fn test_serialize_bytes() {
+        let bytes = "Welcome to my world!!!".as_bytes();
+        let encode_result = StandardHuffmanCoding::encode_bytes(bytes).unwrap();
+        let serialize_result = encode_result.serialize().unwrap();
+        let text_result = StandardHuffmanCoding::encode("Welcome to my world!!!").unwrap();
+
+        let text_serialize_result = text_result.serialize().unwrap();
+
+        assert_eq!(serialize_result[5], 0);
+        assert_eq!(text_serialize_result[5], 1);
+        assert_eq!(serialize_result[..5], text_serialize_result[..5]);
+        assert_eq!(serialize_result[6..], text_serialize_result[6..]);
+    }
+
+    #[test]
+    fn test_serialize_failed() {
+        let bytes = "Welcome to my world!!!".as_bytes();
+        let encoding = StandardHuffmanCoding::encode_bytes(bytes).unwrap();
+
+        // Shape-coded payloads do not match the canonical codes.
+        let mislabeled = HuffmanEncoding::new(
+            encoding.get_huffman_tree().clone(),
+            TreeFormat::CodeLengths,
+            encoding.get_encoded_bits().clone(),
+            encoding.get_original_len(),
+            encoding.get_checksum(),
+        );
+        assert_eq!(
+            mislabeled.serialize(),
+            Err(HuffmanError::cannot_serialize_tree())
+        );
+
+        for tree_format in [TreeFormat::CodeLengths, TreeFormat::Run] {
+            let empty = HuffmanEncoding::<char>::new(
+                HuffmanTree::default(),
+                tree_format,
+                PackedBits::default(),
+                0,
+                0,
+            );
+            assert!(empty.serialize().is_err());
+        }
+    }
+
+    #[test]
+    fn test_deserialize_bytes_successful() {
+        let bytes: Vec<u8> = vec![0x89, b'P', b'N', b'G', 0x0d, 0x0a, 0x1a, 0x0a, 0x00, 0x00];
+        let serialized = StandardHuffmanCoding::encode_bytes(&bytes)
+            .unwrap()
+            .serialize()
+            .unwrap();
+        let encoding_result = HuffmanEncoding::<u8>::deserialize(&serialized).unwrap();
+        let tree = encoding_result.get_huffman_tree().clone();
+        let encoded_vec = encoding_result.get_encoded_bits().clone();
+
+        assert_eq!(
+            StandardHuffmanCoding::decode_bytes(tree, encoded_vec).unwrap(),
+            bytes
+        );
+    }
+
+    #[test]
+    fn test_deserialize_bytes_failed() {
+        let header: [u8; 18] = [
+            0x89, b'H', b'U', b'F', 0x01, 0x00, 0, 0, 0, 0, 0, 0, 0, 0x01, 0xe8, 0xb7, 0xbe, 0x43,
+        ];
+        let bodies: [&[u8]; 5] = [
+            &[],
+            &[0x05, 0x00, 0x00],
+            &[0x00, 0x00, 0x00, 0x00, 0x63, b'0', b'1', b'a'],
+            &[0x08, 0x00, 0x00, 0x00, 0x02, b'1', b'a', 0x00],
+            &[0x00, 0x00, 0x00, 0x00, 0x03, b'0', b'2', b'a'],
+        ];
+
+        for body in bodies {
+            let input = [&header[..], body].concat();
+            let result = HuffmanEncoding::<u8>::deserialize(&input);
+
+            assert_eq!(
+                result.err().unwrap().kind,
+                HuffmanErrorKind::DeserializingError
+            );
+        }
+
+        let mut input = header.to_vec();
+        input.extend_from_slice(&[0x07, 0x00, 0x00, 0x00, 0x03, b'0', b'1', b'a', 0x00]);
+        assert!(HuffmanEncoding::<u8>::deserialize(&input).is_ok());
+
+        input[0] = b'H';
+        let result = HuffmanEncoding::<u8>::deserialize(&input);
+        assert_eq!(result.err().unwrap().kind, HuffmanErrorKind::BadMagic);
+
+        input[0] = 0x89;
+        input[4] = 0x02;
+        let result = HuffmanEncoding::<u8>::deserialize(&input);
+        assert_eq!(
+            result.err().unwrap().kind,
+            HuffmanErrorKind::UnsupportedVersion
+        );
+
+        input[4] = 0x01;
+        input[5] = 0x01;
+        let result = HuffmanEncoding::<u8>::deserialize(&input);
+        assert_eq!(
+            result.err().unwrap().kind,
+            HuffmanErrorKind::DeserializingError
+        );
+
+        let result = HuffmanEncoding::<char>::deserialize(&input[..17]);
+        assert_eq!(
+            result.err().unwrap().kind,
+            HuffmanErrorKind::DeserializingError
+        );
+    }
+
+    #[test]
+    fn test_deserialize_declared_lengths() {
+        let encoding = StandardHuffmanCoding::encode_bytes(b"aab").unwrap();
+        let serialized = encoding.serialize().unwrap();
+
+        let mut input = serialized.clone();
+        input[19..23].copy_from_slice(&u32::MAX.to_be_bytes());
+        assert_eq!(
+            HuffmanEncoding::<u8>::deserialize(&input).err(),
+            Some(HuffmanError::invalid_declared_length("tree length", u32::MAX as u64).at_byte(19))
+        );
+
+        let mut input = serialized.clone();
+        input[6..14].copy_from_slice(&u64::MAX.to_be_bytes());
+        assert_eq!(
+            HuffmanEncoding::<u8>::deserialize(&input).err(),
+            Some(HuffmanError::invalid_declared_length("original length", u64::MAX).at_byte(6))
+        );
+
+        let mut input = serialized[..19].to_vec();
+        input.extend_from_slice(&1_000_000u32.to_be_bytes());
+        input.extend(std::iter::repeat_n(b'0', 1_000_000));
+        assert_eq!(
+            HuffmanEncoding::<u8>::deserialize(&input).err(),
+            Some(HuffmanError::cannot_deserialize_tree().at_byte(23))
+        );
+
+        // A tree length ending inside a multi-byte character.
+        let mut input = StandardHuffmanCoding::encode("ééa")
+            .unwrap()
+            .serialize()
+            .unwrap();
+        input[22] -= 1;
+        assert_eq!(
+            HuffmanEncoding::<char>::deserialize(&input).err(),
+            Some(HuffmanError::cannot_deserialize_tree().at_byte(23))
+        );
+    }
+
+    #[test]
+    fn test_decompress_options() {
+        let options = DecompressOptions::new();
+        assert_eq!(options.output_limit(10), usize::MAX);
+
+        let options = options.with_max_expansion_ratio(8);
+        assert_eq!(options.output_limit(10), 80);
+        assert_eq!(options.output_limit(0), 8);
+        assert_eq!(options.with_max_output_len(50).output_limit(10), 50);
+    }
+
+    #[test]
+    fn test_decode_with_options() {
+        let mut bytes = vec![b'a'; 999];
+        bytes.push(b'b');
+        let encoding = StandardHuffmanCoding::encode_bytes(&bytes).unwrap();
+        let decode = |options: DecompressOptions| {
+            StandardHuffmanCoding::decode_bytes_with_options(
+                encoding.get_huffman_tree(),
+                encoding.get_encoded_bits(),
+                &options,
+            )
+        };
+
+        assert_eq!(decode(DecompressOptions::new()).unwrap(), bytes);
+        assert_eq!(
+            decode(DecompressOptions::new().with_max_output_len(1000)).unwrap(),
+            bytes
+        );
+        assert_eq!(
+            decode(DecompressOptions::new().with_max_output_len(999)),
+            Err(HuffmanError::output_limit_exceeded(999)
+                .at_bit(999)
+                .with_progress(DecodeProgress {
+                    symbols: 999,
+                    output_len: 999,
+                    partial_output: None,
+                }))
+        );
+        assert_eq!(
+            decode(DecompressOptions::new().with_max_expansion_ratio(8)).unwrap(),
+            bytes
+        );
+        assert_eq!(
+            decode(DecompressOptions::new().with_max_expansion_ratio(7))
+                .err()
+                .unwrap()
+                .kind,
+            HuffmanErrorKind::LimitExceeded
+        );
+
+        // Limits count bytes of UTF-8, not characters.
+        let encoding = StandardHuffmanCoding::encode("éééab").unwrap();
+        let result = StandardHuffmanCoding::decode_with_options(
+            encoding.get_huffman_tree(),
+            encoding.get_encoded_bits(),
+            &DecompressOptions::new()
+                .with_max_output_len(7)
+                .with_partial_output(true),
+        );
+        assert_eq!(
+            result,
+            Err(HuffmanError::output_limit_exceeded(7)
+                .at_bit(5)
+                .with_progress(DecodeProgress {
+                    symbols: 4,
+                    output_len: 7,
+                    partial_output: Some("éééa".as_bytes().to_vec()),
+                }))
+        );
+    }
+
+    #[test]
+    fn test_decode_partial_output() {
+        let text = "Welcome to my world!!!";
+        let mut serialized = CanonicalHuffmanCoding::encode(text)
+            .unwrap()
+            .serialize()
+            .unwrap();
+        let len = serialized.len();
+        // Cut the payload short in the middle of a code.
+        serialized.truncate(len - 2);
+        serialized[18] = 1;
+        let encoding = HuffmanEncoding::<char>::deserialize(&serialized).unwrap();
+
+        let error = encoding.decode().unwrap_err();
+        assert_eq!(error.kind, HuffmanErrorKind::DecodingError);
+        assert_eq!(error.partial_output(), None);
+
+        let error = encoding
+            .decode_with_options(&DecompressOptions::new().with_partial_output(true))
+            .unwrap_err();
+        let progress = error.progress.clone().unwrap();
+        let partial = String::from_utf8(error.partial_output().unwrap().to_vec()).unwrap();
+        assert!(text.starts_with(&partial));
+        assert_eq!(progress.symbols, partial.chars().count());
+        assert_eq!(progress.output_len, partial.len());
+        assert!(progress.symbols > 0);
+        assert!(
+            matches!(error.position, Some(ErrorPosition::Bit(offset)) if offset < encoding.get_encoded_bits().len())
+        );
+        assert!(error.to_string().contains("symbols"));
+    }
+
+    #[test]
+    fn test_serialize_non_ascii() {
+        let text = "Đây là một câu tiếng Việt có dấu ✓✓✓".to_string();
+        let encode_result = StandardHuffmanCoding::encode(&text).unwrap();
+        let serialized = encode_result.serialize().unwrap();
+        let encoding = HuffmanEncoding::<char>::deserialize(&serialized).unwrap();
+        let tree = encoding.get_huffman_tree().clone();
+        let encoded_vec = encoding.get_encoded_bits().clone();
+        let decoded = StandardHuffmanCoding::decode(tree, encoded_vec).unwrap();
+
+        assert_eq!(decoded.decoded_text, text);
+        assert_eq!(
+            serialized[18] as usize,
+            (8 - encode_result.encoded_bits.len() % 8) % 8
+        );
+    }
+
+    #[test]
+    fn test_verify() {
+        let text = "Welcome to my world!!!";
+        let encoding = StandardHuffmanCoding::encode(text).unwrap();
+
+        assert_eq!(encoding.get_original_len(), 22);
+        assert_eq!(encoding.get_checksum(), 0x927b_ea5c);
+        assert!(encoding.verify(text.as_bytes()).is_ok());
+        assert_eq!(
+            encoding.verify("Welcome to my world!!?".as_bytes()),
+            Err(HuffmanError::checksum_mismatch())
+        );
+        assert_eq!(
+            encoding.verify("Welcome to my world!!".as_bytes()),
+            Err(HuffmanError::checksum_mismatch())
+        );
+    }
+
+    #[test]
+    fn test_canonical_roundtrip() {
+        let text = "Welcome to my world!!!";
+        let encode_result = CanonicalHuffmanCoding::encode(text).unwrap();
+        let serialized = encode_result.serialize().unwrap();
+
+        assert_eq!(serialized[5], 0b11);
+
+        let encoding = HuffmanEncoding::<char>::deserialize(&serialized).unwrap();
+        assert_eq!(encoding.get_tree_format(), TreeFormat::CodeLengths);
+        assert_eq!(
+            encoding.get_huffman_tree().serialize().unwrap(),
+            encode_result.get_huffman_tree().serialize().unwrap()
+        );
+
+        let tree = encoding.get_huffman_tree().clone();
+        let encoded_vec = encoding.get_encoded_bits().clone();
+        let decoded = CanonicalHuffmanCoding::decode(tree, encoded_vec).unwrap();
+        assert_eq!(decoded.decoded_text, text);
+    }
+
+    #[test]
+    fn test_canonical_bytes_roundtrip() {
+        let bytes: Vec<u8> = (0..=255).chain([0, 0, 0, 7, 7, 255]).collect();
+        let serialized = CanonicalHuffmanCoding::encode_bytes(&bytes)
+            .unwrap()
+            .serialize()
+            .unwrap();
+        let shape_serialized = StandardHuffmanCoding::encode_bytes(&bytes)
+            .unwrap()
+            .serialize()
+            .unwrap();
+        let encoding = HuffmanEncoding::<u8>::deserialize(&serialized).unwrap();
+        let tree = encoding.get_huffman_tree().clone();
+        let encoded_vec = encoding.get_encoded_bits().clone();
+
+        assert_eq!(serialized[5], 0b10);
+        assert!(serialized.len() < shape_serialized.len());
+        assert_eq!(
+            CanonicalHuffmanCoding::decode_bytes(tree, encoded_vec).unwrap(),
+            bytes
+        );
+    }
+
+    #[test]
+    fn test_canonical_keeps_code_lengths() {
+        let text = "Đây là một câu tiếng Việt có dấu ✓✓✓";
+        let standard = StandardHuffmanCoding::encode(text).unwrap();
+        let canonical = CanonicalHuffmanCoding::encode(text).unwrap();
+
+        assert_eq!(
+            standard.get_huffman_tree().get_code_lengths().unwrap(),
+            canonical.get_huffman_tree().get_code_lengths().unwrap()
+        );
+        assert_eq!(
+            standard.get_encoded_bits().len(),
+            canonical.get_encoded_bits().len()
+        );
+    }
 }
@@ -0,0 +1,384 @@
+use std::hash::Hash;
+
+use super::huffman::{
+    DecodedSymbol, DecompressOptions, HuffmanCoding, HuffmanDecoding, HuffmanEncoding, TreeFormat,
+};
+use super::single_symbol::SingleSymbolCoding;
+use crate::errors::huffman_error::HuffmanError;
+use crate::models::decoding_table::DecodingTable;
+use crate::models::huffman_tree::{HuffmanNode, HuffmanTree};
+use crate::utils::bit_io::{BitReader, BitWriter, PackedBits};
+use crate::utils::{byte_reader::ByteReader, checksum};
+
+/// Canonical coding with the payload split into interleaved streams, like
+/// the 4-stream mode of huff0: the i-th symbol is coded into stream
+/// `i % streams`. The decoder reads one symbol from every stream per round,
+/// and since the streams do not depend on each other their lookups can
+/// overlap (see `DecodingTable::decode_interleaved`). The payload is laid
+/// out as:
+///
+/// | field        | size                     |                              |
+/// |--------------|--------------------------|------------------------------|
+/// | stream count | `u8`                     | at least 1                   |
+/// | jump table   | `u64` per stream but one | length of the stream in bits |
+/// | streams      | variable                 | one after another, unaligned |
+///
+/// The jump table has no entry for the last stream, which takes the rest.
+pub struct InterleavedHuffmanCoding {}
+
+impl InterleavedHuffmanCoding {
+    pub const DEFAULT_STREAMS: usize = 4;
+    pub const MAX_STREAMS: usize = u8::MAX as usize;
+    const COUNT_BITS: usize = 8;
+    const JUMP_BITS: usize = 64;
+
+    /// Encodes `text` into `streams` streams, clamped to `1..=MAX_STREAMS`.
+    pub fn encode_with_streams(
+        text: &str,
+        streams: usize,
+    ) -> Result<HuffmanEncoding<char>, HuffmanError> {
+        let char_vec: Vec<char> = text.chars().collect();
+
+        Self::encode_symbols(&char_vec, text.as_bytes(), streams)
+    }
+
+    /// Encodes `bytes` into `streams` streams, clamped to `1..=MAX_STREAMS`.
+    pub fn encode_bytes_with_streams(
+        bytes: &[u8],
+        streams: usize,
+    ) -> Result<HuffmanEncoding<u8>, HuffmanError> {
+        Self::encode_symbols(bytes, bytes, streams)
+    }
+
+    /// Decodes like `decode`, failing as soon as the output grows past the
+    /// limits in `options`.
+    pub fn decode_with_options(
+        huffman_tree: &HuffmanTree<char>,
+        encoded_bits: &PackedBits,
+        options: &DecompressOptions,
+    ) -> Result<HuffmanDecoding, HuffmanError> {
+        match Self::decode_symbols(huffman_tree, encoded_bits, options) {
+            Ok(chars) => Ok(HuffmanDecoding::new(chars.into_iter().collect())),
+            Err(e) => Err(e),
+        }
+    }
+
+    /// Decodes like `decode_bytes`, failing as soon as the output grows past
+    /// the limits in `options`.
+    pub fn decode_bytes_with_options(
+        huffman_tree: &HuffmanTree<u8>,
+        encoded_bits: &PackedBits,
+        options: &DecompressOptions,
+    ) -> Result<Vec<u8>, HuffmanError> {
+        Self::decode_symbols(huffman_tree, encoded_bits, options)
+    }
+
+    fn encode_symbols<T>(
+        symbols: &[T],
+        original: &[u8],
+        streams: usize,
+    ) -> Result<HuffmanEncoding<T>, HuffmanError>
+    where
+        T: Eq + Hash + Copy + Ord + ToString,
+        HuffmanNode<T>: ToString,
+    {
+        if let Some(encoding) = SingleSymbolCoding::try_encode(symbols, original) {
+            return Ok(encoding);
+        }
+        let max_length = HuffmanTree::<T>::DEFAULT_MAX_CODE_LENGTH;
+
+        match HuffmanTree::from_with_max_length(symbols, max_length) {
+            Ok(tree) => match tree.to_canonical() {
+                Ok(canonical_tree) => {
+                    match Self::write_streams(&canonical_tree, symbols, streams) {
+                        Ok(encoded_bits) => Ok(HuffmanEncoding::new(
+                            canonical_tree,
+                            TreeFormat::Interleaved,
+                            encoded_bits,
+                            original.len() as u64,
+                            checksum::crc32(original),
+                        )),
+                        Err(e) => Err(e),
+                    }
+                }
+                Err(e) => Err(e),
+            },
+            Err(e) => Err(e),
+        }
+    }
+
+    fn write_streams<T>(
+        huffman_tree: &HuffmanTree<T>,
+        symbols: &[T],
+        streams: usize,
+    ) -> Result<PackedBits, HuffmanError>
+    where
+        T: Eq + Hash + Copy + Ord + ToString,
+        HuffmanNode<T>: ToString,
+    {
+        let streams = streams.clamp(1, Self::MAX_STREAMS);
+        let mut writers: Vec<BitWriter> = (0..streams).map(|_| BitWriter::new()).collect();
+
+        match huffman_tree.get_encoding_map() {
+            Ok(encoding_map) => match symbols.iter().enumerate().try_for_each(|(index, s)| {
+                match encoding_map.get(s) {
+                    Some(code) => {
+                        writers[index % streams].write_code(code);
+                        Ok(())
+                    }
+                    None => Err(HuffmanError::not_found_in_tree()),
+                }
+            }) {
+                Ok(()) => {
+                    let streams: Vec<PackedBits> =
+                        writers.into_iter().map(BitWriter::finish).collect();
+                    let mut payload = BitWriter::new();
+
+                    payload.write_bits(streams.len() as u64, Self::COUNT_BITS);
+                    streams[..streams.len() - 1].iter().for_each(|stream| {
+                        payload.write_bits(stream.len() as u64, Self::JUMP_BITS)
+                    });
+                    streams
+                        .iter()
+                        .for_each(|stream| payload.write_packed(stream));
+                    Ok(payload.finish())
+                }
+                Err(e) => Err(e),
+            },
+            Err(e) => Err(e),
+        }
+    }
+
+    fn decode_symbols<T>(
+        huffman_tree: &HuffmanTree<T>,
+        encoded_bits: &PackedBits,
+        options: &DecompressOptions,
+    ) -> Result<Vec<T>, HuffmanError>
+    where
+        T: Eq + Hash + Copy + Ord + ToString + DecodedSymbol,
+        HuffmanNode<T>: ToString,
+    {
+        let limit = options.output_limit(encoded_bits.as_bytes().len());
+        let mut output_len: usize = 0;
+        let mut decoded: Vec<T> = Vec::new();
+
+        match (
+            DecodingTable::from_tree(huffman_tree),
+            Self::split_streams(encoded_bits),
+        ) {
+            (Ok(table), Ok(mut streams)) => match table.decode_interleaved(&mut streams, |round| {
+                for &symbol in round {
+                    if output_len + symbol.output_len() > limit {
+                        return Err(HuffmanError::output_limit_exceeded(limit));
+                    }
+                    output_len += symbol.output_len();
+                    decoded.push(symbol);
+                }
+                Ok(())
+            }) {
+                Ok(()) => Ok(decoded),
+                Err(e) => Err(options.decode_failure(e, &decoded, output_len)),
+            },
+            (Err(e), _) | (_, Err(e)) => Err(e),
+        }
+    }
+
+    /// Reads the stream count and the jump table, returning a reader for
+    /// every stream. Positions stay counted from the start of the payload.
+    fn split_streams(encoded_bits: &PackedBits) -> Result<Vec<BitReader<'_>>, HuffmanError> {
+        let bytes = encoded_bits.as_bytes();
+        let mut reader = ByteReader::new(bytes);
+        let count = match reader.read_u8() {
+            Some(count) if count > 0 => count as usize,
+            _ => return Err(HuffmanError::decoding_error().at_bit(0)),
+        };
+        let mut start = Self::COUNT_BITS + (count - 1) * Self::JUMP_BITS;
+        let mut streams: Vec<BitReader> = Vec::with_capacity(count);
+
+        if start > encoded_bits.len() {
+            return Err(HuffmanError::decoding_error().at_bit(encoded_bits.len()));
+        }
+        for _ in 1..count {
+            let entry = reader.position() * 8;
+
+            match reader.read_u64() {
+                Some(len) if len <= (encoded_bits.len() - start) as u64 => {
+                    streams.push(BitReader::with_range(bytes, start, len as usize));
+                    start += len as usize;
+                }
+                Some(len) => {
+                    return Err(
+                        HuffmanError::invalid_declared_length("stream length", len).at_bit(entry)
+                    )
+                }
+                None => return Err(HuffmanError::decoding_error().at_bit(entry)),
+            }
+        }
+        streams.push(BitReader::with_range(
+            bytes,
+            start,
+            encoded_bits.len() - start,
+        ));
+
+        Ok(streams)
+    }
+}
+
+impl HuffmanCoding for InterleavedHuffmanCoding {
+    fn encode(text: &str) -> Result<HuffmanEncoding<char>, HuffmanError> {
+        Self::encode_with_streams(text, Self::DEFAULT_STREAMS)
+    }
+
+    fn decode(
+        huffman_tree: HuffmanTree<char>,
+        encoded_bits: PackedBits,
+    ) -> Result<HuffmanDecoding, HuffmanError> {
+        Self::decode_with_options(&huffman_tree, &encoded_bits, &DecompressOptions::default())
+    }
+
+    fn encode_bytes(bytes: &[u8]) -> Result<HuffmanEncoding<u8>, HuffmanError> {
+        Self::encode_bytes_with_streams(bytes, Self::DEFAULT_STREAMS)
+    }
+
+    fn decode_bytes(
+        huffman_tree: HuffmanTree<u8>,
+        encoded_bits: PackedBits,
+    ) -> Result<Vec<u8>, HuffmanError> {
+        Self::decode_bytes_with_options(&huffman_tree, &encoded_bits, &DecompressOptions::default())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::coding::huffman::CanonicalHuffmanCoding;
+    use crate::errors::huffman_error::HuffmanErrorKind;
+
+    #[test]
+    fn test_roundtrip() {
+        let text = "Welcome to my world!!! Chào mừng bạn đến với thế giới của tôi";
+
+        for streams in [1, 2, 3, 4, 7, 64, 255] {
+            let encoding = InterleavedHuffmanCoding::encode_with_streams(text, streams).unwrap();
+            let serialized = encoding.serialize().unwrap();
+            let deserialized = HuffmanEncoding::<char>::deserialize(&serialized).unwrap();
+
+            assert_eq!(deserialized.get_tree_format(), TreeFormat::Interleaved);
+            assert_eq!(deserialized.decode().unwrap(), text);
+        }
+
+        let bytes: Vec<u8> = (0..5000u32).map(|i| (i * i % 251) as u8).collect();
+        let encoding = InterleavedHuffmanCoding::encode_bytes(&bytes).unwrap();
+        let deserialized =
+            HuffmanEncoding::<u8>::deserialize(&encoding.serialize().unwrap()).unwrap();
+
+        assert_eq!(deserialized.decode().unwrap(), bytes);
+        assert_eq!(
+            InterleavedHuffmanCoding::decode_bytes(
+                encoding.get_huffman_tree().clone(),
+                encoding.get_encoded_bits().clone()
+            )
+            .unwrap(),
+            bytes
+        );
+    }
+
+    #[test]
+    fn test_layout() {
+        let bytes = b"aabbbbccd".repeat(3);
+        let canonical = CanonicalHuffmanCoding::encode_bytes(&bytes).unwrap();
+        let encoding = InterleavedHuffmanCoding::encode_bytes_with_streams(&bytes, 4).unwrap();
+        let payload = encoding.get_encoded_bits().as_bytes();
+        let jump = |index: usize| {
+            u64::from_be_bytes(payload[1 + index * 8..9 + index * 8].try_into().unwrap())
+        };
+
+        let encoding_map = canonical.get_huffman_tree().get_encoding_map().unwrap();
+        let stream_len = |stream: usize| -> usize {
+            bytes
+                .iter()
+                .skip(stream)
+                .step_by(4)
+                .map(|b| encoding_map[b].len)
+                .sum()
+        };
+
+        // Same codes as the canonical coding, with symbols dealt round-robin.
+        assert_eq!(
+            encoding
+                .get_huffman_tree()
+                .serialize_code_lengths()
+                .unwrap(),
+            canonical
+                .get_huffman_tree()
+                .serialize_code_lengths()
+                .unwrap()
+        );
+        assert_eq!(payload[0], 4);
+        assert_eq!(
+            encoding.get_encoded_bits().len(),
+            8 + 3 * 64 + canonical.get_encoded_bits().len()
+        );
+        for stream in 0..3 {
+            assert_eq!(jump(stream), stream_len(stream) as u64);
+        }
+    }
+
+    #[test]
+    fn test_fewer_symbols_than_streams() {
+        let encoding = InterleavedHuffmanCoding::encode_bytes_with_streams(b"ab", 8).unwrap();
+
+        assert_eq!(encoding.get_encoded_bits().as_bytes()[0], 8);
+        assert_eq!(encoding.decode().unwrap(), b"ab");
+        assert_eq!(
+            InterleavedHuffmanCoding::encode_bytes_with_streams(b"ab", 0)
+                .unwrap()
+                .get_encoded_bits()
+                .as_bytes()[0],
+            1
+        );
+    }
+
+    #[test]
+    fn test_decode_failed() {
+        let encoding =
+            InterleavedHuffmanCoding::encode_bytes_with_streams(b"aabbbbccd", 2).unwrap();
+        let tree = encoding.get_huffman_tree().clone();
+        let payload = encoding.get_encoded_bits();
+        let decode = |bytes: Vec<u8>, len: usize| {
+            InterleavedHuffmanCoding::decode_bytes(
+                tree.clone(),
+                PackedBits::new(bytes, len).unwrap(),
+            )
+        };
+
+        let mut corrupted = payload.as_bytes().to_vec();
+        corrupted[0] = 0;
+        assert_eq!(
+            decode(corrupted, payload.len()),
+            Err(HuffmanError::decoding_error().at_bit(0))
+        );
+
+        assert_eq!(
+            decode(payload.as_bytes()[..5].to_vec(), 40),
+            Err(HuffmanError::decoding_error().at_bit(40))
+        );
+
+        let mut corrupted = payload.as_bytes().to_vec();
+        corrupted[1..9].copy_from_slice(&u64::MAX.to_be_bytes());
+        assert_eq!(
+            decode(corrupted, payload.len()).unwrap_err().kind,
+            HuffmanErrorKind::InvalidDeclaredLength
+        );
+
+        // Moving a bit from the first stream to the second leaves the first
+        // one ending early while the second still has symbols.
+        let mut corrupted = payload.as_bytes().to_vec();
+        let first_len = u64::from_be_bytes(corrupted[1..9].try_into().unwrap());
+        corrupted[1..9].copy_from_slice(&(first_len - 1).to_be_bytes());
+        assert_eq!(
+            decode(corrupted, payload.len()).unwrap_err().kind,
+            HuffmanErrorKind::DecodingError
+        );
+    }
+}
@@ -1 +1,8 @@
+pub mod adaptive_huffman;
+pub mod dictionary;
 pub mod huffman;
+pub mod interleaved;
+pub mod parallel;
+pub mod single_symbol;
+pub mod stored;
+pub mod stream;
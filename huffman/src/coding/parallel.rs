@@ -0,0 +1,343 @@
+use std::{
+    num::NonZeroUsize,
+    sync::atomic::{AtomicUsize, Ordering},
+    thread,
+};
+
+use super::huffman::{DecompressOptions, HuffmanEncoding};
+use super::stream::{self, OutputBudget, DEFAULT_BLOCK_SIZE, STREAM_MAGIC};
+use crate::errors::huffman_error::{ErrorPosition, HuffmanError};
+use crate::utils::byte_reader::ByteReader;
+
+/// Frame containers with their byte offset in the stream.
+type Frames<'a> = Vec<(usize, &'a [u8])>;
+
+/// How `ParallelCoding` splits the work.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ParallelOptions {
+    threads: usize,
+    block_size: usize,
+    decompress_options: DecompressOptions,
+}
+
+impl Default for ParallelOptions {
+    /// One thread per available core, the block size of `HuffmanEncoder`
+    /// and no output limits.
+    fn default() -> Self {
+        Self {
+            threads: thread::available_parallelism().map_or(1, NonZeroUsize::get),
+            block_size: DEFAULT_BLOCK_SIZE,
+            decompress_options: DecompressOptions::default(),
+        }
+    }
+}
+
+impl ParallelOptions {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Number of worker threads, at least 1.
+    pub fn with_threads(mut self, threads: usize) -> Self {
+        self.threads = threads.max(1);
+        self
+    }
+
+    /// Size of the independent blocks, clamped like
+    /// `HuffmanEncoder::with_block_size`. Ignored when decompressing, where
+    /// the block size recorded in the stream is used.
+    pub fn with_block_size(mut self, block_size: usize) -> Self {
+        self.block_size = stream::clamp_block_size(block_size);
+        self
+    }
+
+    /// Limits on the whole decompressed output, as for `HuffmanDecoder`.
+    pub fn with_decompress_options(mut self, decompress_options: DecompressOptions) -> Self {
+        self.decompress_options = decompress_options;
+        self
+    }
+
+    pub fn get_threads(&self) -> usize {
+        self.threads
+    }
+
+    pub fn get_block_size(&self) -> usize {
+        self.block_size
+    }
+
+    pub fn get_decompress_options(&self) -> DecompressOptions {
+        self.decompress_options
+    }
+}
+
+/// Block-parallel version of `HuffmanEncoder` and `HuffmanDecoder` for data
+/// held in memory. Every block is counted, gets its own tree and is encoded
+/// independently, so blocks are spread over worker threads and the stream is
+/// byte for byte the one `HuffmanEncoder` writes, whatever the thread count.
+pub struct ParallelCoding {}
+
+impl ParallelCoding {
+    pub fn compress(bytes: &[u8], options: &ParallelOptions) -> Result<Vec<u8>, HuffmanError> {
+        let blocks: Vec<&[u8]> = bytes.chunks(options.block_size).collect();
+        let frames = Self::map_blocks(&blocks, options.threads, |block| {
+            stream::encode_frame(block)
+        });
+        let mut result: Vec<u8> = Vec::new();
+
+        result.extend_from_slice(&stream::stream_header(options.block_size));
+        for frame in frames {
+            match frame {
+                Ok(frame) => result.extend_from_slice(&frame),
+                Err(e) => return Err(e),
+            }
+        }
+        result.extend_from_slice(&stream::STREAM_END);
+
+        Ok(result)
+    }
+
+    /// Every frame is parsed and checked against the output limits in stream
+    /// order before any block is decoded.
+    pub fn decompress(input: &[u8], options: &ParallelOptions) -> Result<Vec<u8>, HuffmanError> {
+        let (block_size, frames) = Self::split_frames(input)?;
+        let mut budget = OutputBudget::new(options.decompress_options);
+        // Each frame with its offset and the offset of its payload.
+        let mut encodings: Vec<(usize, usize, HuffmanEncoding<u8>)> =
+            Vec::with_capacity(frames.len());
+
+        for (offset, frame) in frames {
+            let encoding = match HuffmanEncoding::<u8>::deserialize(frame) {
+                Ok(encoding) => encoding,
+                Err(e) => return Err(Self::at_frame(e, offset, offset)),
+            };
+            // The payload ends the container.
+            let payload_offset =
+                offset + frame.len() - encoding.get_encoded_bits().as_bytes().len();
+            match budget.spend(offset + frame.len(), encoding.get_original_len()) {
+                Ok(()) => encodings.push((offset, payload_offset, encoding)),
+                Err(e) => return Err(e.at_byte(offset)),
+            }
+        }
+
+        let blocks = Self::map_blocks(
+            &encodings,
+            options.threads,
+            |(offset, payload_offset, encoding)| match stream::decode_frame(encoding, block_size) {
+                Ok(block) => Ok(block),
+                Err(e) => Err(Self::at_frame(e, *offset, *payload_offset)),
+            },
+        );
+        let mut result: Vec<u8> = Vec::new();
+
+        for block in blocks {
+            match block {
+                Ok(block) => result.extend_from_slice(&block),
+                Err(e) => return Err(e),
+            }
+        }
+
+        Ok(result)
+    }
+
+    /// Moves the position of an error in the frame at `offset` to a byte
+    /// offset in the whole stream. Byte positions count from the start of the
+    /// container and bit positions from its payload at `payload_offset`; an
+    /// error without a position points at the container.
+    fn at_frame(e: HuffmanError, offset: usize, payload_offset: usize) -> HuffmanError {
+        match e.position {
+            Some(ErrorPosition::Byte(byte)) => e.at_byte(offset + byte),
+            Some(ErrorPosition::Bit(bit)) => e.at_byte(payload_offset + bit / 8),
+            None => e.at_byte(offset),
+        }
+    }
+
+    /// Reads the stream header and returns the block size and every frame
+    /// with its offset in `input`.
+    fn split_frames(input: &[u8]) -> Result<(usize, Frames<'_>), HuffmanError> {
+        let error = HuffmanError::cannot_deserialize_tree();
+        let mut reader = ByteReader::new(input);
+        let mut frames: Frames = Vec::new();
+
+        match reader.read_bytes(STREAM_MAGIC.len()) {
+            Some(magic) if magic == STREAM_MAGIC => {}
+            _ => return Err(HuffmanError::bad_magic().at_byte(0)),
+        }
+        let block_size = match reader.read_u32() {
+            Some(0) | None => return Err(error.at_byte(STREAM_MAGIC.len())),
+            Some(block_size) => block_size as usize,
+        };
+
+        loop {
+            let offset = reader.position();
+
+            match reader.read_u32() {
+                Some(0) => return Ok((block_size, frames)),
+                Some(len) if len as usize <= stream::max_frame_len(block_size) => {
+                    match reader.read_bytes(len as usize) {
+                        Some(frame) => frames.push((offset + 4, frame)),
+                        None => return Err(error.at_byte(offset)),
+                    }
+                }
+                _ => return Err(error.at_byte(offset)),
+            }
+        }
+    }
+
+    /// Applies `f` to every block on up to `threads` threads and returns the
+    /// results in block order. Workers take the next unclaimed block, so a
+    /// slow block does not hold up the others.
+    fn map_blocks<I, O, F>(blocks: &[I], threads: usize, f: F) -> Vec<O>
+    where
+        I: Sync,
+        O: Send,
+        F: Fn(&I) -> O + Sync,
+    {
+        let threads = threads.clamp(1, blocks.len().max(1));
+        if threads == 1 {
+            return blocks.iter().map(f).collect();
+        }
+
+        let next = AtomicUsize::new(0);
+        let mut results: Vec<(usize, O)> = thread::scope(|scope| {
+            let workers: Vec<_> = (0..threads)
+                .map(|_| {
+                    scope.spawn(|| {
+                        let mut done: Vec<(usize, O)> = Vec::new();
+                        loop {
+                            let index = next.fetch_add(1, Ordering::Relaxed);
+                            match blocks.get(index) {
+                                Some(block) => done.push((index, f(block))),
+                                None => return done,
+                            }
+                        }
+                    })
+                })
+                .collect();
+
+            workers
+                .into_iter()
+                .flat_map(|worker| match worker.join() {
+                    Ok(done) => done,
+                    Err(panic) => std::panic::resume_unwind(panic),
+                })
+                .collect()
+        });
+
+        results.sort_by_key(|&(index, _)| index);
+        results.into_iter().map(|(_, result)| result).collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::coding::stream::{HuffmanDecoder, HuffmanEncoder};
+    use crate::errors::huffman_error::HuffmanErrorKind;
+    use std::io::{Read, Write};
+
+    fn sample(len: usize) -> Vec<u8> {
+        (0..len as u32)
+            .map(|i| (i.wrapping_mul(2654435761) >> 24) as u8 & 0x3f)
+            .collect()
+    }
+
+    #[test]
+    fn test_same_output_as_stream() {
+        let bytes = sample(100_000);
+        let mut encoder = HuffmanEncoder::with_block_size(Vec::new(), 4096);
+        encoder.write_all(&bytes).unwrap();
+        let expect = encoder.finish().unwrap();
+
+        for threads in [1, 2, 3, 8, 64] {
+            let options = ParallelOptions::new()
+                .with_threads(threads)
+                .with_block_size(4096);
+            let compressed = ParallelCoding::compress(&bytes, &options).unwrap();
+
+            assert_eq!(compressed, expect);
+            assert_eq!(
+                ParallelCoding::decompress(&compressed, &options).unwrap(),
+                bytes
+            );
+        }
+
+        let mut decoded = Vec::new();
+        HuffmanDecoder::new(&expect[..])
+            .read_to_end(&mut decoded)
+            .unwrap();
+        assert_eq!(decoded, bytes);
+
+        // Both encoders clamp block sizes to the same bound.
+        for block_size in [0, usize::MAX] {
+            assert_eq!(
+                ParallelOptions::new()
+                    .with_block_size(block_size)
+                    .get_block_size(),
+                HuffmanEncoder::with_block_size(Vec::new(), block_size).get_block_size()
+            );
+        }
+    }
+
+    #[test]
+    fn test_empty_input() {
+        let options = ParallelOptions::new().with_threads(4);
+        let compressed = ParallelCoding::compress(b"", &options).unwrap();
+
+        assert_eq!(compressed.len(), 12);
+        assert!(ParallelCoding::decompress(&compressed, &options)
+            .unwrap()
+            .is_empty());
+    }
+
+    #[test]
+    fn test_output_limit() {
+        let bytes = sample(10_000);
+        let options = ParallelOptions::new().with_threads(4).with_block_size(1000);
+        let compressed = ParallelCoding::compress(&bytes, &options).unwrap();
+
+        let limited = options
+            .with_decompress_options(DecompressOptions::new().with_max_output_len(bytes.len()));
+        assert_eq!(
+            ParallelCoding::decompress(&compressed, &limited).unwrap(),
+            bytes
+        );
+
+        let limited = options
+            .with_decompress_options(DecompressOptions::new().with_max_output_len(bytes.len() - 1));
+        let error = ParallelCoding::decompress(&compressed, &limited).unwrap_err();
+        assert_eq!(error.kind, HuffmanErrorKind::LimitExceeded);
+
+        let limited =
+            options.with_decompress_options(DecompressOptions::new().with_max_expansion_ratio(1));
+        assert!(ParallelCoding::decompress(&compressed, &limited).is_err());
+    }
+
+    #[test]
+    fn test_decompress_failed() {
+        let options = ParallelOptions::new().with_threads(4).with_block_size(1000);
+        let compressed = ParallelCoding::compress(&sample(10_000), &options).unwrap();
+
+        assert_eq!(
+            ParallelCoding::decompress(&compressed[1..], &options).err(),
+            Some(HuffmanError::bad_magic().at_byte(0))
+        );
+        assert!(ParallelCoding::decompress(&compressed[..compressed.len() - 1], &options).is_err());
+
+        // A corrupted checksum in the second frame.
+        let mut corrupted = compressed.clone();
+        let first_len = u32::from_be_bytes(corrupted[8..12].try_into().unwrap()) as usize;
+        let second = 12 + first_len + 4;
+        corrupted[second + 14] ^= 0xff;
+        let error = ParallelCoding::decompress(&corrupted, &options).unwrap_err();
+        assert_eq!(error.kind, HuffmanErrorKind::ChecksumMismatch);
+        assert_eq!(error.position, Some(ErrorPosition::Byte(second)));
+
+        // Positions inside a frame are moved to the whole stream.
+        let mut corrupted = compressed.clone();
+        corrupted[second + 4] = 0xff;
+        assert_eq!(
+            ParallelCoding::decompress(&corrupted, &options).err(),
+            Some(HuffmanError::unsupported_version(0xff).at_byte(second + 4))
+        );
+    }
+}
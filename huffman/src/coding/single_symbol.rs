@@ -0,0 +1,353 @@
+use std::hash::Hash;
+
+use super::huffman::{
+    DecodedSymbol, DecompressOptions, HuffmanCoding, HuffmanDecoding, HuffmanEncoding, TreeFormat,
+};
+use crate::errors::huffman_error::HuffmanError;
+use crate::models::huffman_tree::{HuffmanNode, HuffmanTree};
+use crate::utils::bit_io::{BitWriter, PackedBits};
+use crate::utils::checksum;
+
+/// Covers the inputs a Huffman tree cannot describe on its own: empty input
+/// becomes an empty stored container, and input made of one repeated symbol
+/// stores just that symbol (as the tree) and a `u64` run count (as the payload).
+pub struct SingleSymbolCoding {}
+
+impl SingleSymbolCoding {
+    const COUNT_BITS: usize = 64;
+
+    /// Returns the encoding for `symbols` if it is one of the edge cases above.
+    pub fn try_encode<T>(symbols: &[T], original: &[u8]) -> Option<HuffmanEncoding<T>>
+    where
+        T: Eq + Hash + Copy + Ord + ToString,
+        HuffmanNode<T>: ToString,
+    {
+        match symbols.first() {
+            None => Some(HuffmanEncoding::new(
+                HuffmanTree::default(),
+                TreeFormat::Stored,
+                PackedBits::default(),
+                0,
+                checksum::crc32(original),
+            )),
+            Some(&first) if symbols.iter().all(|&symbol| symbol == first) => {
+                match HuffmanTree::from_code_lengths(&[(first, 1)]) {
+                    Ok(tree) => {
+                        let mut writer = BitWriter::new();
+                        writer.write_bits(symbols.len() as u64, Self::COUNT_BITS);
+
+                        Some(HuffmanEncoding::new(
+                            tree,
+                            TreeFormat::Run,
+                            writer.finish(),
+                            original.len() as u64,
+                            checksum::crc32(original),
+                        ))
+                    }
+                    Err(_) => None,
+                }
+            }
+            Some(_) => None,
+        }
+    }
+
+    /// The run count stored in a `TreeFormat::Run` payload, if the payload
+    /// is exactly one count long.
+    pub(crate) fn run_count(encoded_bits: &PackedBits) -> Option<u64> {
+        if encoded_bits.len() != Self::COUNT_BITS {
+            return None;
+        }
+
+        match encoded_bits.as_bytes().try_into() {
+            Ok(bytes) => Some(u64::from_be_bytes(bytes)),
+            Err(_) => None,
+        }
+    }
+
+    /// Decodes like `decode`, failing before anything is allocated if the
+    /// run does not add up to `original_len` bytes or is longer than the
+    /// limits in `options` allow.
+    pub fn decode_with_options(
+        huffman_tree: &HuffmanTree<char>,
+        encoded_bits: &PackedBits,
+        original_len: u64,
+        options: &DecompressOptions,
+    ) -> Result<HuffmanDecoding, HuffmanError> {
+        let (symbol, count, output_len) =
+            Self::decode_run(huffman_tree, encoded_bits, original_len, options)?;
+        let mut decoded = String::new();
+
+        match decoded.try_reserve_exact(output_len) {
+            Ok(()) => {
+                decoded.extend(std::iter::repeat_n(symbol, count));
+                Ok(HuffmanDecoding::new(decoded))
+            }
+            Err(_) => Err(HuffmanError::decoding_error()),
+        }
+    }
+
+    /// Decodes like `decode_bytes`, failing before anything is allocated if
+    /// the run does not add up to `original_len` bytes or is longer than the
+    /// limits in `options` allow.
+    pub fn decode_bytes_with_options(
+        huffman_tree: &HuffmanTree<u8>,
+        encoded_bits: &PackedBits,
+        original_len: u64,
+        options: &DecompressOptions,
+    ) -> Result<Vec<u8>, HuffmanError> {
+        let (symbol, count, _) =
+            Self::decode_run(huffman_tree, encoded_bits, original_len, options)?;
+        let mut decoded = Vec::new();
+
+        match decoded.try_reserve_exact(count) {
+            Ok(()) => {
+                decoded.resize(count, symbol);
+                Ok(decoded)
+            }
+            Err(_) => Err(HuffmanError::decoding_error()),
+        }
+    }
+
+    /// Returns the run's symbol, its count and its decoded length in bytes.
+    fn decode_run<T>(
+        huffman_tree: &HuffmanTree<T>,
+        encoded_bits: &PackedBits,
+        original_len: u64,
+        options: &DecompressOptions,
+    ) -> Result<(T, usize, usize), HuffmanError>
+    where
+        T: Eq + Hash + Copy + Ord + ToString + DecodedSymbol,
+        HuffmanNode<T>: ToString,
+    {
+        let symbol = match huffman_tree.get_code_lengths() {
+            Ok(lengths) if lengths.len() == 1 => lengths[0].0,
+            _ => return Err(HuffmanError::invalid_huffman_tree()),
+        };
+        let count = match Self::run_count(encoded_bits) {
+            Some(count) => count,
+            None => return Err(HuffmanError::decoding_error()),
+        };
+        if count.checked_mul(symbol.output_len() as u64) != Some(original_len) {
+            return Err(HuffmanError::decoding_error());
+        }
+
+        let limit = options.output_limit(encoded_bits.as_bytes().len());
+        match (usize::try_from(count), usize::try_from(original_len)) {
+            (Ok(count), Ok(output_len)) if output_len <= limit => Ok((symbol, count, output_len)),
+            _ => Err(HuffmanError::output_limit_exceeded(limit)),
+        }
+    }
+
+    /// The decoded length a bare run payload stands for, used where there is
+    /// no container header recording it.
+    fn run_original_len<T>(huffman_tree: &HuffmanTree<T>, encoded_bits: &PackedBits) -> u64
+    where
+        T: Eq + Hash + Copy + Ord + ToString + DecodedSymbol,
+        HuffmanNode<T>: ToString,
+    {
+        match (
+            huffman_tree.get_code_lengths(),
+            Self::run_count(encoded_bits),
+        ) {
+            (Ok(lengths), Some(count)) if lengths.len() == 1 => {
+                count.saturating_mul(lengths[0].0.output_len() as u64)
+            }
+            _ => 0,
+        }
+    }
+
+    fn encode_symbols<T>(symbols: &[T], original: &[u8]) -> Result<HuffmanEncoding<T>, HuffmanError>
+    where
+        T: Eq + Hash + Copy + Ord + ToString,
+        HuffmanNode<T>: ToString,
+    {
+        match Self::try_encode(symbols, original) {
+            Some(encoding) => Ok(encoding),
+            None => Err(HuffmanError::encoding_error()),
+        }
+    }
+}
+
+impl HuffmanCoding for SingleSymbolCoding {
+    fn encode(text: &str) -> Result<HuffmanEncoding<char>, HuffmanError> {
+        let char_vec: Vec<char> = text.chars().collect();
+
+        Self::encode_symbols(&char_vec, text.as_bytes())
+    }
+
+    fn decode(
+        huffman_tree: HuffmanTree<char>,
+        encoded_bits: PackedBits,
+    ) -> Result<HuffmanDecoding, HuffmanError> {
+        let original_len = Self::run_original_len(&huffman_tree, &encoded_bits);
+
+        Self::decode_with_options(
+            &huffman_tree,
+            &encoded_bits,
+            original_len,
+            &DecompressOptions::default(),
+        )
+    }
+
+    fn encode_bytes(bytes: &[u8]) -> Result<HuffmanEncoding<u8>, HuffmanError> {
+        Self::encode_symbols(bytes, bytes)
+    }
+
+    fn decode_bytes(
+        huffman_tree: HuffmanTree<u8>,
+        encoded_bits: PackedBits,
+    ) -> Result<Vec<u8>, HuffmanError> {
+        let original_len = Self::run_original_len(&huffman_tree, &encoded_bits);
+
+        Self::decode_bytes_with_options(
+            &huffman_tree,
+            &encoded_bits,
+            original_len,
+            &DecompressOptions::default(),
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::errors::huffman_error::HuffmanErrorKind;
+
+    #[test]
+    fn test_empty_input() {
+        let serialized = SingleSymbolCoding::encode("").unwrap().serialize().unwrap();
+
+        assert_eq!(
+            serialized,
+            [
+                0x89, b'H', b'U', b'F', 0x01, 0x09, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0,
+                0
+            ]
+        );
+        assert_eq!(
+            HuffmanEncoding::<char>::deserialize(&serialized)
+                .unwrap()
+                .decode()
+                .unwrap(),
+            ""
+        );
+        assert!(SingleSymbolCoding::encode_bytes(b"").is_ok());
+    }
+
+    #[test]
+    fn test_run_roundtrip() {
+        let text = "ééééé";
+        let serialized = SingleSymbolCoding::encode(text)
+            .unwrap()
+            .serialize()
+            .unwrap();
+        let mut expect = vec![
+            0x89, b'H', b'U', b'F', 0x01, 0x11, 0, 0, 0, 0, 0, 0, 0, 0x0a,
+        ];
+        expect.extend_from_slice(&checksum::crc32(text.as_bytes()).to_be_bytes());
+        expect.extend_from_slice(&[0x00, 0x00, 0x00, 0x00, 0x02]);
+        expect.extend_from_slice("é".as_bytes());
+        expect.extend_from_slice(&5u64.to_be_bytes());
+        assert_eq!(serialized, expect);
+
+        let encoding = HuffmanEncoding::<char>::deserialize(&serialized).unwrap();
+        assert_eq!(encoding.get_tree_format(), TreeFormat::Run);
+        assert_eq!(encoding.decode().unwrap(), text);
+
+        let bytes = vec![0u8; 100_000];
+        let serialized = SingleSymbolCoding::encode_bytes(&bytes)
+            .unwrap()
+            .serialize()
+            .unwrap();
+        let encoding = HuffmanEncoding::<u8>::deserialize(&serialized).unwrap();
+        assert_eq!(serialized.len(), HuffmanEncoding::<u8>::HEADER_LEN + 1 + 8);
+        assert_eq!(encoding.decode().unwrap(), bytes);
+    }
+
+    #[test]
+    fn test_decode_failed() {
+        assert_eq!(
+            SingleSymbolCoding::encode("ab").err(),
+            Some(HuffmanError::encoding_error())
+        );
+
+        let encoding = SingleSymbolCoding::encode_bytes(b"aaaa").unwrap();
+        let tree = encoding.get_huffman_tree().clone();
+        let options = DecompressOptions::new().with_max_output_len(3);
+        assert_eq!(
+            SingleSymbolCoding::decode_bytes_with_options(
+                &tree,
+                encoding.get_encoded_bits(),
+                4,
+                &options
+            ),
+            Err(HuffmanError::output_limit_exceeded(3))
+        );
+        assert_eq!(
+            SingleSymbolCoding::decode_bytes(tree.clone(), PackedBits::from_bytes(vec![0; 4])),
+            Err(HuffmanError::decoding_error())
+        );
+
+        // A run of 2^64 - 1 symbols can never fit in memory.
+        let mut serialized = encoding.serialize().unwrap();
+        let len = serialized.len();
+        serialized[6..14].copy_from_slice(&u64::MAX.to_be_bytes());
+        serialized[len - 8..].copy_from_slice(&u64::MAX.to_be_bytes());
+        let encoding = HuffmanEncoding::<u8>::deserialize(&serialized).unwrap();
+        assert_eq!(
+            encoding
+                .decode_with_options(&DecompressOptions::new().with_max_output_len(1 << 30))
+                .err()
+                .unwrap()
+                .kind,
+            HuffmanErrorKind::LimitExceeded
+        );
+
+        // The run count must add up to the recorded length, whatever the limits.
+        let mut serialized = SingleSymbolCoding::encode_bytes(b"aaaa")
+            .unwrap()
+            .serialize()
+            .unwrap();
+        let len = serialized.len();
+        serialized[len - 8..].copy_from_slice(&(1u64 << 62).to_be_bytes());
+        assert_eq!(
+            HuffmanEncoding::<u8>::deserialize(&serialized)
+                .err()
+                .unwrap()
+                .kind,
+            HuffmanErrorKind::InvalidDeclaredLength
+        );
+        assert!(crate::decompress_bytes(&serialized).is_err());
+        assert_eq!(
+            SingleSymbolCoding::decode_bytes_with_options(
+                &tree,
+                &PackedBits::from_bytes((1u64 << 62).to_be_bytes().to_vec()),
+                4,
+                &DecompressOptions::default()
+            ),
+            Err(HuffmanError::decoding_error())
+        );
+
+        // Recording the same huge length fails to allocate instead of aborting.
+        serialized[6..14].copy_from_slice(&(1u64 << 62).to_be_bytes());
+        let encoding = HuffmanEncoding::<u8>::deserialize(&serialized).unwrap();
+        assert_eq!(encoding.decode(), Err(HuffmanError::decoding_error()));
+        let mut serialized = SingleSymbolCoding::encode("ééé")
+            .unwrap()
+            .serialize()
+            .unwrap();
+        let len = serialized.len();
+        serialized[6..14].copy_from_slice(&(1u64 << 62).to_be_bytes());
+        serialized[len - 8..].copy_from_slice(&(1u64 << 61).to_be_bytes());
+        assert!(crate::decompress(&serialized).is_err());
+
+        // The tree must hold exactly one symbol.
+        let mut serialized = SingleSymbolCoding::encode_bytes(b"aaaa")
+            .unwrap()
+            .serialize()
+            .unwrap();
+        serialized[22] = 2;
+        serialized.insert(23, b'b');
+        assert!(HuffmanEncoding::<u8>::deserialize(&serialized).is_err());
+    }
+}
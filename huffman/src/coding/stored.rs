@@ -0,0 +1,176 @@
+use super::huffman::{
+    DecompressOptions, HuffmanCoding, HuffmanDecoding, HuffmanEncoding, TreeFormat,
+};
+use crate::errors::huffman_error::HuffmanError;
+use crate::models::huffman_tree::HuffmanTree;
+use crate::utils::bit_io::PackedBits;
+use crate::utils::checksum;
+
+/// Keeps the data as is, for inputs that Huffman coding would only make
+/// larger (short or already compressed data). The `huffman_tree` passed to
+/// `decode` and `decode_bytes` is ignored.
+pub struct StoredCoding {}
+
+impl StoredCoding {
+    pub fn encode_text(text: &str) -> HuffmanEncoding<char> {
+        Self::encode_original(text.as_bytes())
+    }
+
+    pub fn encode_raw(bytes: &[u8]) -> HuffmanEncoding<u8> {
+        Self::encode_original(bytes)
+    }
+
+    /// Decodes like `decode`, failing if the output is larger than the
+    /// limits in `options`.
+    pub fn decode_with_options(
+        encoded_bits: &PackedBits,
+        options: &DecompressOptions,
+    ) -> Result<HuffmanDecoding, HuffmanError> {
+        match Self::decode_bytes_with_options(encoded_bits, options) {
+            Ok(bytes) => match String::from_utf8(bytes) {
+                Ok(text) => Ok(HuffmanDecoding::new(text)),
+                Err(_) => Err(HuffmanError::decoding_error()),
+            },
+            Err(e) => Err(e),
+        }
+    }
+
+    /// Decodes like `decode_bytes`, failing if the output is larger than the
+    /// limits in `options`.
+    pub fn decode_bytes_with_options(
+        encoded_bits: &PackedBits,
+        options: &DecompressOptions,
+    ) -> Result<Vec<u8>, HuffmanError> {
+        let bytes = encoded_bits.as_bytes();
+        let limit = options.output_limit(bytes.len());
+
+        if bytes.len() > limit {
+            Err(HuffmanError::output_limit_exceeded(limit))
+        } else {
+            Ok(bytes.to_vec())
+        }
+    }
+
+    fn encode_original<T>(original: &[u8]) -> HuffmanEncoding<T> {
+        HuffmanEncoding::new(
+            HuffmanTree::default(),
+            TreeFormat::Stored,
+            PackedBits::from_bytes(original.to_vec()),
+            original.len() as u64,
+            checksum::crc32(original),
+        )
+    }
+}
+
+impl HuffmanCoding for StoredCoding {
+    fn encode(text: &str) -> Result<HuffmanEncoding<char>, HuffmanError> {
+        Ok(Self::encode_text(text))
+    }
+
+    fn decode(
+        _huffman_tree: HuffmanTree<char>,
+        encoded_bits: PackedBits,
+    ) -> Result<HuffmanDecoding, HuffmanError> {
+        Self::decode_with_options(&encoded_bits, &DecompressOptions::default())
+    }
+
+    fn encode_bytes(bytes: &[u8]) -> Result<HuffmanEncoding<u8>, HuffmanError> {
+        Ok(Self::encode_raw(bytes))
+    }
+
+    fn decode_bytes(
+        _huffman_tree: HuffmanTree<u8>,
+        encoded_bits: PackedBits,
+    ) -> Result<Vec<u8>, HuffmanError> {
+        Self::decode_bytes_with_options(&encoded_bits, &DecompressOptions::default())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::coding::huffman::CanonicalHuffmanCoding;
+
+    #[test]
+    fn test_roundtrip() {
+        let text = "Huffman-ft-uyen";
+        let serialized = StoredCoding::encode_text(text).serialize().unwrap();
+
+        assert_eq!(
+            serialized.len(),
+            HuffmanEncoding::<char>::HEADER_LEN + text.len()
+        );
+        assert_eq!(serialized[5], 0b1001);
+        assert_eq!(
+            &serialized[HuffmanEncoding::<char>::HEADER_LEN..],
+            text.as_bytes()
+        );
+
+        let encoding = HuffmanEncoding::<char>::deserialize(&serialized).unwrap();
+        let tree = encoding.get_huffman_tree().clone();
+        let encoded_bits = encoding.get_encoded_bits().clone();
+        let decoded = StoredCoding::decode(tree, encoded_bits).unwrap();
+
+        assert_eq!(encoding.get_tree_format(), TreeFormat::Stored);
+        assert_eq!(decoded.get_decoded_text(), text);
+        assert!(encoding.verify(text.as_bytes()).is_ok());
+    }
+
+    #[test]
+    fn test_bytes_roundtrip() {
+        let bytes: Vec<u8> = (0..=255).collect();
+        let serialized = StoredCoding::encode_raw(&bytes).serialize().unwrap();
+        let encoding = HuffmanEncoding::<u8>::deserialize(&serialized).unwrap();
+        let tree = encoding.get_huffman_tree().clone();
+        let encoded_bits = encoding.get_encoded_bits().clone();
+
+        assert_eq!(serialized[5], 0b1000);
+        assert_eq!(
+            StoredCoding::decode_bytes(tree, encoded_bits).unwrap(),
+            bytes
+        );
+    }
+
+    #[test]
+    fn test_decode_failed() {
+        let encoded_bits = PackedBits::from_bytes(vec![0xff, 0xfe]);
+        assert_eq!(
+            StoredCoding::decode(HuffmanTree::default(), encoded_bits.clone()),
+            Err(HuffmanError::decoding_error())
+        );
+        assert_eq!(
+            StoredCoding::decode_bytes_with_options(
+                &encoded_bits,
+                &DecompressOptions::new().with_max_output_len(1)
+            ),
+            Err(HuffmanError::output_limit_exceeded(1))
+        );
+
+        // A stored container cannot carry a tree or padding bits.
+        let mut serialized = StoredCoding::encode_raw(b"abc").serialize().unwrap();
+        serialized[18] = 1;
+        assert!(HuffmanEncoding::<u8>::deserialize(&serialized).is_err());
+        serialized[18] = 0;
+        serialized[5] |= 0b0010;
+        assert!(HuffmanEncoding::<u8>::deserialize(&serialized).is_err());
+    }
+
+    #[test]
+    fn test_serialize_or_stored() {
+        let text = "Huffman-ft-uyen";
+        let encoding = CanonicalHuffmanCoding::encode(text).unwrap();
+        let serialized = encoding.serialize_or_stored(text).unwrap();
+
+        assert_eq!(
+            serialized,
+            StoredCoding::encode_text(text).serialize().unwrap()
+        );
+
+        let text = "aaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaab";
+        let encoding = CanonicalHuffmanCoding::encode(text).unwrap();
+        assert_eq!(
+            encoding.serialize_or_stored(text).unwrap(),
+            encoding.serialize().unwrap()
+        );
+    }
+}
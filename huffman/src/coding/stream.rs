@@ -0,0 +1,479 @@
+use std::io::{self, Read, Write};
+
+use super::huffman::{CanonicalHuffmanCoding, DecompressOptions, HuffmanCoding, HuffmanEncoding};
+use crate::errors::huffman_error::HuffmanError;
+use crate::utils::bit_io::Code;
+
+pub(crate) const STREAM_MAGIC: [u8; 4] = [0x89, b'H', b'U', b'S'];
+pub(crate) const DEFAULT_BLOCK_SIZE: usize = 1 << 20;
+/// A frame holds at most a stored container of a whole block, whose length
+/// must still fit the `u32` frame length.
+pub(crate) const MAX_BLOCK_SIZE: usize = u32::MAX as usize - HuffmanEncoding::<u8>::HEADER_LEN;
+// A block container can never be larger than this many bytes per input byte
+// (codes are at most `Code::MAX_LEN` bits), plus room for the header and the tree.
+const MAX_FRAME_BYTES_PER_SYMBOL: usize = Code::MAX_LEN / 8;
+const MAX_FRAME_OVERHEAD: usize = 1024;
+
+fn invalid_data(e: HuffmanError) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, e)
+}
+
+pub(crate) fn max_frame_len(block_size: usize) -> usize {
+    block_size
+        .saturating_mul(MAX_FRAME_BYTES_PER_SYMBOL)
+        .saturating_add(MAX_FRAME_OVERHEAD)
+}
+
+/// Clamps `block_size` to `1..=MAX_BLOCK_SIZE`.
+pub(crate) fn clamp_block_size(block_size: usize) -> usize {
+    block_size.clamp(1, MAX_BLOCK_SIZE)
+}
+
+/// The magic and block size that open a stream of `clamp_block_size` blocks.
+pub(crate) fn stream_header(block_size: usize) -> [u8; 8] {
+    let mut header = [0u8; 8];
+
+    header[..4].copy_from_slice(&STREAM_MAGIC);
+    header[4..].copy_from_slice(&(clamp_block_size(block_size) as u32).to_be_bytes());
+    header
+}
+
+/// The zero frame length that ends a stream.
+pub(crate) const STREAM_END: [u8; 4] = [0; 4];
+
+/// Encodes one block into its frame: the container and its `u32` length.
+pub(crate) fn encode_frame(block: &[u8]) -> Result<Vec<u8>, HuffmanError> {
+    let container = CanonicalHuffmanCoding::encode_bytes(block)
+        .and_then(|encoding| encoding.serialize_or_stored(block))?;
+
+    match u32::try_from(container.len()) {
+        Ok(len) => {
+            let mut frame: Vec<u8> = Vec::with_capacity(4 + container.len());
+            frame.extend_from_slice(&len.to_be_bytes());
+            frame.extend_from_slice(&container);
+            Ok(frame)
+        }
+        Err(_) => Err(HuffmanError::encoding_error()),
+    }
+}
+
+/// Decodes the container of one frame, which must not hold more than
+/// `block_size` bytes.
+pub(crate) fn decode_frame(
+    encoding: &HuffmanEncoding<u8>,
+    block_size: usize,
+) -> Result<Vec<u8>, HuffmanError> {
+    encoding.decode_with_options(&DecompressOptions::new().with_max_output_len(block_size))
+}
+
+/// Running totals of a stream being decoded. Every block is checked against
+/// the `DecompressOptions` limits before it is decoded, so a stream of many
+/// small blocks cannot expand past them either.
+pub(crate) struct OutputBudget {
+    options: DecompressOptions,
+    output_len: u64,
+}
+
+impl OutputBudget {
+    pub(crate) fn new(options: DecompressOptions) -> Self {
+        Self {
+            options,
+            output_len: 0,
+        }
+    }
+
+    /// Accounts for a block of `original_len` bytes whose frame ends
+    /// `input_len` bytes into the stream.
+    pub(crate) fn spend(
+        &mut self,
+        input_len: usize,
+        original_len: u64,
+    ) -> Result<(), HuffmanError> {
+        let limit = self.options.output_limit(input_len);
+
+        match self.output_len.checked_add(original_len) {
+            Some(output_len) if output_len <= limit as u64 => {
+                self.output_len = output_len;
+                Ok(())
+            }
+            _ => Err(HuffmanError::output_limit_exceeded(limit)),
+        }
+    }
+}
+
+/// Compresses everything written to it into a stream of independent blocks.
+///
+/// The input is cut into blocks of at most `block_size` bytes and every block
+/// is encoded with its own tree, so memory use does not depend on the input
+/// size. The stream is laid out as:
+///
+/// | field      | size     |                                           |
+/// |------------|----------|-------------------------------------------|
+/// | magic      | 4 bytes  | `0x89 'H' 'U' 'S'`                        |
+/// | block size | `u32`    | upper bound on the decoded size of a block |
+/// | frames     | variable | `u32` length followed by a block container |
+/// | end        | `u32`    | a zero length                             |
+///
+/// `finish` must be called to write the last block and the end marker.
+pub struct HuffmanEncoder<W: Write> {
+    writer: W,
+    block_size: usize,
+    buffer: Vec<u8>,
+    header_written: bool,
+}
+
+impl<W: Write> HuffmanEncoder<W> {
+    pub fn new(writer: W) -> Self {
+        Self::with_block_size(writer, DEFAULT_BLOCK_SIZE)
+    }
+
+    /// `block_size` is clamped to `1..=u32::MAX - HuffmanEncoding::HEADER_LEN`,
+    /// so every frame length fits its `u32` prefix.
+    pub fn with_block_size(writer: W, block_size: usize) -> Self {
+        let block_size = clamp_block_size(block_size);
+
+        Self {
+            writer,
+            block_size,
+            buffer: Vec::new(),
+            header_written: false,
+        }
+    }
+
+    pub fn get_block_size(&self) -> usize {
+        self.block_size
+    }
+
+    /// Writes the remaining data and the end marker, returning the inner writer.
+    pub fn finish(mut self) -> io::Result<W> {
+        self.write_header()?;
+        self.write_block()?;
+        self.writer.write_all(&STREAM_END)?;
+        self.writer.flush()?;
+        Ok(self.writer)
+    }
+
+    fn write_header(&mut self) -> io::Result<()> {
+        if !self.header_written {
+            self.writer.write_all(&stream_header(self.block_size))?;
+            self.header_written = true;
+        }
+        Ok(())
+    }
+
+    fn write_block(&mut self) -> io::Result<()> {
+        if self.buffer.is_empty() {
+            return Ok(());
+        }
+
+        match encode_frame(&self.buffer) {
+            Ok(frame) => {
+                self.writer.write_all(&frame)?;
+                self.buffer.clear();
+                Ok(())
+            }
+            Err(e) => Err(invalid_data(e)),
+        }
+    }
+}
+
+impl<W: Write> Write for HuffmanEncoder<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.write_header()?;
+
+        let len = buf.len().min(self.block_size - self.buffer.len());
+        self.buffer.extend_from_slice(&buf[..len]);
+
+        if self.buffer.len() == self.block_size {
+            self.write_block()?;
+        }
+        Ok(len)
+    }
+
+    /// Flushes the inner writer. A partially filled block is kept until it is
+    /// full or `finish` is called, so flushing does not shrink the blocks.
+    fn flush(&mut self) -> io::Result<()> {
+        self.writer.flush()
+    }
+}
+
+/// Reads a stream written by `HuffmanEncoder`, decoding one block at a time.
+///
+/// The limits of `DecompressOptions` apply to the whole stream: the total
+/// output so far is checked against them before every block.
+pub struct HuffmanDecoder<R: Read> {
+    reader: R,
+    block_size: Option<usize>,
+    block: Vec<u8>,
+    pos: usize,
+    finished: bool,
+    input_len: usize,
+    budget: OutputBudget,
+}
+
+impl<R: Read> HuffmanDecoder<R> {
+    /// A decoder without output limits.
+    pub fn new(reader: R) -> Self {
+        Self::with_options(reader, DecompressOptions::default())
+    }
+
+    pub fn with_options(reader: R, options: DecompressOptions) -> Self {
+        Self {
+            reader,
+            block_size: None,
+            block: Vec::new(),
+            pos: 0,
+            finished: false,
+            input_len: 0,
+            budget: OutputBudget::new(options),
+        }
+    }
+
+    pub fn into_inner(self) -> R {
+        self.reader
+    }
+
+    fn read_u32(&mut self) -> io::Result<u32> {
+        let mut bytes = [0u8; 4];
+
+        self.reader.read_exact(&mut bytes)?;
+        self.input_len += bytes.len();
+        Ok(u32::from_be_bytes(bytes))
+    }
+
+    fn read_header(&mut self) -> io::Result<usize> {
+        match self.block_size {
+            Some(block_size) => Ok(block_size),
+            None => {
+                let mut magic = [0u8; 4];
+                self.reader.read_exact(&mut magic)?;
+                self.input_len += magic.len();
+
+                if magic != STREAM_MAGIC {
+                    return Err(invalid_data(HuffmanError::bad_magic().at_byte(0)));
+                }
+
+                match self.read_u32()? as usize {
+                    0 => Err(invalid_data(HuffmanError::cannot_deserialize_tree())),
+                    block_size => {
+                        self.block_size = Some(block_size);
+                        Ok(block_size)
+                    }
+                }
+            }
+        }
+    }
+
+    /// Loads the next block, returning `false` once the end marker is reached.
+    fn read_block(&mut self) -> io::Result<bool> {
+        let block_size = self.read_header()?;
+        let frame_len = self.read_u32()? as usize;
+
+        if frame_len == 0 {
+            self.finished = true;
+            return Ok(false);
+        }
+        if frame_len > max_frame_len(block_size) {
+            return Err(invalid_data(HuffmanError::cannot_deserialize_tree()));
+        }
+
+        // The length is untrusted: only what the reader actually holds is
+        // buffered, instead of allocating `frame_len` bytes up front.
+        let mut frame = Vec::new();
+        (&mut self.reader)
+            .take(frame_len as u64)
+            .read_to_end(&mut frame)?;
+        if frame.len() < frame_len {
+            return Err(io::ErrorKind::UnexpectedEof.into());
+        }
+        self.input_len += frame_len;
+
+        let decoded = HuffmanEncoding::<u8>::deserialize(&frame).and_then(|encoding| {
+            self.budget
+                .spend(self.input_len, encoding.get_original_len())
+                .and_then(|()| decode_frame(&encoding, block_size))
+        });
+        match decoded {
+            Ok(decoded) => {
+                self.block = decoded;
+                self.pos = 0;
+                Ok(true)
+            }
+            Err(e) => Err(invalid_data(e)),
+        }
+    }
+}
+
+impl<R: Read> Read for HuffmanDecoder<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        while self.pos == self.block.len() {
+            if self.finished || buf.is_empty() || !self.read_block()? {
+                return Ok(0);
+            }
+        }
+
+        let len = buf.len().min(self.block.len() - self.pos);
+        buf[..len].copy_from_slice(&self.block[self.pos..self.pos + len]);
+        self.pos += len;
+        Ok(len)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::coding::huffman::TreeFormat;
+
+    fn compress_stream(input: &[u8], block_size: usize) -> Vec<u8> {
+        let mut encoder = HuffmanEncoder::with_block_size(Vec::new(), block_size);
+
+        encoder.write_all(input).unwrap();
+        encoder.finish().unwrap()
+    }
+
+    fn decompress_stream(input: &[u8]) -> io::Result<Vec<u8>> {
+        let mut decoder = HuffmanDecoder::new(input);
+        let mut output = Vec::new();
+
+        decoder.read_to_end(&mut output)?;
+        Ok(output)
+    }
+
+    #[test]
+    fn test_stream_roundtrip() {
+        let input = "Welcome to my world!!! ".repeat(100).into_bytes();
+
+        for block_size in [1, 7, 64, 1000, DEFAULT_BLOCK_SIZE] {
+            let compressed = compress_stream(&input, block_size);
+
+            assert_eq!(decompress_stream(&compressed).unwrap(), input);
+        }
+    }
+
+    #[test]
+    fn test_stream_layout() {
+        let compressed = compress_stream(b"aabbbbcc", 4);
+        let frame_len = u32::from_be_bytes(compressed[8..12].try_into().unwrap()) as usize;
+        let first_block = HuffmanEncoding::<u8>::deserialize(&compressed[12..12 + frame_len]);
+
+        assert_eq!(compressed[..4], STREAM_MAGIC);
+        assert_eq!(compressed[4..8], [0, 0, 0, 4]);
+        assert_eq!(first_block.unwrap().get_original_len(), 4);
+        assert_eq!(compressed[compressed.len() - 4..], [0, 0, 0, 0]);
+    }
+
+    #[test]
+    fn test_stream_stored_blocks() {
+        let input: Vec<u8> = (0..=255).collect();
+        let compressed = compress_stream(&input, 256);
+        let frame_len = u32::from_be_bytes(compressed[8..12].try_into().unwrap()) as usize;
+        let block = HuffmanEncoding::<u8>::deserialize(&compressed[12..12 + frame_len]).unwrap();
+
+        assert_eq!(block.get_tree_format(), TreeFormat::Stored);
+        assert_eq!(frame_len, HuffmanEncoding::<u8>::HEADER_LEN + input.len());
+        assert_eq!(decompress_stream(&compressed).unwrap(), input);
+    }
+
+    #[test]
+    fn test_stream_empty_input() {
+        let compressed = compress_stream(b"", 16);
+
+        assert_eq!(
+            compressed,
+            [0x89, b'H', b'U', b'S', 0, 0, 0, 16, 0, 0, 0, 0]
+        );
+        assert_eq!(decompress_stream(&compressed).unwrap(), b"");
+    }
+
+    #[test]
+    fn test_stream_block_size_clamped() {
+        let encoder = HuffmanEncoder::with_block_size(Vec::new(), usize::MAX);
+        let stored_frame_len = HuffmanEncoding::<u8>::HEADER_LEN + encoder.get_block_size();
+
+        assert_eq!(encoder.get_block_size(), MAX_BLOCK_SIZE);
+        assert_eq!(stored_frame_len, u32::MAX as usize);
+        assert_eq!(
+            HuffmanEncoder::with_block_size(Vec::new(), 0).get_block_size(),
+            1
+        );
+    }
+
+    #[test]
+    fn test_stream_small_writes() {
+        let input: Vec<u8> = (0..=255).cycle().take(5000).collect();
+        let mut encoder = HuffmanEncoder::with_block_size(Vec::new(), 300);
+
+        input
+            .chunks(17)
+            .for_each(|chunk| encoder.write_all(chunk).unwrap());
+        let compressed = encoder.finish().unwrap();
+
+        assert_eq!(compressed, compress_stream(&input, 300));
+        assert_eq!(decompress_stream(&compressed).unwrap(), input);
+    }
+
+    #[test]
+    fn test_stream_output_limit() {
+        let input = "Welcome to my world!!! ".repeat(100).into_bytes();
+        let compressed = compress_stream(&input, 64);
+        let decompress_with = |options: DecompressOptions| {
+            let mut decoder = HuffmanDecoder::with_options(&compressed[..], options);
+            let mut output = Vec::new();
+            decoder.read_to_end(&mut output).map(|_| output)
+        };
+
+        let options = DecompressOptions::new().with_max_output_len(input.len());
+        assert_eq!(decompress_with(options).unwrap(), input);
+
+        // Every block is small, but together they exceed the limit.
+        let options = DecompressOptions::new().with_max_output_len(input.len() - 1);
+        let error = decompress_with(options).unwrap_err();
+        assert_eq!(error.kind(), io::ErrorKind::InvalidData);
+        assert_eq!(
+            error
+                .into_inner()
+                .unwrap()
+                .downcast::<HuffmanError>()
+                .unwrap()
+                .kind,
+            crate::errors::huffman_error::HuffmanErrorKind::LimitExceeded
+        );
+
+        let compressed = compress_stream(&[b'a'; 10_000], 1000);
+        let options = DecompressOptions::new().with_max_expansion_ratio(10);
+        let mut decoder = HuffmanDecoder::with_options(&compressed[..], options);
+        assert!(decoder.read_to_end(&mut Vec::new()).is_err());
+    }
+
+    #[test]
+    fn test_stream_failed() {
+        let compressed = compress_stream(b"Welcome to my world!!!", 8);
+
+        let result = decompress_stream(&compressed[..compressed.len() - 1]);
+        assert_eq!(result.unwrap_err().kind(), io::ErrorKind::UnexpectedEof);
+
+        let mut corrupted = compressed.clone();
+        corrupted[0] = 0;
+        let result = decompress_stream(&corrupted);
+        assert_eq!(result.unwrap_err().kind(), io::ErrorKind::InvalidData);
+
+        let mut corrupted = compressed.clone();
+        let last_payload_byte = corrupted.len() - 5;
+        corrupted[last_payload_byte] ^= 0xff;
+        let result = decompress_stream(&corrupted);
+        assert_eq!(result.unwrap_err().kind(), io::ErrorKind::InvalidData);
+
+        let mut corrupted = compressed.clone();
+        corrupted[8..12].copy_from_slice(&u32::MAX.to_be_bytes());
+        let result = decompress_stream(&corrupted);
+        assert_eq!(result.unwrap_err().kind(), io::ErrorKind::InvalidData);
+
+        // A huge block size and frame length with nothing behind them.
+        let mut header = STREAM_MAGIC.to_vec();
+        header.extend_from_slice(&u32::MAX.to_be_bytes());
+        header.extend_from_slice(&(u32::MAX - 1).to_be_bytes());
+        header.extend_from_slice(b"abcd");
+        let result = decompress_stream(&header);
+        assert_eq!(result.unwrap_err().kind(), io::ErrorKind::UnexpectedEof);
+    }
+}
@@ -1,24 +1,65 @@
 use std::fmt;
 
-#[derive(Debug, PartialEq)]
+use super::huffman_error::HuffmanError;
+
+#[derive(Debug, PartialEq, Clone)]
 pub struct CompressionError {
     pub msg: String,
+    pub kind: CompressionErrorKind,
+    /// The coding error that made the operation fail.
+    pub source: HuffmanError,
 }
 
 impl CompressionError {
-    pub fn cannot_compress_text(detail: String) -> Self {
-        let msg: String = format!("Cannot compress text input: {}", detail);
-        Self { msg: msg }
+    pub fn cannot_compress_text(source: HuffmanError) -> Self {
+        Self {
+            msg: "Cannot compress text input".to_string(),
+            kind: CompressionErrorKind::CompressText,
+            source,
+        }
+    }
+
+    pub fn cannot_decompress_text(source: HuffmanError) -> Self {
+        Self {
+            msg: "Cannot decompress text input".to_string(),
+            kind: CompressionErrorKind::DecompressText,
+            source,
+        }
+    }
+
+    pub fn cannot_compress_bytes(source: HuffmanError) -> Self {
+        Self {
+            msg: "Cannot compress binary input".to_string(),
+            kind: CompressionErrorKind::CompressBytes,
+            source,
+        }
     }
 
-    pub fn cannot_decompress_text(detail: String) -> Self {
-        let msg: String = format!("Cannot decompress text input: {}", detail);
-        Self { msg: msg }
+    pub fn cannot_decompress_bytes(source: HuffmanError) -> Self {
+        Self {
+            msg: "Cannot decompress binary input".to_string(),
+            kind: CompressionErrorKind::DecompressBytes,
+            source,
+        }
     }
 }
 
 impl fmt::Display for CompressionError {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        write!(f, "Error: {}", &self.msg)
+        write!(f, "{}", &self.msg)
     }
 }
+
+impl std::error::Error for CompressionError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        Some(&self.source)
+    }
+}
+
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum CompressionErrorKind {
+    CompressText,
+    DecompressText,
+    CompressBytes,
+    DecompressBytes,
+}
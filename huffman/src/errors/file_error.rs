@@ -1,34 +1,84 @@
-use std::fmt;
+use std::{fmt, io};
 
-#[derive(Debug, PartialEq)]
+#[derive(Debug)]
 pub struct FileError {
     pub msg: String,
+    pub kind: FileErrorKind,
+    pub path: String,
+    /// The I/O error reported by the operating system, if any.
+    pub source: Option<io::Error>,
 }
 
 impl FileError {
+    fn new(msg: String, kind: FileErrorKind, path: &str) -> Self {
+        Self {
+            msg,
+            kind,
+            path: path.to_string(),
+            source: None,
+        }
+    }
+
     pub fn cannot_open_file(path: &str) -> Self {
         let msg: String = format!("There is an error when trying to open file: {}", path);
-        Self { msg: msg }
+        Self::new(msg, FileErrorKind::CannotOpen, path)
     }
 
     pub fn cannot_read_file(path: &str) -> Self {
         let msg: String = format!("There is an error when trying to read file: {}", path);
-        Self { msg: msg }
+        Self::new(msg, FileErrorKind::CannotRead, path)
     }
 
     pub fn cannot_write_file(path: &str) -> Self {
         let msg: String = format!("There is an error when trying to write file: {}", path);
-        Self { msg: msg }
+        Self::new(msg, FileErrorKind::CannotWrite, path)
+    }
+
+    pub fn cannot_create_file(path: &str) -> Self {
+        let msg: String = format!("There is an error when trying to create file: {}", path);
+        Self::new(msg, FileErrorKind::CannotCreate, path)
     }
 
     pub fn file_already_existed(path: &str) -> Self {
         let msg: String = format!("This file has already existed: {}", path);
-        Self { msg: msg }
+        Self::new(msg, FileErrorKind::AlreadyExists, path)
+    }
+
+    /// Attaches the underlying I/O error.
+    pub fn with_source(mut self, source: io::Error) -> Self {
+        self.source = Some(source);
+        self
+    }
+}
+
+/// Two errors are equal when they describe the same failure on the same path;
+/// the attached I/O errors are not compared.
+impl PartialEq for FileError {
+    fn eq(&self, other: &Self) -> bool {
+        self.kind == other.kind && self.path == other.path && self.msg == other.msg
     }
 }
 
 impl fmt::Display for FileError {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        write!(f, "Error: {}", &self.msg)
+        write!(f, "{}", &self.msg)
     }
 }
+
+impl std::error::Error for FileError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match &self.source {
+            Some(source) => Some(source),
+            None => None,
+        }
+    }
+}
+
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum FileErrorKind {
+    CannotOpen,
+    CannotCreate,
+    CannotRead,
+    CannotWrite,
+    AlreadyExists,
+}
@@ -4,58 +4,222 @@ use std::fmt;
 pub struct HuffmanError {
     pub msg: String,
     pub kind: HuffmanErrorKind,
+    /// Where in the input the failure was detected, when known.
+    pub position: Option<ErrorPosition>,
+    /// How far decoding got before it failed, for decoding errors.
+    pub progress: Option<DecodeProgress>,
 }
 
 impl HuffmanError {
+    /// Records the byte offset in the input at which the failure was detected.
+    pub fn at_byte(mut self, offset: usize) -> Self {
+        self.position = Some(ErrorPosition::Byte(offset));
+        self
+    }
+
+    /// Records the bit offset in the payload at which the failure was detected.
+    pub fn at_bit(mut self, offset: usize) -> Self {
+        self.position = Some(ErrorPosition::Bit(offset));
+        self
+    }
+
+    /// Records how much had been decoded when the failure was detected.
+    pub fn with_progress(mut self, progress: DecodeProgress) -> Self {
+        self.progress = Some(progress);
+        self
+    }
+
+    /// The output decoded before the failure, if it was asked for with
+    /// `DecompressOptions::with_partial_output`.
+    pub fn partial_output(&self) -> Option<&[u8]> {
+        match &self.progress {
+            Some(progress) => progress.partial_output.as_deref(),
+            None => None,
+        }
+    }
+
     pub fn invalid_huffman_tree() -> Self {
         Self {
             msg: "The tree seems to be invalid.".to_string(),
             kind: HuffmanErrorKind::InvalidTree,
+            position: None,
+            progress: None,
         }
     }
     pub fn not_found_in_tree() -> Self {
         Self {
             msg: "Cannot found item in Huffman tree.".to_string(),
             kind: HuffmanErrorKind::ItemNotFound,
+            position: None,
+            progress: None,
         }
     }
     pub fn cannot_serialize_tree() -> Self {
         Self {
             msg: "Cannot serialize the Huffman tree".to_string(),
-            kind: HuffmanErrorKind::InvalidTree,
+            kind: HuffmanErrorKind::SerializingError,
+            position: None,
+            progress: None,
         }
     }
     pub fn cannot_deserialize_tree() -> Self {
         Self {
             msg: "Cannot deserialize the input".to_string(),
             kind: HuffmanErrorKind::DeserializingError,
+            position: None,
+            progress: None,
         }
     }
     pub fn encoding_error() -> Self {
         Self {
             msg: "Cannot encode text".to_string(),
             kind: HuffmanErrorKind::EncodingError,
+            position: None,
+            progress: None,
         }
     }
     pub fn decoding_error() -> Self {
         Self {
             msg: "Cannot decode text".to_string(),
             kind: HuffmanErrorKind::DecodingError,
+            position: None,
+            progress: None,
+        }
+    }
+    pub fn bad_magic() -> Self {
+        Self {
+            msg: "The input is not a Huffman-compressed container".to_string(),
+            kind: HuffmanErrorKind::BadMagic,
+            position: None,
+            progress: None,
+        }
+    }
+    pub fn unsupported_version(version: u8) -> Self {
+        Self {
+            msg: format!("Unsupported container format version: {}", version),
+            kind: HuffmanErrorKind::UnsupportedVersion,
+            position: None,
+            progress: None,
+        }
+    }
+    pub fn invalid_max_code_length(max_length: usize) -> Self {
+        Self {
+            msg: format!("Cannot limit the codes to {} bits", max_length),
+            kind: HuffmanErrorKind::InvalidMaxCodeLength,
+            position: None,
+            progress: None,
+        }
+    }
+    pub fn invalid_dictionary_name(name: &str) -> Self {
+        Self {
+            msg: format!("Dictionary names must be 1 to 255 bytes long: {:?}", name),
+            kind: HuffmanErrorKind::InvalidDictionaryName,
+            position: None,
+            progress: None,
+        }
+    }
+    pub fn unknown_dictionary(id: u32) -> Self {
+        Self {
+            msg: format!("No dictionary with id {:08x} is available", id),
+            kind: HuffmanErrorKind::UnknownDictionary,
+            position: None,
+            progress: None,
+        }
+    }
+    pub fn invalid_declared_length(field: &str, len: u64) -> Self {
+        Self {
+            msg: format!("The declared {} ({}) is out of bounds", field, len),
+            kind: HuffmanErrorKind::InvalidDeclaredLength,
+            position: None,
+            progress: None,
+        }
+    }
+    pub fn output_limit_exceeded(limit: usize) -> Self {
+        Self {
+            msg: format!("The decoded output exceeds the limit of {} bytes", limit),
+            kind: HuffmanErrorKind::LimitExceeded,
+            position: None,
+            progress: None,
+        }
+    }
+    pub fn checksum_mismatch() -> Self {
+        Self {
+            msg: "The decoded data does not match the stored checksum".to_string(),
+            kind: HuffmanErrorKind::ChecksumMismatch,
+            position: None,
+            progress: None,
         }
     }
 }
 
 impl fmt::Display for HuffmanError {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        write!(f, "Error: {}", &self.msg)
+        match (self.position, &self.progress) {
+            (Some(position), Some(progress)) => {
+                write!(f, "{} (at {}, {})", &self.msg, position, progress)
+            }
+            (Some(position), None) => write!(f, "{} (at {})", &self.msg, position),
+            (None, Some(progress)) => write!(f, "{} ({})", &self.msg, progress),
+            (None, None) => write!(f, "{}", &self.msg),
+        }
     }
 }
 
-#[derive(Debug, PartialEq, Clone)]
+impl std::error::Error for HuffmanError {}
+
+#[derive(Debug, PartialEq, Eq, Clone, Default)]
+pub struct DecodeProgress {
+    /// Number of symbols decoded before the failure.
+    pub symbols: usize,
+    /// Length in bytes of the output decoded before the failure.
+    pub output_len: usize,
+    /// That output itself, when it was asked for.
+    pub partial_output: Option<Vec<u8>>,
+}
+
+impl fmt::Display for DecodeProgress {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "after {} symbols, {} bytes decoded",
+            self.symbols, self.output_len
+        )
+    }
+}
+
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum ErrorPosition {
+    /// Offset into the serialized input.
+    Byte(usize),
+    /// Offset into the encoded payload.
+    Bit(usize),
+}
+
+impl fmt::Display for ErrorPosition {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ErrorPosition::Byte(offset) => write!(f, "byte {}", offset),
+            ErrorPosition::Bit(offset) => write!(f, "bit {}", offset),
+        }
+    }
+}
+
+/// One kind per `HuffmanError` constructor, so callers can tell every
+/// failure apart without parsing the message.
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
 pub enum HuffmanErrorKind {
     InvalidTree,
     ItemNotFound,
+    SerializingError,
+    DeserializingError,
     EncodingError,
     DecodingError,
-    DeserializingError,
+    BadMagic,
+    UnsupportedVersion,
+    InvalidMaxCodeLength,
+    InvalidDictionaryName,
+    UnknownDictionary,
+    InvalidDeclaredLength,
+    LimitExceeded,
+    ChecksumMismatch,
 }
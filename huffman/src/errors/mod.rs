@@ -1,3 +1,65 @@
 pub mod compression_error;
 pub mod file_error;
 pub mod huffman_error;
+
+use std::{fmt, io};
+
+use compression_error::CompressionError;
+use file_error::FileError;
+use huffman_error::HuffmanError;
+
+/// Any error this crate can return, so callers can propagate them all with
+/// `?` into one type and still match on the specific failure.
+#[derive(Debug)]
+pub enum Error {
+    Huffman(HuffmanError),
+    Compression(CompressionError),
+    File(FileError),
+    Io(io::Error),
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Error::Huffman(e) => e.fmt(f),
+            Error::Compression(e) => e.fmt(f),
+            Error::File(e) => e.fmt(f),
+            Error::Io(e) => e.fmt(f),
+        }
+    }
+}
+
+impl std::error::Error for Error {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Error::Huffman(e) => std::error::Error::source(e),
+            Error::Compression(e) => std::error::Error::source(e),
+            Error::File(e) => std::error::Error::source(e),
+            Error::Io(e) => std::error::Error::source(e),
+        }
+    }
+}
+
+impl From<HuffmanError> for Error {
+    fn from(e: HuffmanError) -> Self {
+        Error::Huffman(e)
+    }
+}
+
+impl From<CompressionError> for Error {
+    fn from(e: CompressionError) -> Self {
+        Error::Compression(e)
+    }
+}
+
+impl From<FileError> for Error {
+    fn from(e: FileError) -> Self {
+        Error::File(e)
+    }
+}
+
+impl From<io::Error> for Error {
+    fn from(e: io::Error) -> Self {
+        Error::Io(e)
+    }
+}
@@ -1,36 +1,143 @@
-mod coding;
-mod errors;
-mod models;
-mod utils;
+//! Huffman coding for text and raw bytes.
+//!
+//! `compress`/`decompress` and their `_bytes` variants cover the common case.
+//! Custom pipelines can build a `HuffmanTree` (or a `FrequencyTable`), encode
+//! with any `HuffmanCoding` and serialize the resulting `HuffmanEncoding`;
+//! `prelude` re-exports everything needed for that.
 
-use coding::huffman::{HuffmanCoding, HuffmanEncoding, StandardHuffmanCoding};
-use errors::compression_error::CompressionError;
+pub mod coding;
+pub mod errors;
+pub mod models;
+pub mod prelude;
+pub mod utils;
 
-pub fn compress(text: &String) -> Result<String, CompressionError> {
-    match <StandardHuffmanCoding as HuffmanCoding>::encode(&text) {
-        Ok(encoding_result) => Ok(encoding_result.serialize()),
-        Err(e) => Err(CompressionError::cannot_compress_text(e.msg)),
+pub use coding::dictionary::HuffmanDictionary;
+pub use coding::huffman::{
+    CanonicalHuffmanCoding, DecompressOptions, HuffmanCoding, HuffmanDecoding, HuffmanEncoding,
+    StandardHuffmanCoding,
+};
+pub use coding::parallel::{ParallelCoding, ParallelOptions};
+pub use coding::stream::{HuffmanDecoder, HuffmanEncoder};
+pub use errors::compression_error::{CompressionError, CompressionErrorKind};
+pub use errors::file_error::{FileError, FileErrorKind};
+pub use errors::huffman_error::{DecodeProgress, ErrorPosition, HuffmanError, HuffmanErrorKind};
+pub use errors::Error;
+pub use models::frequency_table::FrequencyTable;
+pub use models::huffman_tree::HuffmanTree;
+
+pub fn compress(text: &str) -> Result<Vec<u8>, CompressionError> {
+    match <CanonicalHuffmanCoding as HuffmanCoding>::encode(text)
+        .and_then(|encoding_result| encoding_result.serialize_or_stored(text))
+    {
+        Ok(serialized) => Ok(serialized),
+        Err(e) => Err(CompressionError::cannot_compress_text(e)),
     }
 }
 
-pub fn decompress(text: &String) -> Result<String, CompressionError> {
-    match HuffmanEncoding::deserialize(text.to_string()) {
-        Ok(encoding) => {
-            let tree = encoding.get_huffman_tree().clone();
-            let bin_vec = encoding.get_encoded_vec().clone();
+pub fn decompress(bytes: &[u8]) -> Result<String, CompressionError> {
+    decompress_with_options(bytes, &DecompressOptions::default())
+}
 
-            match <StandardHuffmanCoding as HuffmanCoding>::decode(tree, bin_vec) {
-                Ok(decoding) => Ok(decoding.get_decoded_text().clone()),
-                Err(e) => Err(CompressionError::cannot_decompress_text(e.msg)),
-            }
+/// Like `decompress`, but fails once the output would exceed the limits in `options`.
+pub fn decompress_with_options(
+    bytes: &[u8],
+    options: &DecompressOptions,
+) -> Result<String, CompressionError> {
+    match HuffmanEncoding::<char>::deserialize(bytes) {
+        Ok(encoding) => match encoding.decode_with_options(options) {
+            Ok(decoded) => Ok(decoded),
+            Err(e) => Err(CompressionError::cannot_decompress_text(e)),
+        },
+        Err(e) => Err(CompressionError::cannot_decompress_text(e)),
+    }
+}
+
+pub fn compress_bytes(bytes: &[u8]) -> Result<Vec<u8>, CompressionError> {
+    match <CanonicalHuffmanCoding as HuffmanCoding>::encode_bytes(bytes)
+        .and_then(|encoding_result| encoding_result.serialize_or_stored(bytes))
+    {
+        Ok(serialized) => Ok(serialized),
+        Err(e) => Err(CompressionError::cannot_compress_bytes(e)),
+    }
+}
+
+pub fn decompress_bytes(bytes: &[u8]) -> Result<Vec<u8>, CompressionError> {
+    decompress_bytes_with_options(bytes, &DecompressOptions::default())
+}
+
+/// Like `decompress_bytes`, but fails once the output would exceed the limits in `options`.
+pub fn decompress_bytes_with_options(
+    bytes: &[u8],
+    options: &DecompressOptions,
+) -> Result<Vec<u8>, CompressionError> {
+    match HuffmanEncoding::<u8>::deserialize(bytes) {
+        Ok(encoding) => match encoding.decode_with_options(options) {
+            Ok(decoded) => Ok(decoded),
+            Err(e) => Err(CompressionError::cannot_decompress_bytes(e)),
+        },
+        Err(e) => Err(CompressionError::cannot_decompress_bytes(e)),
+    }
+}
+
+/// Compresses `bytes` into the block stream written by `HuffmanEncoder`,
+/// encoding the blocks on several threads. The output does not depend on the
+/// thread count.
+pub fn compress_bytes_parallel(
+    bytes: &[u8],
+    options: &ParallelOptions,
+) -> Result<Vec<u8>, CompressionError> {
+    match ParallelCoding::compress(bytes, options) {
+        Ok(compressed) => Ok(compressed),
+        Err(e) => Err(CompressionError::cannot_compress_bytes(e)),
+    }
+}
+
+/// Decompresses a block stream, decoding the blocks on several threads.
+pub fn decompress_bytes_parallel(
+    bytes: &[u8],
+    options: &ParallelOptions,
+) -> Result<Vec<u8>, CompressionError> {
+    match ParallelCoding::decompress(bytes, options) {
+        Ok(decompressed) => Ok(decompressed),
+        Err(e) => Err(CompressionError::cannot_decompress_bytes(e)),
+    }
+}
+
+/// Compresses `text` against a pre-shared dictionary; the output only records
+/// the dictionary id, not the tree.
+pub fn compress_with_dictionary(text: &str, dictionary: &HuffmanDictionary) -> Vec<u8> {
+    dictionary.compress(text)
+}
+
+/// Decompresses a message with whichever of `dictionaries` it was compressed with.
+pub fn decompress_with_dictionary(
+    bytes: &[u8],
+    dictionaries: &[HuffmanDictionary],
+) -> Result<String, CompressionError> {
+    let id = match HuffmanDictionary::message_id(bytes) {
+        Some(id) => id,
+        None => {
+            return Err(CompressionError::cannot_decompress_text(
+                HuffmanError::cannot_deserialize_tree(),
+            ))
         }
-        Err(e) => Err(CompressionError::cannot_decompress_text(e.msg)),
+    };
+
+    match dictionaries.iter().find(|d| d.get_id() == id) {
+        Some(dictionary) => match dictionary.decompress(bytes) {
+            Ok(text) => Ok(text),
+            Err(e) => Err(CompressionError::cannot_decompress_text(e)),
+        },
+        None => Err(CompressionError::cannot_decompress_text(
+            HuffmanError::unknown_dictionary(id),
+        )),
     }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use coding::adaptive_huffman::AdaptiveHuffmanCoding;
 
     #[test]
     fn test_compress() {
@@ -39,16 +146,39 @@ mod tests {
 
         assert!(result.is_ok());
         let result = result.unwrap();
+        let mut expect = vec![
+            0x89, b'H', b'U', b'F', 0x01, 0x03, 0, 0, 0, 0, 0, 0, 0, 0x0f, 0x72, 0x66, 0x31, 0xef,
+            0x00, 0x00, 0x00, 0x00, 0x14,
+        ];
+        expect.extend_from_slice(b"f\x02-\x03n\x03u\x03H\x04a\x04e\x04m\x04t\x04y\x04");
+        expect.extend_from_slice(&[0xa8, 0x1b, 0x6d, 0x1c, 0xa7, 0xe3]);
         assert_eq!(
-            result,
-            "1-29-001f01n1-0001y1t1u001a1H01m1em\u{7}1\u{19}\u{17}1t"
+            CanonicalHuffmanCoding::encode(&text)
+                .unwrap()
+                .serialize()
+                .unwrap(),
+            expect
         );
+
+        // The coded container is longer than the text, so the text is stored as is.
+        let mut expect = vec![
+            0x89, b'H', b'U', b'F', 0x01, 0x09, 0, 0, 0, 0, 0, 0, 0, 0x0f, 0x72, 0x66, 0x31, 0xef,
+            0x00, 0x00, 0x00, 0x00, 0x00,
+        ];
+        expect.extend_from_slice(text.as_bytes());
+        assert_eq!(result, expect);
+        assert_eq!(decompress(&result).unwrap(), text);
     }
 
     #[test]
     fn test_decompress_successful() {
-        let text = "1-29-001f01n1-0001y1t1u001a1H01m1em\u{7}1\u{19}\u{17}1t".to_string();
-        let result = decompress(&text);
+        let mut input = vec![
+            0x89, b'H', b'U', b'F', 0x01, 0x01, 0, 0, 0, 0, 0, 0, 0, 0x0f, 0x72, 0x66, 0x31, 0xef,
+            0x00, 0x00, 0x00, 0x00, 0x1d,
+        ];
+        input.extend_from_slice(b"001f01n1-0001y1t1u001a1H01m1e");
+        input.extend_from_slice(&[0xda, 0x1d, 0x89, 0x92, 0xec, 0x7a]);
+        let result = decompress(&input);
 
         assert!(result.is_ok());
         let result = result.unwrap();
@@ -57,10 +187,87 @@ mod tests {
 
     #[test]
     fn test_decompress_failed() {
-        let text = "1-29-001f01n1-0001y1t1u001a1H01m1em\u{7}1\u{19}\u{17}1t6457".to_string();
-        let result = decompress(&text);
+        let mut input = vec![
+            0x89, b'H', b'U', b'F', 0x01, 0x01, 0, 0, 0, 0, 0, 0, 0, 0x0f, 0x72, 0x66, 0x31, 0xef,
+            0x00, 0x00, 0x00, 0x00, 0x1d,
+        ];
+        input.extend_from_slice(b"001f01n1-0001y1t1u001a1H01m1e");
+        input.extend_from_slice(&[0xda, 0x1d, 0x89, 0x92, 0xec, 0x7a, 0x64, 0x57]);
+        let result = decompress(&input);
 
         assert!(result.is_err());
+
+        // Flipping payload bits still decodes, but no longer matches the checksum.
+        let mut input = compress("Huffman-ft-uyen").unwrap();
+        let last = input.len() - 1;
+        input[last] ^= 0b0000_0011;
+        let result = decompress(&input);
+
+        assert!(result.is_err());
+
+        let result = decompress("Huffman-ft-uyen".as_bytes());
+
+        assert!(result.is_err());
+    }
+
+    #[test]
+    fn test_decompress_malformed_input() {
+        let text = "Welcome to my world!!! Xin chào thế giới ✓✓✓";
+        let seeds = [
+            compress(text).unwrap(),
+            compress_bytes(text.as_bytes()).unwrap(),
+            AdaptiveHuffmanCoding::encode(text)
+                .unwrap()
+                .serialize()
+                .unwrap(),
+            AdaptiveHuffmanCoding::encode_bytes(text.as_bytes())
+                .unwrap()
+                .serialize()
+                .unwrap(),
+        ];
+        let mut state: u64 = 0x2545_f491_4f6c_dd1d;
+        let mut next = || {
+            state ^= state << 13;
+            state ^= state >> 7;
+            state ^= state << 17;
+            state as usize
+        };
+
+        // Corrupted containers must be rejected with an error, never a panic.
+        for round in 0..20_000 {
+            let mut input = seeds[round % seeds.len()].clone();
+
+            for _ in 0..next() % 4 + 1 {
+                let i = next() % input.len();
+                match next() % 3 {
+                    0 => input[i] = next() as u8,
+                    1 => input.truncate(i.max(1)),
+                    _ => input.insert(i, next() as u8),
+                }
+            }
+            let _ = decompress(&input);
+            let _ = decompress_bytes(&input);
+        }
+    }
+
+    #[test]
+    fn test_decompress_with_options() {
+        let text = "Huffman-ft-uyen".repeat(10);
+        let compressed = compress(&text).unwrap();
+        let options = DecompressOptions::new().with_max_output_len(text.len());
+
+        assert_eq!(
+            decompress_with_options(&compressed, &options).unwrap(),
+            text
+        );
+
+        let options = options.with_max_output_len(text.len() - 1);
+        assert!(decompress_with_options(&compressed, &options).is_err());
+
+        let compressed = compress_bytes(&[0; 4096]).unwrap();
+        let options = DecompressOptions::new().with_max_expansion_ratio(4);
+        assert!(decompress_bytes_with_options(&compressed, &options).is_err());
+        assert_eq!(decompress_bytes(&compressed).unwrap(), vec![0; 4096]);
     }
 
     #[test]
@@ -81,6 +288,165 @@ Praesent eros urna, imperdiet sit amet laoreet eu, fringilla sed dui. Aenean pha
         println!("Original length: {}", text.len());
         println!("New length: {}", compressing_text.len());
 
+        assert!(compressing_text.len() < text.len());
         assert_eq!(decompressing_result.unwrap(), text);
     }
+
+    #[test]
+    fn test_decompress_adaptive() {
+        let text = "Huffman-ft-uyen";
+        let bytes = AdaptiveHuffmanCoding::encode(text)
+            .unwrap()
+            .serialize()
+            .unwrap();
+        assert_eq!(decompress(&bytes).unwrap(), text);
+
+        let bytes = AdaptiveHuffmanCoding::encode_bytes(text.as_bytes())
+            .unwrap()
+            .serialize()
+            .unwrap();
+        assert_eq!(decompress_bytes(&bytes).unwrap(), text.as_bytes());
+    }
+
+    #[test]
+    fn test_dictionary_flow() {
+        let english = HuffmanDictionary::train("english", ["Lorem ipsum dolor sit amet"]).unwrap();
+        let digits = HuffmanDictionary::train("digits", ["0123456789"]).unwrap();
+        let dictionaries = [english.clone(), digits.clone()];
+
+        let message = compress_with_dictionary("Huffman-ft-uyen", &english);
+        assert_eq!(
+            decompress_with_dictionary(&message, &dictionaries).unwrap(),
+            "Huffman-ft-uyen"
+        );
+
+        let message = compress_with_dictionary("2024", &digits);
+        assert_eq!(
+            decompress_with_dictionary(&message, &dictionaries).unwrap(),
+            "2024"
+        );
+        assert!(decompress_with_dictionary(&message, &dictionaries[..1]).is_err());
+        assert!(decompress_with_dictionary(b"", &dictionaries).is_err());
+    }
+
+    #[test]
+    fn test_compress_bytes() {
+        let bytes = "Huffman-ft-uyen".as_bytes();
+        let result = compress_bytes(bytes);
+
+        assert!(result.is_ok());
+        let mut expect = compress("Huffman-ft-uyen").unwrap();
+        expect[5] = 0x08;
+        assert_eq!(result.unwrap(), expect);
+    }
+
+    #[test]
+    fn test_decompress_bytes_successful() {
+        let mut input = vec![
+            0x89, b'H', b'U', b'F', 0x01, 0x00, 0, 0, 0, 0, 0, 0, 0, 0x0f, 0x72, 0x66, 0x31, 0xef,
+            0x00, 0x00, 0x00, 0x00, 0x1d,
+        ];
+        input.extend_from_slice(b"001f01n1-0001y1t1u001a1H01m1e");
+        input.extend_from_slice(&[0xda, 0x1d, 0x89, 0x92, 0xec, 0x7a]);
+        let result = decompress_bytes(&input);
+
+        assert!(result.is_ok());
+        assert_eq!(result.unwrap(), "Huffman-ft-uyen".as_bytes());
+    }
+
+    #[test]
+    fn test_decompress_bytes_failed() {
+        let mut input = vec![
+            0x89, b'H', b'U', b'F', 0x01, 0x00, 0, 0, 0, 0, 0, 0, 0, 0x0f, 0x72, 0x66, 0x31, 0xef,
+            0x00, 0x00, 0x00, 0x00, 0x1e,
+        ];
+        input.extend_from_slice(b"001f01n1-0001y1t1u001a1H01m1e");
+        input.extend_from_slice(&[0xda, 0x1d, 0x89, 0x92, 0xec, 0x7a]);
+        let result = decompress_bytes(&input);
+
+        assert!(result.is_err());
+    }
+
+    #[test]
+    fn test_full_flow_bytes() {
+        let bytes: Vec<u8> = (0..4096u32)
+            .map(|i| (i.wrapping_mul(2654435761) >> 24) as u8 & 0x3f)
+            .chain([0x00, 0xff, 0x80, 0xc3, 0x28])
+            .collect();
+        let compressing_result = compress_bytes(&bytes).unwrap();
+        let decompressing_result = decompress_bytes(&compressing_result);
+
+        assert!(compressing_result.len() < bytes.len());
+        assert_eq!(decompressing_result.unwrap(), bytes);
+    }
+
+    #[test]
+    fn test_custom_pipeline() {
+        use crate::prelude::*;
+
+        let text = "Welcome to my world!!!";
+        let mut frequencies = FrequencyTable::new();
+        frequencies.update(&text.chars().collect::<Vec<char>>());
+        let tree = frequencies.into_tree().to_canonical().unwrap();
+
+        let encoding = CanonicalHuffmanCoding::encode(text).unwrap();
+        assert_eq!(
+            encoding.get_huffman_tree().get_code_lengths(),
+            tree.get_code_lengths()
+        );
+
+        let serialized = encoding.serialize().unwrap();
+        let decoded: Result<String, HuffmanError> =
+            HuffmanEncoding::<char>::deserialize(&serialized).and_then(|e| e.decode());
+        assert_eq!(decoded.unwrap(), text);
+        assert_eq!(decompress(&serialized).unwrap(), text);
+    }
+
+    #[test]
+    fn test_error_source_chain() {
+        fn decompress_boxed(bytes: &[u8]) -> Result<String, Box<dyn std::error::Error>> {
+            Ok(decompress(bytes)?)
+        }
+
+        let error = decompress(b"not a container").unwrap_err();
+        assert_eq!(error.kind, CompressionErrorKind::DecompressText);
+        assert_eq!(error.source.kind, HuffmanErrorKind::BadMagic);
+        assert_eq!(error.source.position, Some(ErrorPosition::Byte(0)));
+
+        let boxed = decompress_boxed(b"not a container").unwrap_err();
+        let source = boxed.source().unwrap();
+        assert_eq!(source.to_string(), error.source.to_string());
+        assert!(source.to_string().ends_with("(at byte 0)"));
+        assert_eq!(boxed.to_string(), "Cannot decompress text input");
+        assert!(!source.to_string().starts_with("Error:"));
+
+        match Error::from(error) {
+            Error::Compression(e) => assert_eq!(e.source.kind, HuffmanErrorKind::BadMagic),
+            e => panic!("unexpected error: {}", e),
+        }
+    }
+
+    #[test]
+    fn test_error_kinds_are_distinct() {
+        let kinds = [
+            HuffmanError::invalid_huffman_tree().kind,
+            HuffmanError::not_found_in_tree().kind,
+            HuffmanError::cannot_serialize_tree().kind,
+            HuffmanError::cannot_deserialize_tree().kind,
+            HuffmanError::encoding_error().kind,
+            HuffmanError::decoding_error().kind,
+            HuffmanError::bad_magic().kind,
+            HuffmanError::unsupported_version(2).kind,
+            HuffmanError::invalid_max_code_length(0).kind,
+            HuffmanError::invalid_dictionary_name("").kind,
+            HuffmanError::unknown_dictionary(0).kind,
+            HuffmanError::invalid_declared_length("tree length", 0).kind,
+            HuffmanError::output_limit_exceeded(0).kind,
+            HuffmanError::checksum_mismatch().kind,
+        ];
+
+        for (index, kind) in kinds.iter().enumerate() {
+            assert!(!kinds[index + 1..].contains(kind), "{:?} is shared", kind);
+        }
+    }
 }
@@ -0,0 +1,450 @@
+use std::{collections::HashMap, hash::Hash};
+
+use crate::errors::huffman_error::HuffmanError;
+use crate::utils::bit_io::{BitReader, BitWriter, Code};
+
+/// Symbols the adaptive coder can send literally the first time they appear.
+pub trait AdaptiveSymbol: Copy + Eq + Hash {
+    /// Width of the literal form.
+    const BITS: usize;
+
+    fn to_bits(self) -> u64;
+    fn from_bits(bits: u64) -> Option<Self>;
+}
+
+impl AdaptiveSymbol for u8 {
+    const BITS: usize = 8;
+
+    fn to_bits(self) -> u64 {
+        self as u64
+    }
+
+    fn from_bits(bits: u64) -> Option<Self> {
+        u8::try_from(bits).ok()
+    }
+}
+
+impl AdaptiveSymbol for char {
+    const BITS: usize = 21;
+
+    fn to_bits(self) -> u64 {
+        self as u64
+    }
+
+    fn from_bits(bits: u64) -> Option<Self> {
+        u32::try_from(bits).ok().and_then(char::from_u32)
+    }
+}
+
+#[derive(Clone, Debug)]
+struct AdaptiveNode<T> {
+    weight: usize,
+    parent: Option<usize>,
+    left: Option<usize>,
+    right: Option<usize>,
+    symbol: Option<T>,
+    rank: usize,
+}
+
+impl<T> AdaptiveNode<T> {
+    fn is_leaf(&self) -> bool {
+        self.left.is_none()
+    }
+}
+
+/// Huffman tree updated after every symbol with Vitter's algorithm, so encoder
+/// and decoder build the same tree without it ever being transmitted.
+///
+/// Nodes are kept in `order` by decreasing implicit number: the root comes
+/// first and the 0-node (NYT, "not yet transmitted") last. Within equal
+/// weights internal nodes come before leaves, which is the invariant that
+/// keeps the tree of minimal height.
+#[derive(Clone, Debug)]
+pub struct AdaptiveHuffmanTree<T> {
+    nodes: Vec<AdaptiveNode<T>>,
+    order: Vec<usize>,
+    leaves: HashMap<T, usize>,
+    nyt: usize,
+}
+
+impl<T> Default for AdaptiveHuffmanTree<T>
+where
+    T: AdaptiveSymbol,
+{
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<T> AdaptiveHuffmanTree<T>
+where
+    T: AdaptiveSymbol,
+{
+    const ROOT: usize = 0;
+
+    pub fn new() -> Self {
+        Self {
+            nodes: vec![AdaptiveNode {
+                weight: 0,
+                parent: None,
+                left: None,
+                right: None,
+                symbol: None,
+                rank: 0,
+            }],
+            order: vec![Self::ROOT],
+            leaves: HashMap::new(),
+            nyt: Self::ROOT,
+        }
+    }
+
+    /// Writes the code for `symbol`, or the NYT code followed by the literal
+    /// symbol if it has not been seen yet, then updates the tree.
+    pub fn encode(&mut self, symbol: T, writer: &mut BitWriter) -> Result<(), HuffmanError> {
+        match self.leaves.get(&symbol) {
+            Some(&leaf) => match self.code_of(leaf) {
+                Some(code) => writer.write_code(&code),
+                None => return Err(HuffmanError::encoding_error()),
+            },
+            None => {
+                match self.code_of(self.nyt) {
+                    Some(code) => writer.write_code(&code),
+                    None => return Err(HuffmanError::encoding_error()),
+                }
+                writer.write_bits(symbol.to_bits(), T::BITS);
+            }
+        }
+
+        self.update(symbol);
+        Ok(())
+    }
+
+    /// Reads one symbol and updates the tree the same way `encode` did.
+    pub fn decode(&mut self, reader: &mut BitReader<'_>) -> Result<T, HuffmanError> {
+        let mut node = Self::ROOT;
+
+        while !self.nodes[node].is_leaf() {
+            node = match (
+                reader.read_bit(),
+                self.nodes[node].left,
+                self.nodes[node].right,
+            ) {
+                (Some(false), Some(left), _) => left,
+                (Some(true), _, Some(right)) => right,
+                _ => return Err(HuffmanError::decoding_error()),
+            };
+        }
+
+        let symbol = if node == self.nyt {
+            if reader.remaining() < T::BITS {
+                return Err(HuffmanError::decoding_error());
+            }
+            let bits = reader.peek(T::BITS);
+            reader.consume(T::BITS);
+
+            match T::from_bits(bits) {
+                Some(symbol) if !self.leaves.contains_key(&symbol) => symbol,
+                _ => return Err(HuffmanError::decoding_error()),
+            }
+        } else {
+            match self.nodes[node].symbol {
+                Some(symbol) => symbol,
+                None => return Err(HuffmanError::invalid_huffman_tree()),
+            }
+        };
+
+        self.update(symbol);
+        Ok(symbol)
+    }
+
+    /// Current code of the leaf for `symbol`, if it has been seen.
+    pub fn get_code(&self, symbol: &T) -> Option<Code> {
+        self.leaves.get(symbol).and_then(|&leaf| self.code_of(leaf))
+    }
+
+    /// Total weight, which is the number of symbols seen so far.
+    pub fn get_weight(&self) -> usize {
+        self.nodes[Self::ROOT].weight
+    }
+
+    fn code_of(&self, mut node: usize) -> Option<Code> {
+        let mut code = Code::default();
+
+        while let Some(parent) = self.nodes[node].parent {
+            if code.len == Code::MAX_LEN {
+                return None;
+            }
+            if self.nodes[parent].right == Some(node) {
+                code.bits |= 1 << code.len;
+            }
+            code.len += 1;
+            node = parent;
+        }
+
+        Some(code)
+    }
+
+    /// Vitter's `Update` procedure.
+    fn update(&mut self, symbol: T) {
+        let mut leaf_to_increment: Option<usize> = None;
+        let mut q = match self.leaves.get(&symbol) {
+            Some(&leaf) => {
+                let leader = self.leader_of(leaf);
+                if leader != leaf {
+                    self.interchange(leaf, leader);
+                }
+                if self.sibling_of(leaf) == Some(self.nyt) {
+                    leaf_to_increment = Some(leaf);
+                    self.nodes[leaf].parent
+                } else {
+                    Some(leaf)
+                }
+            }
+            None => {
+                let new_leaf = self.split_nyt(symbol);
+                leaf_to_increment = Some(new_leaf);
+                self.nodes[new_leaf].parent
+            }
+        };
+
+        while let Some(node) = q {
+            q = self.slide_and_increment(node);
+        }
+        if let Some(leaf) = leaf_to_increment {
+            self.slide_and_increment(leaf);
+        }
+    }
+
+    /// Turns the NYT leaf into an internal node whose left child is the new
+    /// NYT and right child the leaf for `symbol`, returning that leaf.
+    fn split_nyt(&mut self, symbol: T) -> usize {
+        let parent = self.nyt;
+        let new_leaf = self.nodes.len();
+        let new_nyt = new_leaf + 1;
+
+        for (node, leaf_symbol) in [(new_leaf, Some(symbol)), (new_nyt, None)] {
+            self.nodes.push(AdaptiveNode {
+                weight: 0,
+                parent: Some(parent),
+                left: None,
+                right: None,
+                symbol: leaf_symbol,
+                rank: self.order.len(),
+            });
+            self.order.push(node);
+        }
+
+        self.nodes[parent].left = Some(new_nyt);
+        self.nodes[parent].right = Some(new_leaf);
+        self.leaves.insert(symbol, new_leaf);
+        self.nyt = new_nyt;
+        new_leaf
+    }
+
+    /// Vitter's `SlideAndIncrement`: moves `node` ahead of the block that
+    /// must precede it once its weight grows, increments it and returns the
+    /// next node to update.
+    fn slide_and_increment(&mut self, node: usize) -> Option<usize> {
+        let weight = self.nodes[node].weight;
+        let is_leaf = self.nodes[node].is_leaf();
+        let former_parent = self.nodes[node].parent;
+
+        loop {
+            let rank = self.nodes[node].rank;
+            if rank == 0 {
+                break;
+            }
+
+            let previous = &self.nodes[self.order[rank - 1]];
+            let in_next_block = if is_leaf {
+                !previous.is_leaf() && previous.weight == weight
+            } else {
+                previous.is_leaf() && previous.weight == weight + 1
+            };
+
+            if !in_next_block {
+                break;
+            }
+            self.interchange(node, self.order[rank - 1]);
+        }
+
+        self.nodes[node].weight += 1;
+
+        if is_leaf {
+            self.nodes[node].parent
+        } else {
+            former_parent
+        }
+    }
+
+    /// Highest ranked node with the same weight and kind as `node`.
+    fn leader_of(&self, node: usize) -> usize {
+        let weight = self.nodes[node].weight;
+        let is_leaf = self.nodes[node].is_leaf();
+        let mut rank = self.nodes[node].rank;
+
+        while rank > 0 {
+            let previous = &self.nodes[self.order[rank - 1]];
+            if previous.weight != weight || previous.is_leaf() != is_leaf {
+                break;
+            }
+            rank -= 1;
+        }
+
+        self.order[rank]
+    }
+
+    fn sibling_of(&self, node: usize) -> Option<usize> {
+        self.nodes[node].parent.and_then(|parent| {
+            if self.nodes[parent].left == Some(node) {
+                self.nodes[parent].right
+            } else {
+                self.nodes[parent].left
+            }
+        })
+    }
+
+    /// Swaps the positions of two nodes, neither an ancestor of the other,
+    /// in both the tree and the ordering.
+    fn interchange(&mut self, a: usize, b: usize) {
+        let (parent_a, parent_b) = (self.nodes[a].parent, self.nodes[b].parent);
+
+        if let (Some(parent_a), Some(parent_b)) = (parent_a, parent_b) {
+            if parent_a == parent_b {
+                let parent = &mut self.nodes[parent_a];
+                std::mem::swap(&mut parent.left, &mut parent.right);
+            } else {
+                self.replace_child(parent_a, a, b);
+                self.replace_child(parent_b, b, a);
+                self.nodes[a].parent = Some(parent_b);
+                self.nodes[b].parent = Some(parent_a);
+            }
+        }
+
+        let (rank_a, rank_b) = (self.nodes[a].rank, self.nodes[b].rank);
+        self.order.swap(rank_a, rank_b);
+        self.nodes[a].rank = rank_b;
+        self.nodes[b].rank = rank_a;
+    }
+
+    fn replace_child(&mut self, parent: usize, old: usize, new: usize) {
+        let parent = &mut self.nodes[parent];
+
+        if parent.left == Some(old) {
+            parent.left = Some(new);
+        } else {
+            parent.right = Some(new);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Checks the sibling property and Vitter's ordering invariant.
+    fn assert_invariants<T: AdaptiveSymbol>(tree: &AdaptiveHuffmanTree<T>) {
+        for (rank, &node) in tree.order.iter().enumerate() {
+            let current = &tree.nodes[node];
+            assert_eq!(current.rank, rank);
+
+            if let (Some(left), Some(right)) = (current.left, current.right) {
+                assert_eq!(
+                    current.weight,
+                    tree.nodes[left].weight + tree.nodes[right].weight
+                );
+                assert!(tree.nodes[left].rank > rank && tree.nodes[right].rank > rank);
+            }
+            if rank > 0 {
+                let previous = &tree.nodes[tree.order[rank - 1]];
+                assert!(previous.weight >= current.weight);
+                if previous.weight == current.weight {
+                    assert!(!previous.is_leaf() || current.is_leaf());
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_update_keeps_invariants() {
+        let mut tree = AdaptiveHuffmanTree::<u8>::new();
+        let mut writer = BitWriter::new();
+        let mut state: u32 = 12345;
+
+        for i in 0..3000 {
+            state = state.wrapping_mul(1_103_515_245).wrapping_add(12_345);
+            let symbol = match i % 3 {
+                0 => (state >> 16) as u8 % 4,
+                1 => (state >> 16) as u8 % 40,
+                _ => (state >> 16) as u8,
+            };
+            tree.encode(symbol, &mut writer).unwrap();
+            assert_invariants(&tree);
+        }
+        assert_eq!(tree.get_weight(), 3000);
+    }
+
+    #[test]
+    fn test_encode_decode() {
+        let text: Vec<char> = "abracadabra, Xin chào thế giới 🦀🦀".chars().collect();
+        let mut encoder = AdaptiveHuffmanTree::<char>::new();
+        let mut writer = BitWriter::new();
+
+        text.iter()
+            .for_each(|&c| encoder.encode(c, &mut writer).unwrap());
+
+        let bits = writer.finish();
+        let mut reader = bits.reader();
+        let mut decoder = AdaptiveHuffmanTree::<char>::new();
+        let mut decoded: Vec<char> = Vec::new();
+        while reader.remaining() > 0 {
+            decoded.push(decoder.decode(&mut reader).unwrap());
+        }
+
+        assert_eq!(decoded, text);
+        assert_eq!(encoder.get_code(&'a'), decoder.get_code(&'a'));
+    }
+
+    #[test]
+    fn test_codes() {
+        let mut tree = AdaptiveHuffmanTree::<u8>::new();
+        let mut writer = BitWriter::new();
+
+        // First symbol: empty NYT code followed by the literal.
+        tree.encode(b'a', &mut writer).unwrap();
+        assert_eq!(writer.len(), 8);
+        assert_eq!(tree.get_code(&b'a'), Some(Code::new(0b1, 1)));
+
+        // A known symbol costs only its code.
+        tree.encode(b'a', &mut writer).unwrap();
+        assert_eq!(writer.len(), 9);
+
+        // A new one costs the NYT code plus the literal.
+        tree.encode(b'b', &mut writer).unwrap();
+        assert_eq!(writer.len(), 9 + 1 + 8);
+        assert_eq!(tree.get_code(&b'a'), Some(Code::new(0b1, 1)));
+        assert_eq!(tree.get_code(&b'b').map(|code| code.len), Some(2));
+        assert_eq!(tree.get_code(&b'c'), None);
+    }
+
+    #[test]
+    fn test_decode_failed() {
+        let mut decoder = AdaptiveHuffmanTree::<u8>::new();
+        let bits: crate::utils::bit_io::PackedBits = [true, false, true].into_iter().collect();
+
+        assert_eq!(
+            decoder.decode(&mut bits.reader()),
+            Err(HuffmanError::decoding_error())
+        );
+
+        let mut decoder = AdaptiveHuffmanTree::<char>::new();
+        let mut writer = BitWriter::new();
+        writer.write_bits(0x1f_ffff, 21);
+        let bits = writer.finish();
+
+        assert_eq!(
+            decoder.decode(&mut bits.reader()),
+            Err(HuffmanError::decoding_error())
+        );
+    }
+}
@@ -0,0 +1,410 @@
+use std::{collections::BTreeMap, hash::Hash};
+
+use crate::errors::huffman_error::HuffmanError;
+use crate::utils::bit_io::{BitReader, Code, PackedBits};
+
+use super::huffman_tree::{HuffmanNode, HuffmanTree};
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Entry<T> {
+    /// No code starts with these bits.
+    Invalid,
+    /// A complete code of `length` bits, counted from the start of this table.
+    Symbol(T, u8),
+    /// The code is longer than this table: continue in the table at `offset`,
+    /// which is indexed by the next `width` bits.
+    Link(usize, u8),
+}
+
+/// Lookup tables for decoding many bits at a time instead of walking the tree
+/// one bit per step.
+///
+/// The primary table is indexed by the next `PRIMARY_BITS` bits of input and
+/// resolves every code up to that length in a single lookup. Longer codes
+/// share their first bits with a `Link` entry pointing at a secondary table
+/// for the remaining bits, which is split the same way again only for codes
+/// longer than `PRIMARY_BITS + SECONDARY_BITS`.
+#[derive(Clone, Debug)]
+pub struct DecodingTable<T> {
+    entries: Vec<Entry<T>>,
+    primary_width: u8,
+    /// Length of the longest code, so the fewest bits a symbol can need.
+    max_length: usize,
+}
+
+impl<T> DecodingTable<T>
+where
+    T: Eq + Hash + Copy + Ord + ToString,
+    HuffmanNode<T>: ToString,
+{
+    pub const PRIMARY_BITS: usize = 10;
+    pub const SECONDARY_BITS: usize = 6;
+
+    pub fn from_tree(huffman_tree: &HuffmanTree<T>) -> Result<Self, HuffmanError> {
+        match huffman_tree.get_encoding_map() {
+            Ok(encoding_map) => {
+                let codes: Vec<(T, Code)> = encoding_map.into_iter().collect();
+                let mut entries: Vec<Entry<T>> = Vec::new();
+                let primary_width = Self::build_level(&codes, Self::PRIMARY_BITS, &mut entries);
+                let max_length = codes.iter().map(|(_, code)| code.len).max().unwrap_or(1);
+
+                Ok(Self {
+                    entries,
+                    primary_width,
+                    max_length,
+                })
+            }
+            Err(e) => Err(e),
+        }
+    }
+
+    /// Decodes every symbol in `encoded_bits`, which must end exactly on a code boundary.
+    pub fn decode(&self, encoded_bits: &PackedBits) -> Result<Vec<T>, HuffmanError> {
+        let mut decoded: Vec<T> = Vec::new();
+
+        match self.decode_each(encoded_bits, |symbol| {
+            decoded.push(symbol);
+            Ok(())
+        }) {
+            Ok(()) => Ok(decoded),
+            Err(e) => Err(e),
+        }
+    }
+
+    /// Passes every decoded symbol to `on_symbol` as soon as it is read, and
+    /// stops at the first error it returns. Errors carry the bit offset of the
+    /// code being decoded.
+    pub fn decode_each<F>(
+        &self,
+        encoded_bits: &PackedBits,
+        mut on_symbol: F,
+    ) -> Result<(), HuffmanError>
+    where
+        F: FnMut(T) -> Result<(), HuffmanError>,
+    {
+        let mut reader = encoded_bits.reader();
+
+        while reader.remaining() > 0 {
+            let start = reader.position();
+
+            match self.decode_next(&mut reader) {
+                Ok(symbol) => match on_symbol(symbol) {
+                    Ok(()) => {}
+                    Err(e) => return Err(e.at_bit(start)),
+                },
+                Err(e) => return Err(e),
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Decodes symbols taken in turn from each of `streams`, so the i-th
+    /// symbol is read from stream `i % streams.len()`, and passes them to
+    /// `on_round` one round (one symbol per stream) at a time. Errors it
+    /// returns carry the bit offset at which the round began in the first
+    /// stream. Decoding stops at the first empty stream, and every stream
+    /// after it must be empty as well.
+    ///
+    /// While every stream still holds a code of the longest length, rounds
+    /// are decoded with one bounds check per batch of rounds instead of one
+    /// per symbol, so the lookups of different streams do not depend on each
+    /// other and can overlap. Only the last few rounds take the checked path.
+    pub fn decode_interleaved<F>(
+        &self,
+        streams: &mut [BitReader<'_>],
+        mut on_round: F,
+    ) -> Result<(), HuffmanError>
+    where
+        F: FnMut(&[T]) -> Result<(), HuffmanError>,
+    {
+        let mut round: Vec<T> = Vec::with_capacity(streams.len());
+
+        if self.max_length <= BitReader::MAX_PEEK {
+            loop {
+                let safe_rounds = match streams.iter().map(BitReader::remaining).min() {
+                    Some(remaining) => remaining / self.max_length,
+                    None => 0,
+                };
+                if safe_rounds == 0 {
+                    break;
+                }
+
+                for _ in 0..safe_rounds {
+                    let start = streams[0].position();
+
+                    round.clear();
+                    round.extend(
+                        streams
+                            .iter_mut()
+                            .map_while(|reader| self.decode_unchecked(reader)),
+                    );
+                    if round.len() < streams.len() {
+                        let position = streams[round.len()].position();
+                        return Err(HuffmanError::decoding_error().at_bit(position));
+                    }
+                    if let Err(e) = on_round(&round) {
+                        return Err(e.at_bit(start));
+                    }
+                }
+            }
+        }
+
+        loop {
+            let start = streams.first().map_or(0, BitReader::position);
+
+            round.clear();
+            for reader in streams.iter_mut() {
+                if reader.remaining() == 0 {
+                    break;
+                }
+                match self.decode_next(reader) {
+                    Ok(symbol) => round.push(symbol),
+                    Err(e) => return Err(e),
+                }
+            }
+            if !round.is_empty() {
+                if let Err(e) = on_round(&round) {
+                    return Err(e.at_bit(start));
+                }
+            }
+            if round.len() < streams.len() {
+                return match streams[round.len()..]
+                    .iter()
+                    .find(|rest| rest.remaining() > 0)
+                {
+                    Some(rest) => Err(HuffmanError::decoding_error().at_bit(rest.position())),
+                    None => Ok(()),
+                };
+            }
+        }
+    }
+
+    /// Reads one code from a reader known to hold at least `max_length` more
+    /// bits, peeking them all at once and consuming only the code. Returns
+    /// `None`, consuming nothing, if no code starts with these bits.
+    fn decode_unchecked(&self, reader: &mut BitReader<'_>) -> Option<T> {
+        let bits = reader.peek(self.max_length);
+        let mut used: usize = 0;
+        let mut offset: usize = 0;
+        let mut width = self.primary_width as usize;
+
+        loop {
+            let shift = self.max_length - used - width;
+            let index = (bits >> shift) as usize & ((1 << width) - 1);
+
+            match self.entries[offset + index] {
+                Entry::Symbol(symbol, length) => {
+                    reader.consume(used + length as usize);
+                    return Some(symbol);
+                }
+                Entry::Link(next_offset, next_width) => {
+                    used += width;
+                    offset = next_offset;
+                    width = next_width as usize;
+                }
+                Entry::Invalid => return None,
+            }
+        }
+    }
+
+    /// Reads one code, which must end before the input does. Errors carry
+    /// the bit offset of the code.
+    pub fn decode_next(&self, reader: &mut BitReader<'_>) -> Result<T, HuffmanError> {
+        let start = reader.position();
+        let mut offset: usize = 0;
+        let mut width = self.primary_width as usize;
+
+        loop {
+            let index = reader.peek(width) as usize;
+
+            match self.entries[offset + index] {
+                Entry::Symbol(symbol, length) if length as usize <= reader.remaining() => {
+                    reader.consume(length as usize);
+                    return Ok(symbol);
+                }
+                Entry::Link(next_offset, next_width) if width < reader.remaining() => {
+                    reader.consume(width);
+                    offset = next_offset;
+                    width = next_width as usize;
+                }
+                _ => return Err(HuffmanError::decoding_error().at_bit(start)),
+            }
+        }
+    }
+
+    /// Fills a table of `2^width` entries for `codes`, whose already consumed
+    /// prefix has been stripped, and returns the width actually used.
+    fn build_level(codes: &[(T, Code)], max_width: usize, entries: &mut Vec<Entry<T>>) -> u8 {
+        let longest = codes.iter().map(|(_, code)| code.len).max().unwrap_or(1);
+        let width = longest.clamp(1, max_width);
+        let start = entries.len();
+        let mut long_codes: BTreeMap<usize, Vec<(T, Code)>> = BTreeMap::new();
+
+        entries.resize(start + (1 << width), Entry::Invalid);
+
+        for &(symbol, code) in codes {
+            if code.len <= width {
+                let fill = width - code.len;
+                let first = (code.bits as usize) << fill;
+
+                entries[start + first..start + first + (1 << fill)]
+                    .fill(Entry::Symbol(symbol, code.len as u8));
+            } else {
+                long_codes
+                    .entry(code.prefix(width).bits as usize)
+                    .or_default()
+                    .push((symbol, code.suffix(width)));
+            }
+        }
+
+        for (prefix, group) in long_codes {
+            let offset = entries.len();
+            let next_width = Self::build_level(&group, Self::SECONDARY_BITS, entries);
+
+            entries[start + prefix] = Entry::Link(offset, next_width);
+        }
+
+        width as u8
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::utils::bit_io::BitWriter;
+
+    fn bits(bools: &[bool]) -> PackedBits {
+        bools.iter().copied().collect()
+    }
+
+    #[test]
+    fn test_decode() {
+        let values: Vec<char> = "Welcome to my world!!!".chars().collect();
+        let tree = HuffmanTree::from(&values);
+        let table = DecodingTable::from_tree(&tree).unwrap();
+        let encoding_map = tree.get_encoding_map().unwrap();
+        let mut writer = BitWriter::new();
+        values
+            .iter()
+            .for_each(|v| writer.write_code(&encoding_map[v]));
+        let encoded_bits = writer.finish();
+
+        assert_eq!(table.decode(&encoded_bits).unwrap(), values);
+        assert_eq!(table.decode(&PackedBits::default()).unwrap(), vec![]);
+    }
+
+    #[test]
+    fn test_decode_long_codes() {
+        // Fibonacci frequencies give a maximally skewed tree with codes of up to 23 bits.
+        let mut values: Vec<u8> = Vec::new();
+        let (mut a, mut b) = (1usize, 1usize);
+        for symbol in 0..24u8 {
+            values.extend(std::iter::repeat_n(symbol, a));
+            (a, b) = (b, a + b);
+        }
+
+        let tree = HuffmanTree::from(&values);
+        let table = DecodingTable::from_tree(&tree).unwrap();
+        let encoding_map = tree.get_encoding_map().unwrap();
+        let longest = encoding_map.values().map(|code| code.len).max().unwrap();
+        let mut writer = BitWriter::new();
+        values
+            .iter()
+            .for_each(|v| writer.write_code(&encoding_map[v]));
+        let encoded_bits = writer.finish();
+
+        assert!(longest > DecodingTable::<u8>::PRIMARY_BITS + DecodingTable::<u8>::SECONDARY_BITS);
+        assert_eq!(table.decode(&encoded_bits).unwrap(), values);
+    }
+
+    #[test]
+    fn test_decode_interleaved() {
+        // Long codes go through the secondary tables on the unchecked path.
+        let mut values: Vec<u8> = Vec::new();
+        let (mut a, mut b) = (1usize, 1usize);
+        for symbol in 0..24u8 {
+            values.extend(std::iter::repeat_n(symbol, a));
+            (a, b) = (b, a + b);
+        }
+        let third = values.len() / 3;
+        values.rotate_left(third);
+
+        let tree = HuffmanTree::from(&values);
+        let table = DecodingTable::from_tree(&tree).unwrap();
+        let encoding_map = tree.get_encoding_map().unwrap();
+        let mut writers: Vec<BitWriter> = (0..3).map(|_| BitWriter::new()).collect();
+        values
+            .iter()
+            .enumerate()
+            .for_each(|(index, v)| writers[index % 3].write_code(&encoding_map[v]));
+        let streams: Vec<PackedBits> = writers.into_iter().map(BitWriter::finish).collect();
+        let mut readers: Vec<BitReader> = streams.iter().map(PackedBits::reader).collect();
+        let mut decoded: Vec<u8> = Vec::new();
+
+        table
+            .decode_interleaved(&mut readers, |round| {
+                decoded.extend_from_slice(round);
+                Ok(())
+            })
+            .unwrap();
+        assert_eq!(decoded, values);
+
+        // A stream left over after an empty one is an error.
+        let empty = PackedBits::default();
+        let mut readers = vec![streams[0].reader(), empty.reader(), streams[1].reader()];
+        assert_eq!(
+            table.decode_interleaved(&mut readers, |_| Ok(())),
+            Err(HuffmanError::decoding_error().at_bit(0))
+        );
+    }
+
+    #[test]
+    fn test_decode_interleaved_failed() {
+        // "a" is the only code, so a set bit starts no code at all.
+        let tree = HuffmanTree::from("aaaa".as_bytes());
+        let table = DecodingTable::from_tree(&tree).unwrap();
+        let streams = [bits(&[false; 8]), bits(&[false, false, true, false])];
+        let mut readers: Vec<BitReader> = streams.iter().map(PackedBits::reader).collect();
+        let mut rounds = 0;
+
+        assert_eq!(
+            table.decode_interleaved(&mut readers, |_| {
+                rounds += 1;
+                Ok(())
+            }),
+            Err(HuffmanError::decoding_error().at_bit(2))
+        );
+        assert_eq!(rounds, 2);
+
+        let mut readers: Vec<BitReader> = streams.iter().map(PackedBits::reader).collect();
+        readers[0].consume(4);
+        assert_eq!(
+            table.decode_interleaved(&mut readers, |_| Err(HuffmanError::encoding_error())),
+            Err(HuffmanError::encoding_error().at_bit(4))
+        );
+    }
+
+    #[test]
+    fn test_decode_failed() {
+        let tree = HuffmanTree::from("Welcome to my world!!!".as_bytes());
+        let table = DecodingTable::from_tree(&tree).unwrap();
+
+        assert_eq!(
+            table.decode(&bits(&[true])),
+            Err(HuffmanError::decoding_error().at_bit(0))
+        );
+
+        let tree = HuffmanTree::from("aaaa".as_bytes());
+        let table = DecodingTable::from_tree(&tree).unwrap();
+
+        assert_eq!(table.decode(&bits(&[false, false])).unwrap(), b"aa");
+        assert_eq!(
+            table.decode(&bits(&[false, true])),
+            Err(HuffmanError::decoding_error().at_bit(1))
+        );
+
+        let tree = HuffmanTree::<u8>::from(&[]);
+        assert!(DecodingTable::from_tree(&tree).is_err());
+    }
+}
@@ -0,0 +1,172 @@
+use std::{
+    collections::{btree_map, BTreeMap},
+    hash::Hash,
+};
+
+use crate::errors::huffman_error::HuffmanError;
+
+use super::huffman_tree::{HuffmanNode, HuffmanTree};
+
+/// Symbol counts that can be accumulated chunk by chunk, merged with tables
+/// counted elsewhere (e.g. on other threads) and then turned into a tree.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct FrequencyTable<T> {
+    counts: BTreeMap<T, usize>,
+}
+
+impl<T> Default for FrequencyTable<T> {
+    fn default() -> Self {
+        Self {
+            counts: BTreeMap::new(),
+        }
+    }
+}
+
+impl<T> FrequencyTable<T>
+where
+    T: Eq + Hash + Copy + Ord + ToString,
+    HuffmanNode<T>: ToString,
+{
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Counts every symbol of `values`.
+    pub fn update(&mut self, values: &[T]) {
+        values.iter().for_each(|&v| self.add(v, 1));
+    }
+
+    /// Adds `count` occurrences of `symbol`.
+    pub fn add(&mut self, symbol: T, count: usize) {
+        let entry = self.counts.entry(symbol).or_insert(0);
+        *entry = entry.saturating_add(count);
+    }
+
+    /// Adds every count of `other` to this table.
+    pub fn merge(&mut self, other: &FrequencyTable<T>) {
+        other
+            .iter()
+            .for_each(|(symbol, count)| self.add(symbol, count));
+    }
+
+    pub fn get(&self, symbol: &T) -> usize {
+        self.counts.get(symbol).copied().unwrap_or(0)
+    }
+
+    /// Number of distinct symbols.
+    pub fn len(&self) -> usize {
+        self.counts.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.counts.is_empty()
+    }
+
+    /// Sum of all counts.
+    pub fn total(&self) -> usize {
+        self.counts
+            .values()
+            .fold(0usize, |total, &count| total.saturating_add(count))
+    }
+
+    /// Symbols and their counts, ordered by symbol.
+    pub fn iter(&self) -> impl Iterator<Item = (T, usize)> + '_ {
+        self.counts.iter().map(|(&symbol, &count)| (symbol, count))
+    }
+
+    pub fn into_tree(self) -> HuffmanTree<T> {
+        HuffmanTree::from_frequencies(self)
+    }
+
+    pub fn into_tree_with_max_length(
+        self,
+        max_length: usize,
+    ) -> Result<HuffmanTree<T>, HuffmanError> {
+        HuffmanTree::from_frequencies_with_max_length(self, max_length)
+    }
+}
+
+impl<T> FromIterator<(T, usize)> for FrequencyTable<T>
+where
+    T: Eq + Hash + Copy + Ord + ToString,
+    HuffmanNode<T>: ToString,
+{
+    fn from_iter<I: IntoIterator<Item = (T, usize)>>(iter: I) -> Self {
+        let mut table = Self::new();
+        iter.into_iter()
+            .for_each(|(symbol, count)| table.add(symbol, count));
+
+        table
+    }
+}
+
+impl<T> IntoIterator for FrequencyTable<T> {
+    type Item = (T, usize);
+    type IntoIter = btree_map::IntoIter<T, usize>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.counts.into_iter()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_update_and_merge() {
+        let mut first = FrequencyTable::new();
+        first.update("Welcome to".as_bytes());
+        first.update(" my world!!!".as_bytes());
+
+        let mut second: FrequencyTable<u8> = [(b'!', 2), (b'z', 5)].into_iter().collect();
+        second.merge(&first);
+
+        assert_eq!(first.get(&b'o'), 3);
+        assert_eq!(first.get(&b'z'), 0);
+        assert_eq!(first.len(), 13);
+        assert_eq!(first.total(), 22);
+        assert_eq!(second.get(&b'!'), 5);
+        assert_eq!(second.get(&b'z'), 5);
+        assert_eq!(second.total(), 29);
+        assert!(FrequencyTable::<u8>::new().is_empty());
+    }
+
+    #[test]
+    fn test_into_tree() {
+        let text = "Welcome to my world!!!";
+        let values: Vec<char> = text.chars().collect();
+        let mut table = FrequencyTable::new();
+        values.chunks(5).for_each(|chunk| table.update(chunk));
+
+        assert_eq!(
+            table.clone().into_tree().serialize().unwrap(),
+            HuffmanTree::from(&values).serialize().unwrap()
+        );
+        assert_eq!(
+            table
+                .into_tree_with_max_length(HuffmanTree::<char>::DEFAULT_MAX_CODE_LENGTH)
+                .unwrap()
+                .serialize()
+                .unwrap(),
+            HuffmanTree::from(&values).serialize().unwrap()
+        );
+    }
+
+    #[test]
+    fn test_into_tree_huge_counts() {
+        let table: FrequencyTable<u8> = [(1, usize::MAX), (2, usize::MAX), (3, usize::MAX - 1)]
+            .into_iter()
+            .collect();
+
+        for tree in [
+            table.clone().into_tree(),
+            table.into_tree_with_max_length(32).unwrap(),
+        ] {
+            let mut lengths = tree.get_code_lengths().unwrap();
+            lengths.sort();
+            assert_eq!(lengths.len(), 3);
+            assert!(lengths.iter().all(|&(_, length)| (1..=2).contains(&length)));
+        }
+    }
+}
@@ -1,14 +1,15 @@
 use std::{
-    collections::{BTreeMap, HashMap},
-    fmt::Display,
+    cmp::{Ordering, Reverse},
+    collections::{BTreeMap, BinaryHeap, HashMap, HashSet, VecDeque},
+    fmt::{self, Display},
     hash::Hash,
-    slice::Iter,
-    usize,
 };
 
 use crate::errors::huffman_error::HuffmanError;
+use crate::utils::bit_io::{BitReader, Code};
 
-use super::node::Node;
+use super::node::{Arena, NodeId};
+use super::symbol::Symbol;
 #[derive(Clone)]
 pub struct HuffmanNode<T> {
     freq: usize,
@@ -20,10 +21,7 @@ where
     T: ToString,
 {
     pub fn new(freq: usize, value: Option<T>) -> Self {
-        Self {
-            freq: freq,
-            value: value,
-        }
+        Self { freq, value }
     }
 
     pub fn get_value_as_string(&self) -> String {
@@ -34,176 +32,550 @@ where
     }
 }
 
-impl<T> ToString for HuffmanNode<T>
+impl<T> Display for HuffmanNode<T>
 where
     T: Display,
 {
-    fn to_string(&self) -> String {
-        let result = match &self.value {
-            Some(c) => format!("C: {} - F:{}", c, self.freq),
-            None => format!("None - F:{}", self.freq),
-        };
-        result
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match &self.value {
+            Some(c) => write!(f, "C: {} - F:{}", c, self.freq),
+            None => write!(f, "None - F:{}", self.freq),
+        }
     }
 }
 
+/// Builds and holds a Huffman code tree.
+///
+/// Construction is deterministic and part of the format guarantee: the two
+/// lightest subtrees are merged first, ties going to the shallower subtree
+/// and then to the subtree holding the smallest symbol. The first subtree
+/// taken becomes the left (`0`) child. The same frequencies therefore always
+/// give the same tree, whatever order they were counted in.
+///
+/// Nodes live in an `Arena` and link to their children and parent by index,
+/// so a tree is a single allocation and codes can be read from the leaves up.
 #[derive(Clone)]
 pub struct HuffmanTree<T> {
-    root: Option<Node<HuffmanNode<T>>>,
+    nodes: Arena<HuffmanNode<T>>,
+    root: Option<NodeId>,
+}
+
+/// A subtree waiting to be merged, ordered by the tie-breaking rule of
+/// `HuffmanTree`: frequency, then height, then smallest symbol.
+struct Pending<T> {
+    freq: usize,
+    height: usize,
+    symbol: T,
+    node: NodeId,
+}
+
+/// One node of a tree serialized in preorder.
+enum Token<T> {
+    Parent,
+    Leaf(T),
+    /// The input ended where a node was expected.
+    End,
+}
+
+impl<T> Pending<T>
+where
+    T: Clone,
+{
+    fn leaf(nodes: &Arena<HuffmanNode<T>>, node: NodeId) -> Option<Self> {
+        let value = nodes[node].get_value();
+
+        value.value.clone().map(|symbol| Self {
+            freq: value.freq,
+            height: 0,
+            symbol,
+            node,
+        })
+    }
+}
+
+impl<T: Ord> PartialEq for Pending<T> {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+
+impl<T: Ord> Eq for Pending<T> {}
+
+impl<T: Ord> PartialOrd for Pending<T> {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl<T: Ord> Ord for Pending<T> {
+    fn cmp(&self, other: &Self) -> Ordering {
+        (self.freq, self.height, &self.symbol).cmp(&(other.freq, other.height, &other.symbol))
+    }
+}
+
+impl<T> Default for HuffmanTree<T> {
+    /// An empty tree, without any symbol.
+    fn default() -> Self {
+        Self {
+            nodes: Arena::new(),
+            root: None,
+        }
+    }
 }
 
 impl<T> HuffmanTree<T>
 where
-    T: Eq + Hash + Copy + Ord + ToString,
+    T: Eq + Hash + Clone + Ord + ToString,
     HuffmanNode<T>: ToString,
 {
+    /// Longest code a `Code` can hold.
+    pub const MAX_CODE_LENGTH: usize = Code::MAX_LEN;
+    /// Code length limit used by the compression pipeline.
+    pub const DEFAULT_MAX_CODE_LENGTH: usize = 32;
+
     pub fn from(values: &[T]) -> Self {
-        let leaves = Self::build_node_leaves_vec(values);
-        let tree = Self::build_tree(leaves);
+        Self::from_frequencies(Self::get_freq_using_btreemap(values))
+    }
+
+    /// Builds the tree from precomputed symbol counts instead of the symbols
+    /// themselves. Counts given more than once for a symbol are added up.
+    pub fn from_frequencies<I>(frequencies: I) -> Self
+    where
+        I: IntoIterator<Item = (T, usize)>,
+    {
+        let mut nodes = Arena::new();
+        let leaves =
+            Self::build_node_leaves_from_freq(&mut nodes, Self::sum_frequencies(frequencies));
+        let root = Self::build_tree(&mut nodes, leaves);
+
+        Self { nodes, root }
+    }
+
+    /// Builds an optimal tree whose codes are at most `max_length` bits long.
+    ///
+    /// When the plain Huffman tree already fits it is returned unchanged,
+    /// otherwise the code lengths are computed with package-merge and the
+    /// canonical tree for them is built.
+    pub fn from_with_max_length(values: &[T], max_length: usize) -> Result<Self, HuffmanError> {
+        Self::from_frequencies_with_max_length(Self::get_freq_using_btreemap(values), max_length)
+    }
+
+    /// Same as `from_with_max_length`, from precomputed symbol counts.
+    pub fn from_frequencies_with_max_length<I>(
+        frequencies: I,
+        max_length: usize,
+    ) -> Result<Self, HuffmanError>
+    where
+        I: IntoIterator<Item = (T, usize)>,
+    {
+        let freq_map = Self::sum_frequencies(frequencies);
+        let tree = Self::from_frequencies(freq_map.clone());
+
+        match tree.get_code_lengths() {
+            Ok(lengths) if lengths.iter().all(|&(_, length)| length <= max_length) => Ok(tree),
+            Ok(_) => {
+                let mut weights: Vec<(T, usize)> = freq_map.into_iter().collect();
+                weights.sort_by(Self::by_count_then_symbol);
+
+                match Self::package_merge(&weights, max_length) {
+                    Some(lengths) => Self::from_code_lengths(&lengths),
+                    None => Err(HuffmanError::invalid_max_code_length(max_length)),
+                }
+            }
+            Err(e) => Err(e),
+        }
+    }
 
-        Self { root: tree }
+    /// Orders `(symbol, count)` pairs by count, then by symbol.
+    fn by_count_then_symbol(a: &(T, usize), b: &(T, usize)) -> Ordering {
+        (a.1, &a.0).cmp(&(b.1, &b.0))
     }
 
-    pub fn get_root(&self) -> &Option<Node<HuffmanNode<T>>> {
-        &self.root
+    pub fn get_root(&self) -> Option<NodeId> {
+        self.root
     }
 
-    pub fn get_encoding_map(&self) -> Result<HashMap<T, Vec<bool>>, HuffmanError> {
-        let collection: HashMap<T, Vec<bool>> = HashMap::new();
-        let based_path: Vec<bool> = Vec::new();
+    pub fn get_nodes(&self) -> &Arena<HuffmanNode<T>> {
+        &self.nodes
+    }
 
-        match &self.root {
-            Some(root) => Self::collect_paths(Some(root), collection, based_path),
+    pub fn get_encoding_map(&self) -> Result<HashMap<T, Code>, HuffmanError> {
+        match self.root {
+            Some(root) => Self::collect_paths(&self.nodes, root),
             None => Err(HuffmanError::invalid_huffman_tree()),
         }
     }
 
-    pub fn decode_by_path(&self, iter: &mut Iter<'_, bool>) -> Result<T, HuffmanError> {
-        match &self.root {
-            Some(root) => Self::get_value_by_path(Some(root), iter),
+    /// Code length of every symbol, ordered by length and then by symbol.
+    pub fn get_code_lengths(&self) -> Result<Vec<(T, usize)>, HuffmanError> {
+        match self.get_encoding_map() {
+            Ok(encoding_map) => {
+                let mut lengths: Vec<(T, usize)> = encoding_map
+                    .into_iter()
+                    .map(|(symbol, code)| (symbol, code.len))
+                    .collect();
+                lengths.sort_by(Self::by_count_then_symbol);
+                Ok(lengths)
+            }
+            Err(e) => Err(e),
+        }
+    }
+
+    /// Builds the canonical tree for the given code lengths: at every depth
+    /// the leaves sit to the left of the internal nodes and are ordered by
+    /// symbol, so the codes can be rebuilt from the lengths alone.
+    pub fn from_code_lengths(lengths: &[(T, usize)]) -> Result<Self, HuffmanError> {
+        let mut sorted_lengths = lengths.to_vec();
+        sorted_lengths.sort_by(Self::by_count_then_symbol);
+
+        let has_duplicates = sorted_lengths
+            .iter()
+            .map(|(symbol, _)| symbol)
+            .collect::<HashSet<&T>>()
+            .len()
+            != sorted_lengths.len();
+        let has_invalid_length = sorted_lengths
+            .iter()
+            .any(|&(_, length)| length == 0 || length > Self::MAX_CODE_LENGTH);
+
+        if has_duplicates || has_invalid_length {
+            return Err(HuffmanError::invalid_huffman_tree());
+        }
+
+        let max_length = match sorted_lengths.last() {
+            Some(&(_, length)) => length,
+            None => return Ok(Self::default()),
+        };
+        let mut nodes: Arena<HuffmanNode<T>> = Arena::new();
+        let mut level: Vec<NodeId> = Vec::new();
+
+        for depth in (1..=max_length).rev() {
+            let mut ids: Vec<NodeId> = sorted_lengths
+                .iter()
+                .filter(|&&(_, length)| length == depth)
+                .map(|(symbol, _)| {
+                    nodes.push(HuffmanNode::new(0, Some(symbol.clone())), None, None)
+                })
+                .collect();
+            ids.append(&mut level);
+
+            if depth == 1 && ids.len() == 1 && sorted_lengths.len() == 1 {
+                let root = Self::combine(&mut nodes, ids.pop(), None);
+
+                return Ok(Self {
+                    nodes,
+                    root: Some(root),
+                });
+            }
+            if !ids.len().is_multiple_of(2) {
+                return Err(HuffmanError::invalid_huffman_tree());
+            }
+
+            let mut iter = ids.into_iter();
+            while let (Some(left), Some(right)) = (iter.next(), iter.next()) {
+                level.push(Self::combine(&mut nodes, Some(left), Some(right)));
+            }
+        }
+
+        match (level.pop(), level.is_empty()) {
+            (Some(root), true) => Ok(Self {
+                nodes,
+                root: Some(root),
+            }),
+            _ => Err(HuffmanError::invalid_huffman_tree()),
+        }
+    }
+
+    /// Normalizes the tree into its canonical form, keeping every code length.
+    pub fn to_canonical(&self) -> Result<Self, HuffmanError> {
+        match self.get_code_lengths() {
+            Ok(lengths) => Self::from_code_lengths(&lengths),
+            Err(e) => Err(e),
+        }
+    }
+
+    /// Reads one symbol by walking the tree bit by bit. Errors carry the bit
+    /// offset of the code being decoded.
+    pub fn decode_by_path(&self, reader: &mut BitReader<'_>) -> Result<T, HuffmanError> {
+        let start = reader.position();
+
+        match self.root {
+            Some(root) => match Self::get_value_by_path(&self.nodes, root, reader) {
+                Ok(value) => Ok(value),
+                Err(e) => Err(e.at_bit(start)),
+            },
             None => Err(HuffmanError::invalid_huffman_tree()),
         }
     }
 
+    /// Builds every code from the leaf up, following parent links to the
+    /// root, so no traversal stack is needed.
     fn collect_paths(
-        node: Option<&Node<HuffmanNode<T>>>,
-        mut collection: HashMap<T, Vec<bool>>,
-        path: Vec<bool>,
-    ) -> Result<HashMap<T, Vec<bool>>, HuffmanError> {
-        match node {
-            Some(node) => {
-                if node.is_leaf() {
-                    match node.get_value().value {
-                        Some(v) => {
-                            collection.insert(v, path);
-                            Ok(collection)
-                        }
-                        None => Err(HuffmanError::invalid_huffman_tree()),
-                    }
-                } else {
-                    let mut left_based_path = path.clone();
+        nodes: &Arena<HuffmanNode<T>>,
+        root: NodeId,
+    ) -> Result<HashMap<T, Code>, HuffmanError> {
+        let mut collection: HashMap<T, Code> = HashMap::new();
+
+        for (id, node) in nodes.iter().filter(|(_, node)| node.is_leaf()) {
+            match (&node.get_value().value, Self::path_to_root(nodes, id, root)) {
+                (Some(v), Some(path)) => {
+                    collection.insert(v.clone(), path);
+                }
+                _ => return Err(HuffmanError::invalid_huffman_tree()),
+            }
+        }
 
-                    left_based_path.push(false);
-                    match Self::collect_paths(node.left(), collection, left_based_path) {
-                        Ok(left_result) => {
-                            let mut right_based_path = path.clone();
+        Ok(collection)
+    }
 
-                            right_based_path.push(true);
-                            Self::collect_paths(node.right(), left_result, right_based_path)
-                        }
-                        Err(_) => Err(HuffmanError::invalid_huffman_tree()),
-                    }
-                }
+    /// The code of `id`, one bit per edge on the way up, or `None` if it is
+    /// longer than a `Code` or does not end at `root`.
+    fn path_to_root(nodes: &Arena<HuffmanNode<T>>, id: NodeId, root: NodeId) -> Option<Code> {
+        let mut bits: u128 = 0;
+        let mut len: usize = 0;
+        let mut child = id;
+
+        while let Some(parent) = nodes[child].parent() {
+            if len == Code::MAX_LEN {
+                return None;
+            }
+            if nodes[parent].right() == Some(child) {
+                bits |= 1 << len;
             }
-            None => Ok(collection),
+            len += 1;
+            child = parent;
         }
+
+        (child == root).then(|| Code::new(bits, len))
     }
 
     fn get_value_by_path(
-        node: Option<&Node<HuffmanNode<T>>>,
-        iter: &mut Iter<'_, bool>,
+        nodes: &Arena<HuffmanNode<T>>,
+        root: NodeId,
+        reader: &mut BitReader<'_>,
     ) -> Result<T, HuffmanError> {
-        match node {
-            Some(node) => {
-                if node.is_leaf() {
-                    match node.get_value().value {
-                        Some(v) => Ok(v),
-                        None => Err(HuffmanError::invalid_huffman_tree()),
-                    }
-                } else {
-                    match iter.next() {
-                        Some(direction) => {
-                            if *direction {
-                                // right
-                                Self::get_value_by_path(node.right(), iter)
-                            } else {
-                                // left
-                                Self::get_value_by_path(node.left(), iter)
-                            }
-                        }
-                        None => Err(HuffmanError::decoding_error()),
-                    }
-                }
+        let mut node = &nodes[root];
+
+        while !node.is_leaf() {
+            let next = match reader.read_bit() {
+                Some(true) => node.right(),
+                Some(false) => node.left(),
+                None => return Err(HuffmanError::decoding_error()),
+            };
+
+            match next {
+                Some(next) => node = &nodes[next],
+                None => return Err(HuffmanError::invalid_huffman_tree()),
             }
+        }
+
+        match &node.get_value().value {
+            Some(v) => Ok(v.clone()),
             None => Err(HuffmanError::invalid_huffman_tree()),
         }
     }
 
     fn combine(
-        left: Option<Node<HuffmanNode<T>>>,
-        right: Option<Node<HuffmanNode<T>>>,
-    ) -> Node<HuffmanNode<T>> {
-        let mut new_freq: usize = 0;
-        let left_node = match left {
-            Some(node) => {
-                new_freq += node.get_value().freq;
-                Some(Box::new(node))
+        nodes: &mut Arena<HuffmanNode<T>>,
+        left: Option<NodeId>,
+        right: Option<NodeId>,
+    ) -> NodeId {
+        let new_freq: usize = left
+            .into_iter()
+            .chain(right)
+            .map(|child| nodes[child].get_value().freq)
+            .fold(0, usize::saturating_add);
+
+        nodes.push(HuffmanNode::new(new_freq, None), left, right)
+    }
+
+    /// Merges the two lightest subtrees until only the root is left, the
+    /// first one taken becoming the left child. See `HuffmanTree` for the
+    /// order subtrees are taken in. Leaves already in merge order, as the
+    /// frequency table yields them, take the two-queue build after a linear
+    /// check; leaves in any other order go through the heap in `O(n log n)`.
+    fn build_tree(nodes: &mut Arena<HuffmanNode<T>>, leaves: Vec<NodeId>) -> Option<NodeId> {
+        let mut leaves: Vec<Pending<T>> = leaves
+            .into_iter()
+            .filter_map(|leaf| Pending::leaf(nodes, leaf))
+            .collect();
+
+        if leaves.len() == 1 {
+            return leaves
+                .pop()
+                .map(|leaf| Self::combine(nodes, Some(leaf.node), None));
+        }
+        if leaves.is_sorted() {
+            Self::build_tree_from_sorted(nodes, leaves)
+        } else {
+            Self::build_tree_with_heap(nodes, leaves)
+        }
+    }
+
+    /// Two-queue construction for leaves already in merge order. Merged
+    /// subtrees are created in non-decreasing frequency order, so they queue
+    /// up in runs of equal frequency and only a run has to be kept ordered.
+    fn build_tree_from_sorted(
+        nodes: &mut Arena<HuffmanNode<T>>,
+        leaves: Vec<Pending<T>>,
+    ) -> Option<NodeId> {
+        let mut leaves: VecDeque<Pending<T>> = leaves.into();
+        let mut merged: VecDeque<BinaryHeap<Reverse<Pending<T>>>> = VecDeque::new();
+
+        loop {
+            let min_1 = Self::pop_lightest(&mut leaves, &mut merged);
+            let min_2 = Self::pop_lightest(&mut leaves, &mut merged);
+            let new_pending = match (min_1, min_2) {
+                (Some(min_1), Some(min_2)) => Self::merge(nodes, min_1, min_2),
+                (min_1, _) => return min_1.map(|pending| pending.node),
+            };
+
+            if leaves.is_empty() && merged.is_empty() {
+                return Some(new_pending.node);
             }
-            None => None,
-        };
-        let right_node = match right {
-            Some(node) => {
-                new_freq += node.get_value().freq;
-                Some(Box::new(node))
+            match merged.back_mut() {
+                Some(run) if run.peek().map(|p| p.0.freq) == Some(new_pending.freq) => {
+                    run.push(Reverse(new_pending))
+                }
+                _ => merged.push_back(BinaryHeap::from([Reverse(new_pending)])),
             }
-            None => None,
-        };
+        }
+    }
 
-        Node::new(HuffmanNode::new(new_freq, None), left_node, right_node)
+    fn pop_lightest(
+        leaves: &mut VecDeque<Pending<T>>,
+        merged: &mut VecDeque<BinaryHeap<Reverse<Pending<T>>>>,
+    ) -> Option<Pending<T>> {
+        let leaf = leaves.front();
+
+        match merged.front_mut() {
+            Some(run)
+                if run
+                    .peek()
+                    .is_some_and(|p| leaf.is_none_or(|leaf| p.0 < *leaf)) =>
+            {
+                let pending = run.pop().map(|p| p.0);
+                if run.is_empty() {
+                    merged.pop_front();
+                }
+                pending
+            }
+            _ => leaves.pop_front(),
+        }
     }
 
-    fn build_tree(mut nodes: Vec<Node<HuffmanNode<T>>>) -> Option<Node<HuffmanNode<T>>>
-    where
-        T: ToString,
-    {
-        let mut root: Option<Node<HuffmanNode<T>>> = None;
+    /// Binary heap construction in `O(n log n)`, for leaves in any order.
+    fn build_tree_with_heap(
+        nodes: &mut Arena<HuffmanNode<T>>,
+        leaves: Vec<Pending<T>>,
+    ) -> Option<NodeId> {
+        let mut heap: BinaryHeap<Reverse<Pending<T>>> = leaves.into_iter().map(Reverse).collect();
+
+        loop {
+            let new_pending = match (heap.pop(), heap.pop()) {
+                (Some(min_1), Some(min_2)) => Self::merge(nodes, min_1.0, min_2.0),
+                (min_1, _) => return min_1.map(|pending| pending.0.node),
+            };
 
-        while nodes.len() > 0 {
-            if nodes.len() == 1 {
-                root = Some(Self::combine(nodes.pop(), None));
-                break;
-            } else {
-                let min_1 = nodes.pop();
-                let min_2 = nodes.pop();
-                let new_node = Self::combine(min_1, min_2);
+            if heap.is_empty() {
+                return Some(new_pending.node);
+            }
+            heap.push(Reverse(new_pending));
+        }
+    }
 
-                if nodes.len() == 0 {
-                    root = Some(new_node);
-                    break;
+    fn merge(
+        nodes: &mut Arena<HuffmanNode<T>>,
+        min_1: Pending<T>,
+        min_2: Pending<T>,
+    ) -> Pending<T> {
+        Pending {
+            freq: min_1.freq.saturating_add(min_2.freq),
+            height: min_1.height.max(min_2.height) + 1,
+            symbol: min_1.symbol.min(min_2.symbol),
+            node: Self::combine(nodes, Some(min_1.node), Some(min_2.node)),
+        }
+    }
+
+    /// Package-merge over `weights`, which must be sorted by ascending weight.
+    /// Returns `None` when `max_length` bits cannot encode that many symbols.
+    fn package_merge(weights: &[(T, usize)], max_length: usize) -> Option<Vec<(T, usize)>> {
+        enum Item {
+            Leaf(usize),
+            Package(usize, usize),
+        }
+
+        let n = weights.len();
+        if max_length == 0
+            || max_length > Self::MAX_CODE_LENGTH
+            || (max_length < usize::BITS as usize && n > 1 << max_length)
+        {
+            return None;
+        }
+
+        // Every item of every level lives in `items`; a level is a list of
+        // (weight, item index) sorted by weight.
+        let mut items: Vec<Item> = (0..n).map(Item::Leaf).collect();
+        let leaves: Vec<(usize, usize)> = weights
+            .iter()
+            .enumerate()
+            .map(|(i, &(_, weight))| (weight, i))
+            .collect();
+        let mut level = leaves.clone();
+
+        for _ in 1..max_length {
+            let mut packages: Vec<(usize, usize)> = Vec::new();
+            for pair in level.chunks_exact(2) {
+                items.push(Item::Package(pair[0].1, pair[1].1));
+                packages.push((pair[0].0 + pair[1].0, items.len() - 1));
+            }
+
+            let mut merged: Vec<(usize, usize)> = Vec::with_capacity(n + packages.len());
+            let (mut i, mut j) = (0, 0);
+            while i < leaves.len() || j < packages.len() {
+                if j == packages.len() || (i < leaves.len() && leaves[i].0 <= packages[j].0) {
+                    merged.push(leaves[i]);
+                    i += 1;
                 } else {
-                    nodes = Self::add_node(nodes, new_node);
+                    merged.push(packages[j]);
+                    j += 1;
                 }
             }
+            level = merged;
         }
 
-        root
+        // Each time a leaf appears among the first 2n - 2 items its code gets one bit longer.
+        let mut lengths: Vec<usize> = vec![0; n];
+        let mut stack: Vec<usize> = level
+            .iter()
+            .take(2 * n - 2)
+            .map(|&(_, index)| index)
+            .collect();
+        while let Some(index) = stack.pop() {
+            match items[index] {
+                Item::Leaf(leaf) => lengths[leaf] += 1,
+                Item::Package(left, right) => {
+                    stack.push(left);
+                    stack.push(right);
+                }
+            }
+        }
+
+        Some(
+            weights
+                .iter()
+                .zip(lengths)
+                .map(|((symbol, _), length)| (symbol.clone(), length))
+                .collect(),
+        )
     }
 
+    #[cfg(test)]
     fn get_freq_using_hashmap(values: &[T]) -> HashMap<T, usize> {
         let mut map: HashMap<T, usize> = HashMap::new();
         values
-            .into_iter()
-            .for_each(|&v| *map.entry(v).or_insert(0) += 1);
+            .iter()
+            .for_each(|v| *map.entry(v.clone()).or_insert(0) += 1);
 
         map
     }
@@ -211,133 +583,225 @@ where
     fn get_freq_using_btreemap(values: &[T]) -> BTreeMap<T, usize> {
         let mut map: BTreeMap<T, usize> = BTreeMap::new();
         values
+            .iter()
+            .for_each(|v| *map.entry(v.clone()).or_insert(0) += 1);
+
+        map
+    }
+
+    fn sum_frequencies<I>(frequencies: I) -> BTreeMap<T, usize>
+    where
+        I: IntoIterator<Item = (T, usize)>,
+    {
+        let mut map: BTreeMap<T, usize> = BTreeMap::new();
+        frequencies
             .into_iter()
-            .for_each(|&v| *map.entry(v).or_insert(0) += 1);
+            .for_each(|(v, freq)| *map.entry(v).or_insert(0) += freq);
 
         map
     }
 
-    fn build_node_leaves_vec(values: &[T]) -> Vec<Node<HuffmanNode<T>>> {
-        let map = Self::get_freq_using_btreemap(values);
+    #[cfg(test)]
+    fn build_node_leaves_vec(nodes: &mut Arena<HuffmanNode<T>>, values: &[T]) -> Vec<NodeId> {
+        Self::build_node_leaves_from_freq(nodes, Self::get_freq_using_btreemap(values))
+    }
+
+    /// Leaves in merge order: by frequency, then by symbol.
+    fn build_node_leaves_from_freq(
+        nodes: &mut Arena<HuffmanNode<T>>,
+        map: BTreeMap<T, usize>,
+    ) -> Vec<NodeId> {
         let mut hash_vec: Vec<(T, usize)> = map.into_iter().collect();
-        hash_vec.sort_by(|a, b| b.1.cmp(&a.1));
+        hash_vec.sort_by_key(|a| a.1);
         hash_vec
             .into_iter()
-            .map(|a| Node::new(HuffmanNode::new(a.1, Some(a.0)), None, None))
+            .map(|a| nodes.push(HuffmanNode::new(a.1, Some(a.0)), None, None))
             .collect()
     }
 
-    fn add_node(
-        mut nodes: Vec<Node<HuffmanNode<T>>>,
-        new_node: Node<HuffmanNode<T>>,
-    ) -> Vec<Node<HuffmanNode<T>>> {
-        nodes.push(new_node);
-        nodes.sort_by(|a, b| b.get_value().freq.cmp(&a.get_value().freq));
-        nodes
+    /// Rebuilds a tree from its preorder tokens. The parents still waiting
+    /// for a child are kept on an explicit stack, and a parent deeper than
+    /// `MAX_CODE_LENGTH` is rejected before anything below it is read, and
+    /// so is a leaf repeating a symbol already seen.
+    fn deserialize_preorder<F>(mut next_token: F) -> Result<Self, HuffmanError>
+    where
+        F: FnMut() -> Result<Token<T>, HuffmanError>,
+    {
+        let mut nodes: Arena<HuffmanNode<T>> = Arena::new();
+        // Each entry is a parent, holding its left subtree once it is read.
+        let mut parents: Vec<Option<Option<NodeId>>> = Vec::new();
+        let mut symbols: HashSet<T> = HashSet::new();
+
+        loop {
+            let mut subtree = match next_token() {
+                Ok(Token::Parent) if parents.len() >= Self::MAX_CODE_LENGTH => {
+                    return Err(HuffmanError::cannot_deserialize_tree())
+                }
+                Ok(Token::Parent) => {
+                    parents.push(None);
+                    continue;
+                }
+                Ok(Token::Leaf(symbol)) => {
+                    if !symbols.insert(symbol.clone()) {
+                        return Err(HuffmanError::cannot_deserialize_tree());
+                    }
+                    Some(nodes.push(HuffmanNode::new(0, Some(symbol)), None, None))
+                }
+                Ok(Token::End) => None,
+                Err(e) => return Err(e),
+            };
+
+            loop {
+                match parents.pop() {
+                    None => {
+                        return Ok(Self {
+                            nodes,
+                            root: subtree,
+                        })
+                    }
+                    Some(None) => {
+                        parents.push(Some(subtree));
+                        break;
+                    }
+                    // Only the root of a single-symbol tree has no right
+                    // child; anywhere else the input was cut short.
+                    Some(Some(left))
+                        if subtree.is_none()
+                            && !(parents.is_empty()
+                                && left.is_some_and(|left| nodes[left].is_leaf())) =>
+                    {
+                        return Err(HuffmanError::cannot_deserialize_tree())
+                    }
+                    Some(Some(left)) => {
+                        subtree = Some(nodes.push(HuffmanNode::new(0, None), left, subtree));
+                    }
+                }
+            }
+        }
     }
 
     pub fn print_tree_pretty(&self) {
-        match self.get_root() {
-            Some(root) => root.represent_tree(),
-            None => {}
+        if let Some(root) = self.root {
+            self.nodes.represent_tree(root);
         }
     }
 }
 
-impl HuffmanTree<char> {
-    const CHAR_PARENT_NODE: char = '0';
-    const CHAR_LEAF_NODE: char = '1';
+/// Serialization, for any symbol that can be written as bytes.
+///
+/// The shape format lists the nodes in preorder: `0` for a parent, `1` and
+/// the encoded symbol for a leaf. A `char` tree serializes as UTF-8 text,
+/// but a `u8` tree is only text while every byte is ASCII: bytes from `0x80`
+/// are written raw and are not valid UTF-8 on their own. Serializing fails
+/// only for symbols `Symbol::encode` rejects, such as strings of 4 GiB or more.
+impl<T> HuffmanTree<T>
+where
+    T: Eq + Hash + Clone + Ord + ToString + Symbol,
+    HuffmanNode<T>: ToString,
+{
+    const PARENT_NODE: u8 = b'0';
+    const LEAF_NODE: u8 = b'1';
+
+    pub fn serialize(&self) -> Result<Vec<u8>, HuffmanError> {
+        let mut result: Vec<u8> = Vec::new();
 
-    pub fn serialize(&self) -> String {
-        match &self.root {
-            Some(root) => Self::serialize_internal(root),
-            None => String::new(),
+        if let Some(root) = self.root {
+            self.serialize_internal(root, &mut result)?;
         }
+        Ok(result)
     }
 
-    pub fn deserialize(text: String) -> Result<HuffmanTree<char>, HuffmanError> {
-        let chars_vec: Vec<char> = text.chars().collect();
-        let mut iter: std::slice::Iter<'_, char> = chars_vec.iter();
+    pub fn deserialize(bytes: &[u8]) -> Result<HuffmanTree<T>, HuffmanError> {
+        if bytes.is_empty() {
+            return Ok(Self::default());
+        }
 
-        if iter.len() > 0 {
-            let root = HuffmanTree::deserialize_internal(&mut iter);
+        let mut remaining = bytes;
+        let tree = Self::deserialize_internal(&mut remaining);
 
-            if iter.len() > 0 {
-                Err(HuffmanError::cannot_deserialize_tree())
-            } else {
-                match root {
-                    Ok(root) => Ok(Self { root: root }),
-                    Err(e) => Err(e),
+        if remaining.is_empty() {
+            tree
+        } else {
+            Err(HuffmanError::cannot_deserialize_tree())
+        }
+    }
+
+    /// Serializes only the `(symbol, code length)` pairs of a canonical tree:
+    /// each encoded symbol followed by its length as a `u8`. Fails for an
+    /// empty tree and for one that is not canonical, whose codes could not be
+    /// rebuilt from the lengths.
+    pub fn serialize_code_lengths(&self) -> Result<Vec<u8>, HuffmanError> {
+        let mut result: Vec<u8> = Vec::new();
+        let encoding_map = self.get_encoding_map()?;
+
+        if self.to_canonical()?.get_encoding_map()? != encoding_map {
+            return Err(HuffmanError::cannot_serialize_tree());
+        }
+        for (symbol, length) in self.get_code_lengths()? {
+            symbol.encode(&mut result)?;
+            result.push(length as u8);
+        }
+        Ok(result)
+    }
+
+    pub fn deserialize_code_lengths(bytes: &[u8]) -> Result<HuffmanTree<T>, HuffmanError> {
+        let mut lengths: Vec<(T, usize)> = Vec::new();
+        let mut remaining = bytes;
+
+        while !remaining.is_empty() {
+            match T::decode(remaining) {
+                Some((symbol, width)) if width < remaining.len() => {
+                    lengths.push((symbol, remaining[width] as usize));
+                    remaining = &remaining[width + 1..];
                 }
+                _ => return Err(HuffmanError::cannot_deserialize_tree()),
             }
-        } else {
-            Ok(Self { root: None })
+        }
+
+        match Self::from_code_lengths(&lengths) {
+            Ok(tree) => Ok(tree),
+            Err(_) => Err(HuffmanError::cannot_deserialize_tree()),
         }
     }
 
-    fn serialize_internal(node: &Node<HuffmanNode<char>>) -> String {
-        if node.is_leaf() {
-            return Self::CHAR_LEAF_NODE.to_string() + &node.get_value().get_value_as_string();
-        } else {
-            let left_str = match node.left() {
-                Some(left_node) => Self::serialize_internal(left_node),
-                None => String::new(),
-            };
-            let right_str = match node.right() {
-                Some(right_node) => Self::serialize_internal(right_node),
-                None => String::new(),
-            };
+    fn serialize_internal(&self, root: NodeId, result: &mut Vec<u8>) -> Result<(), HuffmanError> {
+        let mut stack: Vec<NodeId> = vec![root];
 
-            return Self::CHAR_PARENT_NODE.to_string() + &left_str + &right_str;
-        }
-    }
-
-    fn deserialize_internal(
-        iter: &mut Iter<'_, char>,
-    ) -> Result<Option<Node<HuffmanNode<char>>>, HuffmanError> {
-        match iter.next() {
-            Some(n) => {
-                if *n == Self::CHAR_PARENT_NODE {
-                    let mut has_child = false;
-                    let wrapped_left_node = HuffmanTree::deserialize_internal(iter);
-                    let wrapped_right_node = HuffmanTree::deserialize_internal(iter);
-
-                    match (wrapped_left_node, wrapped_right_node) {
-                        (Ok(wrapped_left_node), Ok(wrapped_right_node)) => {
-                            let left = match wrapped_left_node {
-                                Some(node) => {
-                                    has_child = true;
-                                    Some(Box::new(node))
-                                }
-                                None => None,
-                            };
-                            let right = match wrapped_right_node {
-                                Some(node) => {
-                                    has_child = true;
-                                    Some(Box::new(node))
-                                }
-                                None => None,
-                            };
-
-                            if has_child {
-                                Ok(Some(Node::new(HuffmanNode::new(0, None), left, right)))
-                            } else {
-                                Err(HuffmanError::cannot_deserialize_tree())
-                            }
-                        }
-                        _ => Err(HuffmanError::cannot_deserialize_tree()),
-                    }
-                } else if *n == Self::CHAR_LEAF_NODE {
-                    match iter.next() {
-                        Some(c) => Ok(Some(Node::new(HuffmanNode::new(0, Some(*c)), None, None))),
-                        None => Err(HuffmanError::cannot_deserialize_tree()),
-                    }
-                } else {
-                    Err(HuffmanError::cannot_deserialize_tree())
+        while let Some(id) = stack.pop() {
+            let node = &self.nodes[id];
+
+            if node.is_leaf() {
+                if let Some(value) = &node.get_value().value {
+                    result.push(Self::LEAF_NODE);
+                    value.encode(result)?;
                 }
+            } else {
+                result.push(Self::PARENT_NODE);
+                stack.extend(node.right());
+                stack.extend(node.left());
             }
-            None => Ok(None),
         }
+        Ok(())
+    }
+
+    /// Reads one tree from the start of `remaining`, leaving the rest of the
+    /// input in it.
+    fn deserialize_internal(remaining: &mut &[u8]) -> Result<HuffmanTree<T>, HuffmanError> {
+        Self::deserialize_preorder(|| match remaining.split_first() {
+            Some((&Self::PARENT_NODE, rest)) => {
+                *remaining = rest;
+                Ok(Token::Parent)
+            }
+            Some((&Self::LEAF_NODE, rest)) => match T::decode(rest) {
+                Some((symbol, width)) => {
+                    *remaining = &rest[width..];
+                    Ok(Token::Leaf(symbol))
+                }
+                None => Err(HuffmanError::cannot_deserialize_tree()),
+            },
+            Some(_) => Err(HuffmanError::cannot_deserialize_tree()),
+            None => Ok(Token::End),
+        })
     }
 }
 
@@ -346,6 +810,7 @@ mod tests {
     use std::vec;
 
     use super::*;
+    use crate::utils::bit_io::PackedBits;
 
     #[test]
     fn test_get_freq_using_hashmap() {
@@ -412,87 +877,231 @@ mod tests {
     #[test]
     fn test_build_node_leaves_vec() {
         let value = "Welcome to my world!!!".as_bytes();
-        let result = HuffmanTree::build_node_leaves_vec(value);
-        let chars_result: Vec<u8> = result
-            .iter()
-            .map(|node| node.get_value().value.unwrap())
+        let mut nodes = Arena::new();
+        let result: Vec<&HuffmanNode<u8>> = HuffmanTree::build_node_leaves_vec(&mut nodes, value)
+            .into_iter()
+            .map(|id| nodes[id].get_value())
             .collect();
-        let chars_expect = &[32, 33, 111, 101, 108, 109, 87, 99, 100, 114, 116, 119, 121];
+        let chars_result: Vec<u8> = result.iter().map(|node| node.value.unwrap()).collect();
+        let chars_expect = &[87, 99, 100, 114, 116, 119, 121, 101, 108, 109, 32, 33, 111];
         assert_eq!(chars_result, chars_expect);
 
-        let freqs_result: Vec<usize> = result.iter().map(|node| node.get_value().freq).collect();
-        let freqs_expect: &[usize; 13] = &[3, 3, 3, 2, 2, 2, 1, 1, 1, 1, 1, 1, 1];
+        let freqs_result: Vec<usize> = result.iter().map(|node| node.freq).collect();
+        let freqs_expect: &[usize; 13] = &[1, 1, 1, 1, 1, 1, 1, 2, 2, 2, 3, 3, 3];
         assert_eq!(freqs_result, freqs_expect);
     }
 
     #[test]
     fn test_combine() {
-        let left = Node::new(HuffmanNode::new(3, Some(32)), None, None);
-        let right = Node::new(HuffmanNode::new(1, Some(12)), None, None);
-        let root = HuffmanTree::combine(Some(left), Some(right));
+        let mut nodes = Arena::new();
+        let left = nodes.push(HuffmanNode::new(3, Some(32)), None, None);
+        let right = nodes.push(HuffmanNode::new(1, Some(12)), None, None);
+        let root = HuffmanTree::combine(&mut nodes, Some(left), Some(right));
 
-        assert_eq!(root.get_value().freq, 4);
-        assert!(root.get_value().value.is_none());
-        assert_eq!(root.left().unwrap().get_value().freq, 3);
-        assert_eq!(root.right().unwrap().get_value().freq, 1);
+        assert_eq!(nodes[root].get_value().freq, 4);
+        assert!(nodes[root].get_value().value.is_none());
+        assert_eq!(nodes[root].left(), Some(left));
+        assert_eq!(nodes[root].right(), Some(right));
+        assert_eq!(nodes[left].parent(), Some(root));
+        assert_eq!(nodes[right].parent(), Some(root));
 
-        let left = Node::new(HuffmanNode::new(3, Some(32)), None, None);
-        let root = HuffmanTree::combine(Some(left), None);
+        let left = nodes.push(HuffmanNode::new(3, Some(32)), None, None);
+        let root = HuffmanTree::combine(&mut nodes, Some(left), None);
 
-        assert_eq!(root.get_value().freq, 3);
-        assert_eq!(root.left().unwrap().get_value().freq, 3);
-        assert_eq!(root.left().unwrap().get_value().value, Some(32));
-        assert!(root.right().is_none());
+        assert_eq!(nodes[root].get_value().freq, 3);
+        assert_eq!(nodes[root].left(), Some(left));
+        assert!(nodes[root].right().is_none());
 
-        let right = Node::new(HuffmanNode::new(3, Some(32)), None, None);
-        let root = HuffmanTree::combine(None, Some(right));
+        let right = nodes.push(HuffmanNode::new(3, Some(32)), None, None);
+        let root = HuffmanTree::combine(&mut nodes, None, Some(right));
 
-        assert_eq!(root.get_value().freq, 3);
-        assert_eq!(root.right().unwrap().get_value().freq, 3);
-        assert_eq!(root.right().unwrap().get_value().value, Some(32));
-        assert!(root.left().is_none());
+        assert_eq!(nodes[root].get_value().freq, 3);
+        assert_eq!(nodes[root].right(), Some(right));
+        assert!(nodes[root].left().is_none());
 
-        let root: Node<HuffmanNode<u8>> = HuffmanTree::combine(None, None);
+        let root = HuffmanTree::<u8>::combine(&mut nodes, None, None);
 
-        assert_eq!(root.get_value().freq, 0);
-        assert!(root.left().is_none());
-        assert!(root.right().is_none());
+        assert_eq!(nodes[root].get_value().freq, 0);
+        assert!(nodes[root].is_leaf());
     }
 
     #[test]
     fn test_combine_complex() {
-        let left_left = Node::new(HuffmanNode::new(1, Some(1)), None, None);
-        let left_right = Node::new(HuffmanNode::new(2, Some(3)), None, None);
-        let left = HuffmanTree::combine(Some(left_left), Some(left_right));
-        let right = Node::new(HuffmanNode::new(4, Some(5)), None, None);
-        let root = HuffmanTree::combine(Some(left), Some(right));
-
-        assert_eq!(root.get_value().freq, 7);
-        assert!(root.get_value().value.is_none());
-        assert_eq!(root.left().unwrap().get_value().freq, 3);
-        assert!(root.left().unwrap().get_value().value.is_none());
-        assert_eq!(root.right().unwrap().get_value().freq, 4);
-        assert_eq!(root.right().unwrap().get_value().value, Some(5));
-        assert_eq!(root.left().unwrap().left().unwrap().get_value().freq, 1);
-        assert_eq!(
-            root.left().unwrap().left().unwrap().get_value().value,
-            Some(1)
-        );
-        assert_eq!(root.left().unwrap().right().unwrap().get_value().freq, 2);
-        assert_eq!(
-            root.left().unwrap().right().unwrap().get_value().value,
-            Some(3)
-        );
+        let mut nodes = Arena::new();
+        let left_left = nodes.push(HuffmanNode::new(1, Some(1)), None, None);
+        let left_right = nodes.push(HuffmanNode::new(2, Some(3)), None, None);
+        let left = HuffmanTree::combine(&mut nodes, Some(left_left), Some(left_right));
+        let right = nodes.push(HuffmanNode::new(4, Some(5)), None, None);
+        let root = HuffmanTree::combine(&mut nodes, Some(left), Some(right));
+
+        assert_eq!(nodes[root].get_value().freq, 7);
+        assert!(nodes[root].get_value().value.is_none());
+        assert_eq!(nodes[left].get_value().freq, 3);
+        assert!(nodes[left].get_value().value.is_none());
+        assert_eq!(nodes[left].parent(), Some(root));
+        assert_eq!(nodes.depth(left_left), 2);
+        assert_eq!(nodes.depth(left_right), 2);
+        assert_eq!(nodes.depth(right), 1);
     }
 
     #[test]
     fn test_build_tree() {
         let value = "Welcome to my world!!!".as_bytes();
-        let node_leaves = HuffmanTree::build_node_leaves_vec(value);
-        let tree = HuffmanTree::build_tree(node_leaves);
+        let mut nodes = Arena::new();
+        let node_leaves = HuffmanTree::build_node_leaves_vec(&mut nodes, value);
+        let root = HuffmanTree::build_tree(&mut nodes, node_leaves);
+
+        assert!(root.is_some());
+        assert_eq!(nodes[root.unwrap()].get_value().freq, 22);
+    }
+
+    #[test]
+    fn test_build_tree_order_independent() {
+        let mut seed: u32 = 7;
+        let mut next = |modulo: u32| {
+            seed = seed.wrapping_mul(1103515245).wrapping_add(12345);
+            (seed >> 16) % modulo
+        };
+
+        for round in 0..200 {
+            // Small frequency ranges give plenty of ties.
+            let max_freq = [2, 5, 1000][round % 3];
+            let frequencies: BTreeMap<u8, usize> = (0..1 + next(256))
+                .map(|_| (next(256) as u8, 1 + next(max_freq) as usize))
+                .collect();
+            let mut nodes = Arena::new();
+            let leaves = HuffmanTree::build_node_leaves_from_freq(&mut nodes, frequencies);
+            let mut shuffled = leaves.clone();
+            for i in (1..shuffled.len()).rev() {
+                shuffled.swap(i, next(i as u32 + 1) as usize);
+            }
+            let pending = |ids: &[NodeId]| -> Vec<Pending<u8>> {
+                ids.iter()
+                    .filter_map(|&id| Pending::leaf(&nodes, id))
+                    .collect()
+            };
+            // Every build adds its parents to a copy of the same leaves.
+            let build = |f: &dyn Fn(&mut Arena<HuffmanNode<u8>>) -> Option<NodeId>| {
+                let mut nodes = nodes.clone();
+                let root = f(&mut nodes);
+                HuffmanTree::<u8> { nodes, root }.serialize().unwrap()
+            };
+            let expect = build(&|nodes| HuffmanTree::build_tree(nodes, leaves.clone()));
+
+            assert_eq!(
+                build(&|nodes| HuffmanTree::build_tree(nodes, shuffled.clone())),
+                expect
+            );
+            assert_eq!(
+                build(&|nodes| HuffmanTree::build_tree_with_heap(nodes, pending(&leaves))),
+                expect
+            );
+            assert_eq!(
+                build(&|nodes| HuffmanTree::build_tree_with_heap(nodes, pending(&shuffled))),
+                expect
+            );
+        }
+    }
+
+    #[test]
+    fn test_parent_links() {
+        let tree = HuffmanTree::from("Welcome to my world!!!".as_bytes());
+        let nodes = tree.get_nodes();
+        let root = tree.get_root().unwrap();
+        let encoding_map = tree.get_encoding_map().unwrap();
+
+        // 13 leaves and 12 parents in one arena, every node but the root linked up.
+        assert_eq!(nodes.len(), 25);
+        assert_eq!(
+            nodes
+                .iter()
+                .filter(|(_, node)| node.parent().is_none())
+                .count(),
+            1
+        );
+        for (id, node) in nodes.iter().filter(|(_, node)| node.is_leaf()) {
+            let code = encoding_map[&node.get_value().value.unwrap()];
+            assert_eq!(nodes.depth(id), code.len);
+            assert_eq!(HuffmanTree::path_to_root(nodes, id, root), Some(code));
+        }
+
+        let clone = tree.clone();
+        assert_eq!(clone.get_nodes().len(), nodes.len());
+        assert_eq!(clone.get_encoding_map().unwrap(), encoding_map);
+    }
+
+    #[test]
+    fn test_tie_breaking() {
+        // b and c merge first as the smallest symbols of frequency 1. The
+        // pair then ties with the leaf a and goes second, being deeper.
+        let tree = HuffmanTree::from_frequencies([(b'c', 1), (b'b', 1), (b'a', 2)]);
+        let encoding_map = tree.get_encoding_map().unwrap();
+
+        assert_eq!(encoding_map[&b'a'], Code::new(0b0, 1));
+        assert_eq!(encoding_map[&b'b'], Code::new(0b10, 2));
+        assert_eq!(encoding_map[&b'c'], Code::new(0b11, 2));
+
+        // The same counts in any order give the same tree.
+        let reordered = HuffmanTree::from_frequencies([(b'a', 2), (b'c', 1), (b'b', 1)]);
+        assert_eq!(reordered.serialize().unwrap(), tree.serialize().unwrap());
+    }
+
+    /// Trees pinned for the compatibility corpus. They must never change:
+    /// every version of the crate builds the same tree from the same input.
+    #[test]
+    fn test_compatibility_corpus() {
+        let corpus: [(&str, &str); 6] = [
+            ("a", "01a"),
+            ("abab", "01a1b"),
+            ("aaaabbbbccccdddd", "001a1b01c1d"),
+            (
+                "Welcome to my world!!!",
+                "00001W1c01d1r001t1w1 001!1o001y1e01l1m",
+            ),
+            (
+                "the quick brown fox jumps over the lazy dog",
+                "000001a1b01c1d001f1g01i1j0001k1l01m1n001p1q01s1v00001w1x01y1z01e1o01 001h1r01t1u",
+            ),
+            (
+                "Xin chào thế giới ✓",
+                "0001h01X1c001g1n01o1t0001à1ế01ớ1✓01i1 ",
+            ),
+        ];
 
-        assert!(tree.is_some());
-        assert_eq!(tree.unwrap().get_value().freq, 22);
+        for (text, expect) in corpus {
+            let values: Vec<char> = text.chars().collect();
+            assert_eq!(
+                HuffmanTree::from(&values).serialize().unwrap(),
+                expect.as_bytes(),
+                "{:?}",
+                text
+            );
+        }
+    }
+
+    #[test]
+    fn test_build_tree_large_alphabet() {
+        let frequencies: Vec<(char, usize)> = (0..0x10000u32)
+            .filter_map(char::from_u32)
+            .map(|c| (c, 1 + (c as usize * 7919) % 1000))
+            .collect();
+        let tree = HuffmanTree::from_frequencies(frequencies.clone());
+        let total: usize = frequencies.iter().map(|&(_, freq)| freq).sum();
+
+        assert_eq!(
+            tree.get_nodes()[tree.get_root().unwrap()].get_value().freq,
+            total
+        );
+        assert_eq!(tree.get_code_lengths().unwrap().len(), frequencies.len());
+
+        // Unsorted leaves go through the heap.
+        let mut nodes = Arena::new();
+        let leaves: Vec<NodeId> = frequencies
+            .iter()
+            .map(|&(c, freq)| nodes.push(HuffmanNode::new(freq, Some(c)), None, None))
+            .collect();
+        let root = HuffmanTree::build_tree(&mut nodes, leaves).unwrap();
+        assert_eq!(nodes[root].get_value().freq, total);
     }
 
     #[test]
@@ -504,28 +1113,42 @@ mod tests {
         tree.print_tree_pretty();
     }
 
+    #[test]
+    fn test_from_frequencies() {
+        let values: Vec<u8> = "Welcome to my world!!!".bytes().collect();
+        let frequencies = HuffmanTree::get_freq_using_hashmap(&values);
+        let tree = HuffmanTree::from_frequencies(frequencies);
+
+        assert_eq!(
+            tree.serialize().unwrap(),
+            HuffmanTree::from(&values).serialize().unwrap()
+        );
+
+        let tree = HuffmanTree::from_frequencies([(b'a', 1), (b'b', 2), (b'a', 3)]);
+        assert_eq!(tree.get_code_lengths().unwrap(), vec![(b'a', 1), (b'b', 1)]);
+        assert!(HuffmanTree::<u8>::from_frequencies([]).get_root().is_none());
+    }
+
     #[test]
     fn test_get_encoding_map() {
         let value = "Welcome to my world!!!".as_bytes();
         let tree = HuffmanTree::from(value);
 
         let result = tree.get_encoding_map().unwrap();
-        let l = false;
-        let r = true;
-        let expect: HashMap<u8, Vec<bool>> = HashMap::from([
-            (32, vec![r, r, l]),
-            (33, vec![r, l, r]),
-            (111, vec![r, l, l]),
-            (101, vec![l, r, l]),
-            (108, vec![r, r, r, r]),
-            (109, vec![r, r, r, l]),
-            (87, vec![l, r, r, l]),
-            (99, vec![l, r, r, r, r]),
-            (100, vec![l, r, r, r, l]),
-            (114, vec![l, l, l, r]),
-            (116, vec![l, l, l, l]),
-            (119, vec![l, l, r, r]),
-            (121, vec![l, l, r, l]),
+        let expect: HashMap<u8, Code> = HashMap::from([
+            (32, Code::new(0b011, 3)),
+            (33, Code::new(0b100, 3)),
+            (111, Code::new(0b101, 3)),
+            (101, Code::new(0b1101, 4)),
+            (108, Code::new(0b1110, 4)),
+            (109, Code::new(0b1111, 4)),
+            (87, Code::new(0b0000, 4)),
+            (99, Code::new(0b0001, 4)),
+            (100, Code::new(0b0010, 4)),
+            (114, Code::new(0b0011, 4)),
+            (116, Code::new(0b0100, 4)),
+            (119, Code::new(0b0101, 4)),
+            (121, Code::new(0b1100, 4)),
         ]);
 
         assert_eq!(result, expect);
@@ -539,14 +1162,15 @@ mod tests {
         let l = false;
         let r = true;
         let encoded_vec = vec![
-            l, r, r, l, l, r, l, r, r, r, r, l, r, r, r, r, r, l, l, r, r, r, l, l, r, l, r, r, l,
-            l, l, l, l, r, l, l, r, r, l, r, r, r, l, l, l, r, l, r, r, l, l, l, r, r, r, l, l, l,
-            l, l, r, r, r, r, r, l, r, r, r, l, r, l, r, r, l, r, r, l, r,
+            l, l, l, l, r, r, l, r, r, r, r, l, l, l, l, r, r, l, r, r, r, r, r, r, r, l, r, l, r,
+            r, l, r, l, l, r, l, r, l, r, r, r, r, r, r, r, r, l, l, l, r, r, l, r, l, r, r, l, r,
+            l, l, r, r, r, r, r, l, l, l, r, l, r, l, l, r, l, l, r, l, l,
         ];
+        let encoded_bits: PackedBits = encoded_vec.into_iter().collect();
+        let mut reader = encoded_bits.reader();
         let mut decoded_text = String::new();
-        let mut iter = encoded_vec.iter();
         for _ in 1..23 {
-            decoded_text.push(tree.decode_by_path(&mut iter).unwrap());
+            decoded_text.push(tree.decode_by_path(&mut reader).unwrap());
         }
 
         assert_eq!(decoded_text, text);
@@ -559,10 +1183,10 @@ mod tests {
         let tree = HuffmanTree::from(&text_as_chars);
         let l = false;
         let r = true;
-        let encoded_vec = vec![l, r];
-        let mut iter = encoded_vec.iter();
+        let encoded_bits: PackedBits = [l, r].into_iter().collect();
+        let mut reader = encoded_bits.reader();
 
-        assert!(tree.decode_by_path(&mut iter).is_err());
+        assert!(tree.decode_by_path(&mut reader).is_err());
     }
 
     #[test]
@@ -570,68 +1194,518 @@ mod tests {
         let text = "Welcome to my world!!!".to_string();
         let text_as_chars: Vec<char> = text.chars().collect();
         let tree = HuffmanTree::from(&text_as_chars);
-        let result = tree.serialize();
-        let expect = "00001t1r01y1w01e01W01d1c001o1!01 01m1l".to_string();
+        let result = tree.serialize().unwrap();
+        let expect = "00001W1c01d1r001t1w1 001!1o001y1e01l1m";
 
-        assert_eq!(result, expect);
+        assert_eq!(result, expect.as_bytes());
     }
 
     #[test]
     fn test_deserialize_successful() {
-        let input = "00001t1r01y1w01e01W01d1c001o1!01 01m1l".to_string();
-        let tree = HuffmanTree::deserialize(input.clone());
-        let serialized_text = tree.unwrap().serialize();
-
-        assert_eq!(serialized_text, input);
-
-        let input = "00001t1r01y1w01e01W01d1c001o1!01 01m".to_string();
-        let tree = HuffmanTree::deserialize(input.clone());
-        let serialized_text = tree.unwrap().serialize();
+        let input = "00001t1r01y1w01e01W01d1c001o1!01 01m1l".as_bytes();
+        let tree = HuffmanTree::<char>::deserialize(input);
+        let serialized_text = tree.unwrap().serialize().unwrap();
 
         assert_eq!(serialized_text, input);
 
-        let input = "1c".to_string();
-        let tree = HuffmanTree::deserialize(input.clone());
-        let serialized_text = tree.unwrap().serialize();
+        let input = "1c".as_bytes();
+        let tree = HuffmanTree::<char>::deserialize(input);
+        let serialized_text = tree.unwrap().serialize().unwrap();
 
         assert_eq!(serialized_text, input);
 
-        let input = String::new();
-        let tree = HuffmanTree::deserialize(input.clone());
-        let serialized_text = tree.unwrap().serialize();
+        let tree = HuffmanTree::<char>::deserialize(&[]);
+        let serialized_text = tree.unwrap().serialize().unwrap();
 
-        assert_eq!(serialized_text, input);
+        assert!(serialized_text.is_empty());
 
         let text = "Welcome to my world - 12312121 00 233 ~@#$%%& #fbdfd af !!!".to_string();
         let text_as_chars: Vec<char> = text.chars().collect();
         let tree = HuffmanTree::from(&text_as_chars);
-        let serialized_text_1 = tree.serialize();
-        let deserialized_tree = HuffmanTree::deserialize(serialized_text_1.clone());
-        let serialized_text_2 = deserialized_tree.unwrap().serialize();
+        let serialized_text_1 = tree.serialize().unwrap();
+        let deserialized_tree = HuffmanTree::<char>::deserialize(&serialized_text_1);
+        let serialized_text_2 = deserialized_tree.unwrap().serialize().unwrap();
 
         assert_eq!(serialized_text_1, serialized_text_2);
     }
 
     #[test]
-    fn test_deserialize_failed() {
-        let input = "00001t1r01y1w01e01W01d1c001o1!01 01m1l03".to_string();
-        let tree = HuffmanTree::deserialize(input);
+    fn test_serialize_bytes() {
+        let value = "Welcome to my world!!!".as_bytes();
+        let tree = HuffmanTree::from(value);
+        let result = tree.serialize().unwrap();
+
+        assert_eq!(result, b"00001W1c01d1r001t1w1 001!1o001y1e01l1m");
+
+        let tree = HuffmanTree::from(&[0xffu8, 0x00, 0xff]);
+        let result = tree.serialize().unwrap();
+
+        assert_eq!(result, vec![b'0', b'1', 0x00, b'1', 0xff]);
+    }
+
+    #[test]
+    fn test_serialize_any_symbol() {
+        let values: Vec<u16> = vec![0x0102, 0x0304, 0x0102];
+        let tree = HuffmanTree::from(&values);
+        let serialized = tree.serialize().unwrap();
+
+        assert_eq!(serialized, vec![b'0', b'1', 0x03, 0x04, b'1', 0x01, 0x02]);
+        let result = HuffmanTree::<u16>::deserialize(&serialized).unwrap();
+        assert_eq!(result.get_encoding_map(), tree.get_encoding_map());
+        assert!(HuffmanTree::<u16>::deserialize(&serialized[..6]).is_err());
+
+        let words: Vec<String> = "the cat and the hat and the bat"
+            .split(' ')
+            .map(String::from)
+            .collect();
+        let tree = HuffmanTree::from(&words);
+        let serialized = tree.serialize().unwrap();
+        let result = HuffmanTree::<String>::deserialize(&serialized).unwrap();
+
+        assert_eq!(result.serialize().unwrap(), serialized);
+        assert_eq!(result.get_encoding_map(), tree.get_encoding_map());
+
+        let canonical = tree.to_canonical().unwrap();
+        let lengths = canonical.serialize_code_lengths().unwrap();
+        let result = HuffmanTree::<String>::deserialize_code_lengths(&lengths).unwrap();
+        assert_eq!(result.serialize().unwrap(), canonical.serialize().unwrap());
+    }
+
+    #[test]
+    fn test_deserialize_bytes_successful() {
+        let input = b"00001t1r01y1w01e01W01d1c001o1!01 01m1l";
+        let tree = HuffmanTree::<u8>::deserialize(input);
+
+        assert_eq!(tree.unwrap().serialize().unwrap(), input);
+
+        let input = vec![b'0', b'1', 0x00, b'1', 0xff];
+        let tree = HuffmanTree::<u8>::deserialize(&input);
+
+        assert_eq!(tree.unwrap().serialize().unwrap(), input);
+
+        let tree = HuffmanTree::<u8>::deserialize(&[]);
+
+        assert!(tree.unwrap().get_root().is_none());
+    }
+
+    #[test]
+    fn test_deserialize_bytes_failed() {
+        let tree = HuffmanTree::<u8>::deserialize(b"00001t1r01y1w01e01W01d1c001o1!01 01m1l03");
         assert!(tree.is_err());
 
-        let input = "00001t1r01y1w01e01W01d1c001o1!01 0".to_string();
-        let tree = HuffmanTree::deserialize(input);
+        let tree = HuffmanTree::<u8>::deserialize(b"00001t1r01y1w01e01W01d1c001o1!01 01m1");
         assert!(tree.is_err());
 
-        let input = "00001t1r01y1w01e01W01d1c001o1!01 01m1".to_string();
-        let tree = HuffmanTree::deserialize(input);
+        let tree = HuffmanTree::<u8>::deserialize(&[b'0', b'2', 0x00]);
         assert!(tree.is_err());
 
-        let input = "00001t1r01y1w01e01W01d1c001o1!1.1,".to_string();
-        let tree = HuffmanTree::deserialize(input);
+        let tree = HuffmanTree::<u8>::deserialize(&vec![b'0'; 1_000_000]);
+        assert!(tree.is_err());
+
+        // The same leaf twice would give one symbol two codes.
+        for input in [&b"01a1a"[..], b"001a1b1a", b"01a01b1a"] {
+            let tree = HuffmanTree::<u8>::deserialize(input);
+            assert_eq!(
+                tree.err(),
+                Some(HuffmanError::cannot_deserialize_tree()),
+                "{:?}",
+                input
+            );
+        }
+    }
+
+    #[test]
+    fn test_deserialize_depth_limit() {
+        let chain = |depth: usize| -> Vec<u8> {
+            let mut bytes: Vec<u8> = (0..depth).flat_map(|i| [b'0', b'1', i as u8]).collect();
+            bytes.truncate(bytes.len() - 2);
+            bytes.extend_from_slice(&[b'1', 0xfe, b'1', 0xff]);
+            bytes
+        };
+
+        let tree = HuffmanTree::<u8>::deserialize(&chain(HuffmanTree::<u8>::MAX_CODE_LENGTH));
+        assert!(tree.is_ok());
+        let tree = HuffmanTree::<u8>::deserialize(&chain(HuffmanTree::<u8>::MAX_CODE_LENGTH + 1));
+        assert!(tree.is_err());
+
+        let tree = HuffmanTree::<char>::deserialize("0".repeat(1_000_000).as_bytes());
+        assert!(tree.is_err());
+        let depth = HuffmanTree::<char>::MAX_CODE_LENGTH + 1;
+        let symbols: Vec<char> = ('a'..).take(depth + 1).collect();
+        let mut text: String = symbols[..depth]
+            .iter()
+            .flat_map(|&symbol| ['0', '1', symbol])
+            .collect();
+        text.extend(['1', symbols[depth]]);
+        let tree = HuffmanTree::<char>::deserialize(text.as_bytes());
         assert!(tree.is_err());
+    }
+
+    /// A chain `depth` parents deep, each with a leaf on the left; the last
+    /// symbol is reached by `depth` right turns.
+    fn deep_tree<T>(symbols: &[T], depth: usize) -> HuffmanTree<T>
+    where
+        T: Eq + Hash + Copy + Ord + ToString,
+        HuffmanNode<T>: ToString,
+    {
+        let mut nodes = Arena::new();
+        let leaf = |nodes: &mut Arena<HuffmanNode<T>>, level: usize| {
+            nodes.push(
+                HuffmanNode::new(1, Some(symbols[level % symbols.len()])),
+                None,
+                None,
+            )
+        };
+        let mut node = leaf(&mut nodes, 0);
+        for level in 1..=depth {
+            let left = leaf(&mut nodes, level);
+            node = HuffmanTree::combine(&mut nodes, Some(left), Some(node));
+        }
+
+        HuffmanTree {
+            nodes,
+            root: Some(node),
+        }
+    }
+
+    #[test]
+    fn test_deep_tree() {
+        let depth = 100_000;
+        let tree = deep_tree(b"xy", depth);
+        let all_right = PackedBits::new(vec![0xff; depth / 8], depth).unwrap();
+        let serialized = tree.serialize().unwrap();
+
+        assert_eq!(serialized.len(), depth * 3 + 2);
+        assert_eq!(serialized[..6], *b"01x01y");
+        assert_eq!(tree.decode_by_path(&mut all_right.reader()), Ok(b'x'));
+        assert_eq!(
+            tree.get_encoding_map().err(),
+            Some(HuffmanError::invalid_huffman_tree())
+        );
+        assert!(HuffmanTree::<u8>::deserialize(&serialized).is_err());
+
+        let tree = deep_tree(&['é', 'a'], depth);
+        let serialized = tree.serialize().unwrap();
+        let text = std::str::from_utf8(&serialized).unwrap();
+
+        assert_eq!(text.chars().count(), depth * 3 + 2);
+        assert!(text.starts_with("01é01a"));
+        assert_eq!(tree.decode_by_path(&mut all_right.reader()), Ok('é'));
+        assert!(HuffmanTree::<char>::deserialize(&serialized).is_err());
+    }
+
+    #[test]
+    fn test_deep_tree_roundtrip() {
+        let depth = HuffmanTree::<u8>::MAX_CODE_LENGTH;
+        let symbols: Vec<u8> = (0..=u8::MAX).take(depth).collect();
+        let tree = deep_tree(&symbols, depth - 1);
+        let deserialized = HuffmanTree::<u8>::deserialize(&tree.serialize().unwrap()).unwrap();
+        let encoding_map = deserialized.get_encoding_map().unwrap();
+
+        assert_eq!(deserialized.serialize().unwrap(), tree.serialize().unwrap());
+        assert_eq!(encoding_map.len(), depth);
+        assert_eq!(encoding_map[&0].len, depth - 1);
+        assert_eq!(encoding_map[&symbols[depth - 1]].len, 1);
+
+        let symbols: Vec<char> = ('é'..).take(depth).collect();
+        let tree = deep_tree(&symbols, depth - 1);
+        let deserialized = HuffmanTree::<char>::deserialize(&tree.serialize().unwrap()).unwrap();
+
+        assert_eq!(deserialized.serialize().unwrap(), tree.serialize().unwrap());
+    }
+
+    #[test]
+    fn test_deserialize_failed() {
+        let inputs = [
+            "00001t1r01y1w01e01W01d1c001o1!01 01m1l03",
+            "00001t1r01y1w01e01W01d1c001o1!01 0",
+            "00001t1r01y1w01e01W01d1c001o1!01 01m1",
+            "00001t1r01y1w01e01W01d1c001o1!1.1,",
+            "00001t1r01y1w01e01W01d1c00",
+        ];
 
-        let input = "00001t1r01y1w01e01W01d1c00".to_string();
-        let tree = HuffmanTree::deserialize(input);
+        for input in inputs {
+            let tree = HuffmanTree::<char>::deserialize(input.as_bytes());
+            assert!(tree.is_err());
+        }
+
+        // Truncated trees whose parents are left without a right child.
+        for input in [
+            "001a1b",
+            "0001a1b1c",
+            "01a0",
+            "0",
+            "00001t1r01y1w01e01W01d1c001o1!01 01m",
+        ] {
+            let tree = HuffmanTree::<char>::deserialize(input.as_bytes());
+            assert_eq!(
+                tree.err(),
+                Some(HuffmanError::cannot_deserialize_tree()),
+                "{:?}",
+                input
+            );
+        }
+        assert!(HuffmanTree::<char>::deserialize(b"01a").is_ok());
+
+        let tree = HuffmanTree::<char>::deserialize(&[b'0', b'1', b'a', b'1', 0xff]);
         assert!(tree.is_err());
     }
+
+    #[test]
+    fn test_from_code_lengths() {
+        let tree =
+            HuffmanTree::from_code_lengths(&[('d', 3), ('a', 1), ('c', 3), ('b', 2)]).unwrap();
+
+        let result = tree.get_encoding_map().unwrap();
+        let expect: HashMap<char, Code> = HashMap::from([
+            ('a', Code::new(0b0, 1)),
+            ('b', Code::new(0b10, 2)),
+            ('c', Code::new(0b110, 3)),
+            ('d', Code::new(0b111, 3)),
+        ]);
+
+        assert_eq!(result, expect);
+    }
+
+    #[test]
+    fn test_from_code_lengths_failed() {
+        assert!(HuffmanTree::from_code_lengths(&[('a', 1), ('b', 2)]).is_err());
+        assert!(HuffmanTree::from_code_lengths(&[('a', 1), ('b', 1), ('c', 1)]).is_err());
+        assert!(HuffmanTree::from_code_lengths(&[('a', 1), ('a', 1)]).is_err());
+        assert!(HuffmanTree::from_code_lengths(&[('a', 0), ('b', 1)]).is_err());
+        assert!(HuffmanTree::from_code_lengths(&[
+            ('a', 1),
+            ('b', 2),
+            ('c', 3),
+            ('d', 3),
+            ('e', 3)
+        ])
+        .is_err());
+    }
+
+    #[test]
+    fn test_to_canonical() {
+        let tree = HuffmanTree::from("Welcome to my world!!!".as_bytes());
+        let canonical = tree.to_canonical().unwrap();
+
+        assert_eq!(
+            canonical.get_code_lengths().unwrap(),
+            tree.get_code_lengths().unwrap()
+        );
+        assert_eq!(
+            canonical.to_canonical().unwrap().serialize().unwrap(),
+            canonical.serialize().unwrap()
+        );
+    }
+
+    #[test]
+    fn test_code_lengths_roundtrip() {
+        let tree = HuffmanTree::from(&"Xin chào thế giới 🦀".chars().collect::<Vec<char>>())
+            .to_canonical()
+            .unwrap();
+        let serialized = tree.serialize_code_lengths().unwrap();
+        let result = HuffmanTree::<char>::deserialize_code_lengths(&serialized).unwrap();
+        assert_eq!(result.serialize().unwrap(), tree.serialize().unwrap());
+
+        let tree = HuffmanTree::from("Welcome to my world!!!".as_bytes())
+            .to_canonical()
+            .unwrap();
+        let serialized = tree.serialize_code_lengths().unwrap();
+        assert_eq!(serialized.len(), 2 * 13);
+        let result = HuffmanTree::<u8>::deserialize_code_lengths(&serialized).unwrap();
+        assert_eq!(result.serialize().unwrap(), tree.serialize().unwrap());
+    }
+
+    #[test]
+    fn test_serialize_code_lengths_failed() {
+        let tree = HuffmanTree::from("Welcome to my world!!!".as_bytes());
+
+        assert_ne!(
+            tree.to_canonical().unwrap().get_encoding_map().unwrap(),
+            tree.get_encoding_map().unwrap()
+        );
+        assert_eq!(
+            tree.serialize_code_lengths(),
+            Err(HuffmanError::cannot_serialize_tree())
+        );
+        assert_eq!(
+            HuffmanTree::<u8>::default().serialize_code_lengths(),
+            Err(HuffmanError::invalid_huffman_tree())
+        );
+    }
+
+    #[test]
+    fn test_deserialize_code_lengths_failed() {
+        assert!(HuffmanTree::<u8>::deserialize_code_lengths(&[b'a', 1, b'b']).is_err());
+        assert!(HuffmanTree::<u8>::deserialize_code_lengths(&[b'a', 1, b'b', 2]).is_err());
+        assert!(HuffmanTree::<char>::deserialize_code_lengths(&[b'a', 1, 0xff, 1]).is_err());
+        assert!(HuffmanTree::<char>::deserialize_code_lengths(b"a").is_err());
+    }
+
+    #[test]
+    fn test_from_with_max_length() {
+        let values: Vec<char> = "abccddddeeeeeeee".chars().collect();
+
+        let tree = HuffmanTree::from_with_max_length(&values, 3).unwrap();
+        assert_eq!(
+            tree.get_code_lengths().unwrap(),
+            vec![('e', 1), ('a', 3), ('b', 3), ('c', 3), ('d', 3)]
+        );
+
+        let tree = HuffmanTree::from_with_max_length(&values, 4).unwrap();
+        assert_eq!(
+            tree.serialize().unwrap(),
+            HuffmanTree::from(&values).serialize().unwrap()
+        );
+
+        assert!(HuffmanTree::from_with_max_length(&values, 2).is_err());
+        assert!(HuffmanTree::from_with_max_length(&values, 0).is_err());
+    }
+
+    #[test]
+    fn test_from_with_max_length_skewed() {
+        // Fibonacci frequencies make the plain Huffman tree as deep as possible.
+        let mut values: Vec<u8> = Vec::new();
+        let (mut a, mut b) = (1usize, 1usize);
+        for symbol in 0..24u8 {
+            values.extend(std::iter::repeat_n(symbol, a));
+            (a, b) = (b, a + b);
+        }
+        let cost = |tree: &HuffmanTree<u8>| -> usize {
+            let lengths: HashMap<u8, usize> =
+                tree.get_code_lengths().unwrap().into_iter().collect();
+            values.iter().map(|v| lengths[v]).sum()
+        };
+
+        let unlimited = HuffmanTree::from(&values);
+        let unlimited_longest = unlimited.get_code_lengths().unwrap().last().unwrap().1;
+        assert_eq!(unlimited_longest, 23);
+
+        for max_length in [5, 8, 12, 16] {
+            let tree = HuffmanTree::from_with_max_length(&values, max_length).unwrap();
+            let lengths = tree.get_code_lengths().unwrap();
+
+            assert_eq!(lengths.len(), 24);
+            assert!(lengths.iter().all(|&(_, length)| length <= max_length));
+            assert!(cost(&tree) >= cost(&unlimited));
+        }
+
+        let tight = HuffmanTree::from_with_max_length(&values, 5).unwrap();
+        let loose = HuffmanTree::from_with_max_length(&values, 12).unwrap();
+        assert!(cost(&tight) > cost(&loose));
+    }
+
+    /// Smallest total cost of any complete prefix code with lengths at most
+    /// `max_length`, for counts sorted in ascending order.
+    fn brute_force_cost(counts: &[usize], max_length: usize) -> usize {
+        // Lengths never grow with the count, so only non-increasing
+        // assignments need to be tried; `kraft` is in units of 2^-max_length.
+        fn search(
+            counts: &[usize],
+            longest: usize,
+            max_length: usize,
+            kraft: usize,
+        ) -> Option<usize> {
+            match counts.split_first() {
+                None if kraft == 1 << max_length => Some(0),
+                None => None,
+                Some((&count, rest)) => (1..=longest)
+                    .filter(|&length| kraft + (1 << (max_length - length)) <= 1 << max_length)
+                    .filter_map(|length| {
+                        let kraft = kraft + (1 << (max_length - length));
+                        search(rest, length, max_length, kraft).map(|cost| cost + count * length)
+                    })
+                    .min(),
+            }
+        }
+        search(counts, max_length, max_length, 0).unwrap()
+    }
+
+    #[test]
+    fn test_from_with_max_length_optimal() {
+        let fibonacci: Vec<(u8, usize)> = vec![
+            (0, 1),
+            (1, 1),
+            (2, 2),
+            (3, 3),
+            (4, 5),
+            (5, 8),
+            (6, 13),
+            (7, 21),
+        ];
+        let cost = |tree: &HuffmanTree<u8>, counts: &[(u8, usize)]| -> usize {
+            let lengths: HashMap<u8, usize> =
+                tree.get_code_lengths().unwrap().into_iter().collect();
+            counts
+                .iter()
+                .map(|(symbol, count)| lengths[symbol] * count)
+                .sum()
+        };
+
+        // Unlimited, the tree is 7 deep and costs 132; each limit forces
+        // package-merge and costs one more bit per step down.
+        let expected = [
+            (
+                4,
+                vec![
+                    (0, 4),
+                    (1, 4),
+                    (2, 4),
+                    (3, 4),
+                    (4, 3),
+                    (5, 3),
+                    (6, 2),
+                    (7, 2),
+                ],
+            ),
+            (
+                5,
+                vec![
+                    (0, 5),
+                    (1, 5),
+                    (2, 4),
+                    (3, 3),
+                    (4, 3),
+                    (5, 3),
+                    (6, 2),
+                    (7, 2),
+                ],
+            ),
+            (
+                6,
+                vec![
+                    (0, 6),
+                    (1, 6),
+                    (2, 5),
+                    (3, 4),
+                    (4, 3),
+                    (5, 2),
+                    (6, 2),
+                    (7, 2),
+                ],
+            ),
+        ];
+        for (max_length, lengths) in expected {
+            let tree = HuffmanTree::from_frequencies_with_max_length(fibonacci.clone(), max_length)
+                .unwrap();
+            let mut actual = tree.get_code_lengths().unwrap();
+            actual.sort();
+            assert_eq!(actual, lengths);
+
+            let counts: Vec<usize> = fibonacci.iter().map(|&(_, count)| count).collect();
+            assert_eq!(
+                cost(&tree, &fibonacci),
+                brute_force_cost(&counts, max_length)
+            );
+        }
+
+        // Uneven counts over a few more symbols, against every limit that fits them.
+        let counts = [1usize, 1, 1, 2, 4, 4, 9, 15, 40, 77];
+        let weights: Vec<(u8, usize)> = (0u8..).zip(counts).collect();
+        for max_length in 4..=9 {
+            let tree =
+                HuffmanTree::from_frequencies_with_max_length(weights.clone(), max_length).unwrap();
+            assert_eq!(cost(&tree, &weights), brute_force_cost(&counts, max_length));
+        }
+    }
 }
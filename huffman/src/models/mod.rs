@@ -1,2 +1,6 @@
+pub mod adaptive_huffman_tree;
+pub mod decoding_table;
+pub mod frequency_table;
 pub mod huffman_tree;
 pub mod node;
+pub mod symbol;
@@ -1,110 +1,201 @@
-#[derive(Clone)]
+use std::ops::Index;
+
+/// Position of a node in its `Arena`.
+pub type NodeId = usize;
+
+/// A node of a binary tree stored in an `Arena`. Its parent and children are
+/// referred to by their position in the same arena.
+#[derive(Clone, Debug, PartialEq)]
 pub struct Node<T> {
     value: T,
-    left: Option<Box<Node<T>>>,
-    right: Option<Box<Node<T>>>,
+    parent: Option<NodeId>,
+    left: Option<NodeId>,
+    right: Option<NodeId>,
 }
 
-impl<T> Node<T>
-where
-    T: ToString,
-{
-    pub fn new(value: T, left: Option<Box<Node<T>>>, right: Option<Box<Node<T>>>) -> Self {
-        Self {
-            value: value,
-            left: left,
-            right: right,
-        }
+impl<T> Node<T> {
+    pub fn get_value(&self) -> &T {
+        &self.value
     }
 
-    fn get_value_str(&self) -> String {
-        self.value.to_string()
+    pub fn parent(&self) -> Option<NodeId> {
+        self.parent
     }
 
-    pub fn get_value(&self) -> &T {
-        &self.value
+    pub fn left(&self) -> Option<NodeId> {
+        self.left
     }
 
-    pub fn left(&self) -> Option<&Node<T>> {
-        self.left.as_deref()
+    pub fn right(&self) -> Option<NodeId> {
+        self.right
     }
 
-    pub fn right(&self) -> Option<&Node<T>> {
-        self.right.as_deref()
+    pub fn is_leaf(&self) -> bool {
+        self.left.is_none() && self.right.is_none()
     }
 
-    pub fn append_left_by_value(&mut self, value: T) {
-        let new_node = Some(Box::new(Self::new(value, None, None)));
-        self.left = new_node;
+    fn has_child(&self) -> bool {
+        self.left.is_some() || self.right.is_some()
     }
+}
 
-    pub fn append_left_by_node(&mut self, node: Node<T>) {
-        self.left = Some(Box::new(node));
+/// Binary tree nodes kept in a single `Vec`: a whole tree is one allocation,
+/// cloning it copies one buffer, and every node knows its parent. Nodes are
+/// only added by the trees of this crate, over children the arena already
+/// holds, and are never removed.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Arena<T> {
+    nodes: Vec<Node<T>>,
+}
+
+impl<T> Default for Arena<T> {
+    fn default() -> Self {
+        Self { nodes: Vec::new() }
     }
+}
 
-    pub fn append_right_by_value(&mut self, value: T) {
-        let new_node = Some(Box::new(Self::new(value, None, None)));
-        self.right = new_node;
+impl<T> Arena<T> {
+    pub fn new() -> Self {
+        Self::default()
     }
 
-    pub fn append_right_by_node(&mut self, node: Node<T>) {
-        self.right = Some(Box::new(node));
+    pub fn len(&self) -> usize {
+        self.nodes.len()
     }
 
-    pub fn is_leaf(&self) -> bool {
-        self.left.is_none() && self.right.is_none()
+    pub fn is_empty(&self) -> bool {
+        self.nodes.is_empty()
     }
 
-    fn has_child(&self) -> bool {
-        self.left.is_some() || self.right.is_some()
+    pub fn get(&self, id: NodeId) -> Option<&Node<T>> {
+        self.nodes.get(id)
+    }
+
+    /// Every node with its id, in the order they were added.
+    pub fn iter(&self) -> impl Iterator<Item = (NodeId, &Node<T>)> {
+        self.nodes.iter().enumerate()
+    }
+
+    /// Adds a node over `left` and `right`, which become its children, and
+    /// returns its id.
+    pub(crate) fn push(&mut self, value: T, left: Option<NodeId>, right: Option<NodeId>) -> NodeId {
+        let id = self.nodes.len();
+
+        left.into_iter()
+            .chain(right)
+            .for_each(|child| self.nodes[child].parent = Some(id));
+        self.nodes.push(Node {
+            value,
+            parent: None,
+            left,
+            right,
+        });
+        id
+    }
+
+    /// Adds a leaf as the left child of `parent`, replacing its previous one.
+    #[cfg(test)]
+    fn append_left(&mut self, parent: NodeId, value: T) -> NodeId {
+        let id = self.push(value, None, None);
+
+        if let Some(previous) = self.nodes[parent].left.replace(id) {
+            self.nodes[previous].parent = None;
+        }
+        self.nodes[id].parent = Some(parent);
+        id
+    }
+
+    /// Adds a leaf as the right child of `parent`, replacing its previous one.
+    #[cfg(test)]
+    fn append_right(&mut self, parent: NodeId, value: T) -> NodeId {
+        let id = self.push(value, None, None);
+
+        if let Some(previous) = self.nodes[parent].right.replace(id) {
+            self.nodes[previous].parent = None;
+        }
+        self.nodes[id].parent = Some(parent);
+        id
     }
 
-    pub fn represent_tree(&self) {
-        println!("{}", self.get_value_str());
-        self.represent_children("".to_string());
-        println!("");
-    }
-    fn represent_children(&self, prefix: String) {
-        let left = self.left();
-        let right = self.right();
-
-        if !self.has_child() {
-            return;
-        } else {
-            print!("{}", prefix);
-            if right.is_some() {
-                if left.is_some() {
-                    print!("├── ");
-                } else {
-                    print!("└── ");
-                }
-            }
+    /// Number of edges between `id` and the root above it.
+    pub fn depth(&self, id: NodeId) -> usize {
+        let mut depth = 0;
+        let mut node = &self.nodes[id];
+
+        while let Some(parent) = node.parent {
+            node = &self.nodes[parent];
+            depth += 1;
         }
+        depth
+    }
+}
+
+impl<T> Index<NodeId> for Arena<T> {
+    type Output = Node<T>;
+
+    fn index(&self, id: NodeId) -> &Node<T> {
+        &self.nodes[id]
+    }
+}
+
+impl<T> Arena<T>
+where
+    T: ToString,
+{
+    pub fn represent_tree(&self, root: NodeId) {
+        println!("{}", self.nodes[root].value.to_string());
+        print!("{}", self.represent_children(root));
+        println!();
+    }
 
-        match right {
-            Some(right) => {
-                let new_prefix = prefix.clone()
-                    + (if left.is_some() && right.has_child() {
-                        "│   "
-                    } else {
-                        "    "
-                    });
-                println!("{}", right.get_value_str());
-                right.represent_children(new_prefix);
-            }
-            None => {}
+    /// Draws every descendant of `root` on its own line, right child first.
+    /// Lines still to be drawn are kept on an explicit stack.
+    fn represent_children(&self, root: NodeId) -> String {
+        let mut result = String::new();
+        // (node, prefix of its own line, its connector, prefix of its children)
+        let mut stack: Vec<(NodeId, String, &str, String)> = Vec::new();
+
+        self.push_children(root, "", &mut stack);
+        while let Some((id, prefix, connector, children_prefix)) = stack.pop() {
+            result.push_str(&prefix);
+            result.push_str(connector);
+            result.push_str(&self.nodes[id].value.to_string());
+            result.push('\n');
+            self.push_children(id, &children_prefix, &mut stack);
         }
+        result
+    }
 
-        match left {
-            Some(left) => {
-                if right.is_some() {
-                    print!("{}", prefix.clone());
-                }
-                print!("└── ");
-                println!("{}", left.get_value_str());
-                left.represent_children(prefix + "    ");
-            }
-            None => {}
+    /// Pushes the lines of the children of `id` so the right one is drawn first.
+    fn push_children(
+        &self,
+        id: NodeId,
+        prefix: &str,
+        stack: &mut Vec<(NodeId, String, &'static str, String)>,
+    ) {
+        let node = &self.nodes[id];
+
+        if let Some(left) = node.left {
+            stack.push((
+                left,
+                prefix.to_string(),
+                "└── ",
+                prefix.to_string() + "    ",
+            ));
+        }
+        if let Some(right) = node.right {
+            let (connector, indent) = match node.left {
+                Some(_) if self.nodes[right].has_child() => ("├── ", "│   "),
+                Some(_) => ("├── ", "    "),
+                None => ("└── ", "    "),
+            };
+
+            stack.push((
+                right,
+                prefix.to_string(),
+                connector,
+                prefix.to_string() + indent,
+            ));
         }
     }
 }
@@ -115,125 +206,161 @@ mod tests {
 
     #[test]
     fn test_init_char_node_successful() {
-        let left_node = Node::new('a', None, None);
-        let right_node = Node::new('b', None, None);
-        let _root_node = Node::new('c', Some(Box::new(left_node)), Some(Box::new(right_node)));
+        let mut arena = Arena::new();
+        let left = arena.push('a', None, None);
+        let right = arena.push('b', None, None);
+        let root = arena.push('c', Some(left), Some(right));
+
+        assert_eq!(arena.len(), 3);
+        assert_eq!(arena[root].left(), Some(left));
+        assert_eq!(arena[root].right(), Some(right));
     }
 
     #[test]
     fn test_init_string_node_successful() {
-        let left_node = Node::new("left".to_string(), None, None);
-        let right_node = Node::new("right".to_string(), None, None);
-        let _root_node = Node::new(
-            "root".to_string(),
-            Some(Box::new(left_node)),
-            Some(Box::new(right_node)),
-        );
+        let mut arena = Arena::new();
+        let left = arena.push("left".to_string(), None, None);
+        let right = arena.push("right".to_string(), None, None);
+        let root = arena.push("root".to_string(), Some(left), Some(right));
+
+        assert_eq!(arena[root].get_value(), "root");
+        assert_eq!(arena.get(3), None);
     }
 
     #[test]
     fn test_get_value() {
         let value = "Hello world!";
-        let node = Node::new(value, None, None);
+        let mut arena = Arena::new();
+        let node = arena.push(value, None, None);
 
-        assert_eq!(*node.get_value(), value);
+        assert_eq!(*arena[node].get_value(), value);
     }
 
     #[test]
     fn test_left_nothing() {
-        let node = Node::new(true, None, None);
+        let mut arena = Arena::new();
+        let node = arena.push(true, None, None);
 
-        assert!(node.left().is_none());
+        assert!(arena[node].left().is_none());
     }
 
     #[test]
     fn test_left() {
-        let left_node = Node::new(true, None, None);
-        let node = Node::new(true, Some(Box::new(left_node)), None);
+        let mut arena = Arena::new();
+        let left = arena.push(true, None, None);
+        let node = arena.push(true, Some(left), None);
 
-        assert!(node.left().is_some());
-        assert_eq!(*node.left().unwrap().get_value(), true);
-        assert!(node.left().unwrap().left().is_none());
+        assert_eq!(arena[node].left(), Some(left));
+        assert!(*arena[left].get_value());
+        assert!(arena[left].left().is_none());
     }
 
     #[test]
     fn test_right_nothing() {
-        let node = Node::new(true, None, None);
+        let mut arena = Arena::new();
+        let node = arena.push(true, None, None);
 
-        assert!(node.right().is_none());
+        assert!(arena[node].right().is_none());
     }
 
     #[test]
     fn test_right() {
-        let right_node = Node::new(true, None, None);
-        let node = Node::new(true, None, Some(Box::new(right_node)));
+        let mut arena = Arena::new();
+        let right = arena.push(true, None, None);
+        let node = arena.push(true, None, Some(right));
 
-        assert!(node.right().is_some());
-        assert_eq!(*node.right().unwrap().get_value(), true);
-        assert!(node.right().unwrap().right().is_none());
+        assert_eq!(arena[node].right(), Some(right));
+        assert!(*arena[right].get_value());
+        assert!(arena[right].right().is_none());
     }
 
     #[test]
-    fn test_append_left_by_value() {
-        let mut node = Node::new(12, None, None);
-
-        let left_value = 34;
-        node.append_left_by_value(left_value);
-        assert!(node.left().is_some());
-        assert_eq!(*node.left().unwrap().get_value(), left_value);
-
-        let new_left_value = 56;
-        node.append_left_by_value(new_left_value);
-        assert!(node.left().is_some());
-        assert_eq!(*node.left().unwrap().get_value(), new_left_value);
+    fn test_parent() {
+        let mut arena = Arena::new();
+        let left = arena.push(1, None, None);
+        let right = arena.push(2, None, None);
+        let root = arena.push(3, Some(left), Some(right));
+
+        assert_eq!(arena[left].parent(), Some(root));
+        assert_eq!(arena[right].parent(), Some(root));
+        assert_eq!(arena[root].parent(), None);
+        assert_eq!(arena.depth(left), 1);
+        assert_eq!(arena.depth(root), 0);
     }
 
     #[test]
-    fn test_append_left_by_node() {
-        let mut node = Node::new(12, None, None);
+    fn test_append_left() {
+        let mut arena = Arena::new();
+        let node = arena.push(12, None, None);
 
         let left_value = 34;
-        let left_node = Node::new(left_value, None, None);
-        node.append_left_by_node(left_node);
-        assert!(node.left().is_some());
-        assert_eq!(*node.left().unwrap().get_value(), left_value);
+        let left = arena.append_left(node, left_value);
+        assert_eq!(arena[node].left(), Some(left));
+        assert_eq!(*arena[left].get_value(), left_value);
+        assert_eq!(arena[left].parent(), Some(node));
 
         let new_left_value = 56;
-        let new_left_node = Node::new(new_left_value, None, None);
-        node.append_left_by_node(new_left_node);
-        assert!(node.left().is_some());
-        assert_eq!(*node.left().unwrap().get_value(), new_left_value);
+        let new_left = arena.append_left(node, new_left_value);
+        assert_eq!(arena[node].left(), Some(new_left));
+        assert_eq!(*arena[new_left].get_value(), new_left_value);
+        assert_eq!(arena[left].parent(), None);
     }
 
     #[test]
-    fn test_append_right_by_value() {
-        let mut node = Node::new(12, None, None);
+    fn test_append_right() {
+        let mut arena = Arena::new();
+        let node = arena.push(12, None, None);
 
         let right_value = 34;
-        node.append_right_by_value(right_value);
-        assert!(node.right().is_some());
-        assert_eq!(*node.right().unwrap().get_value(), right_value);
+        let right = arena.append_right(node, right_value);
+        assert_eq!(arena[node].right(), Some(right));
+        assert_eq!(*arena[right].get_value(), right_value);
+        assert_eq!(arena[right].parent(), Some(node));
 
         let new_right_value = 56;
-        node.append_right_by_value(new_right_value);
-        assert!(node.right().is_some());
-        assert_eq!(*node.right().unwrap().get_value(), new_right_value);
+        let new_right = arena.append_right(node, new_right_value);
+        assert_eq!(arena[node].right(), Some(new_right));
+        assert_eq!(*arena[new_right].get_value(), new_right_value);
+        assert_eq!(arena[right].parent(), None);
     }
 
     #[test]
-    fn test_append_right_by_node() {
-        let mut node = Node::new(12, None, None);
+    fn test_represent_children() {
+        let mut arena = Arena::new();
+        let c = arena.push("c", None, None);
+        let d = arena.push("d", None, None);
+        let b = arena.push("b", Some(c), Some(d));
+        let a = arena.push("a", None, None);
+        let root = arena.push("r", Some(a), Some(b));
+
+        assert_eq!(
+            arena.represent_children(root),
+            "├── b\n│   ├── d\n│   └── c\n└── a\n"
+        );
+        assert_eq!(arena.represent_children(a), "");
+    }
 
-        let right_value = 34;
-        let right_node = Node::new(right_value, None, None);
-        node.append_right_by_node(right_node);
-        assert!(node.right().is_some());
-        assert_eq!(*node.right().unwrap().get_value(), right_value);
+    #[test]
+    fn test_deep_tree() {
+        let depth = 100_000;
+        let mut arena = Arena::new();
+        let deepest = arena.push(0, None, None);
+        let mut node = deepest;
+        for level in 1..=depth {
+            let leaf = arena.push(0, None, None);
+            node = arena.push(level, Some(leaf), Some(node));
+        }
 
-        let new_right_value = 56;
-        let new_right_node = Node::new(new_right_value, None, None);
-        node.append_right_by_node(new_right_node);
-        assert!(node.right().is_some());
-        assert_eq!(*node.right().unwrap().get_value(), new_right_value);
+        assert_eq!(arena.depth(deepest), depth);
+        assert_eq!(arena[node].parent(), None);
+        assert_eq!(arena.clone(), arena);
+
+        // Prefixes grow with the depth, so a shorter chain keeps drawing quick.
+        let mut arena = Arena::new();
+        let mut node = arena.push(0, None, None);
+        for level in 1..=2_000 {
+            node = arena.push(level, None, Some(node));
+        }
+        assert_eq!(arena.represent_children(node).lines().count(), 2_000);
     }
 }
@@ -0,0 +1,113 @@
+use crate::errors::huffman_error::HuffmanError;
+use crate::utils::type_converting;
+
+/// A tree symbol that can be written to and read back from bytes, which is
+/// all `HuffmanTree` needs to serialize itself.
+pub trait Symbol: Sized {
+    /// Appends the encoded symbol to `out`, failing if the symbol cannot be
+    /// represented in the format.
+    fn encode(&self, out: &mut Vec<u8>) -> Result<(), HuffmanError>;
+
+    /// Decodes the symbol at the start of `bytes`, returning it with the
+    /// number of bytes read.
+    fn decode(bytes: &[u8]) -> Option<(Self, usize)>;
+}
+
+/// Integers are stored big-endian in their full width.
+macro_rules! impl_symbol_for_int {
+    ($($int:ty),*) => {$(
+        impl Symbol for $int {
+            fn encode(&self, out: &mut Vec<u8>) -> Result<(), HuffmanError> {
+                out.extend_from_slice(&self.to_be_bytes());
+                Ok(())
+            }
+
+            fn decode(bytes: &[u8]) -> Option<(Self, usize)> {
+                const WIDTH: usize = std::mem::size_of::<$int>();
+
+                match bytes.get(..WIDTH).map(<[u8; WIDTH]>::try_from) {
+                    Some(Ok(be_bytes)) => Some((<$int>::from_be_bytes(be_bytes), WIDTH)),
+                    _ => None,
+                }
+            }
+        }
+    )*};
+}
+
+impl_symbol_for_int!(u8, u16, u32, u64, u128, i8, i16, i32, i64, i128);
+
+/// A `char` is stored as UTF-8.
+impl Symbol for char {
+    fn encode(&self, out: &mut Vec<u8>) -> Result<(), HuffmanError> {
+        let mut buffer = [0u8; 4];
+        out.extend_from_slice(self.encode_utf8(&mut buffer).as_bytes());
+        Ok(())
+    }
+
+    fn decode(bytes: &[u8]) -> Option<(Self, usize)> {
+        type_converting::read_utf8_char(bytes)
+    }
+}
+
+/// A `String` is stored as its UTF-8 length in a big-endian `u32`, followed
+/// by its UTF-8 bytes, so strings of 4 GiB or more cannot be encoded.
+impl Symbol for String {
+    fn encode(&self, out: &mut Vec<u8>) -> Result<(), HuffmanError> {
+        match u32::try_from(self.len()) {
+            Ok(len) => {
+                len.encode(out)?;
+                out.extend_from_slice(self.as_bytes());
+                Ok(())
+            }
+            Err(_) => Err(HuffmanError::cannot_serialize_tree()),
+        }
+    }
+
+    fn decode(bytes: &[u8]) -> Option<(Self, usize)> {
+        let (len, width) = match u32::decode(bytes) {
+            Some((len, width)) => (usize::try_from(len).ok()?, width),
+            None => return None,
+        };
+        let end = width.checked_add(len)?;
+
+        match bytes.get(width..end).map(std::str::from_utf8) {
+            Some(Ok(s)) => Some((s.to_string(), end)),
+            _ => None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn roundtrip<T: Symbol + PartialEq + std::fmt::Debug>(symbol: T, expect: &[u8]) {
+        let mut bytes = Vec::new();
+        symbol.encode(&mut bytes).unwrap();
+        assert_eq!(bytes, expect);
+
+        bytes.push(0xff);
+        assert_eq!(T::decode(&bytes), Some((symbol, expect.len())));
+        assert_eq!(T::decode(&expect[..expect.len() - 1]), None);
+    }
+
+    #[test]
+    fn test_roundtrip() {
+        roundtrip(0xabu8, &[0xab]);
+        roundtrip(0x1234u16, &[0x12, 0x34]);
+        roundtrip(-2i32, &[0xff, 0xff, 0xff, 0xfe]);
+        roundtrip(1u64, &[0, 0, 0, 0, 0, 0, 0, 1]);
+        roundtrip('a', b"a");
+        roundtrip('✓', "✓".as_bytes());
+        roundtrip("hé".to_string(), &[0, 0, 0, 3, b'h', 0xc3, 0xa9]);
+        roundtrip(String::new(), &[0, 0, 0, 0]);
+    }
+
+    #[test]
+    fn test_decode_failed() {
+        assert_eq!(u16::decode(&[]), None);
+        assert_eq!(char::decode(&[0xff]), None);
+        assert_eq!(String::decode(&[0, 0, 0, 1, 0xff]), None);
+        assert_eq!(String::decode(&[0xff, 0xff, 0xff, 0xff, b'a']), None);
+    }
+}
@@ -0,0 +1,25 @@
+//! The types most pipelines need, for a single glob import:
+//!
+//! ```
+//! use huffman::prelude::*;
+//! ```
+
+pub use crate::coding::adaptive_huffman::AdaptiveHuffmanCoding;
+pub use crate::coding::dictionary::HuffmanDictionary;
+pub use crate::coding::huffman::{
+    CanonicalHuffmanCoding, DecompressOptions, HuffmanCoding, HuffmanDecoding, HuffmanEncoding,
+    StandardHuffmanCoding, TreeFormat,
+};
+pub use crate::coding::interleaved::InterleavedHuffmanCoding;
+pub use crate::coding::parallel::{ParallelCoding, ParallelOptions};
+pub use crate::coding::stream::{HuffmanDecoder, HuffmanEncoder};
+pub use crate::errors::compression_error::{CompressionError, CompressionErrorKind};
+pub use crate::errors::file_error::{FileError, FileErrorKind};
+pub use crate::errors::huffman_error::{
+    DecodeProgress, ErrorPosition, HuffmanError, HuffmanErrorKind,
+};
+pub use crate::errors::Error;
+pub use crate::models::frequency_table::FrequencyTable;
+pub use crate::models::huffman_tree::HuffmanTree;
+pub use crate::models::symbol::Symbol;
+pub use crate::utils::bit_io::PackedBits;
@@ -0,0 +1,418 @@
+/// A prefix code of up to `Code::MAX_LEN` bits, stored right-aligned in `bits`
+/// with the first bit of the code as the most significant one.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct Code {
+    pub bits: u128,
+    pub len: usize,
+}
+
+impl Code {
+    pub const MAX_LEN: usize = u128::BITS as usize;
+
+    pub fn new(bits: u128, len: usize) -> Self {
+        Self { bits, len }
+    }
+
+    /// Appends one bit, or returns `None` if the code would exceed `MAX_LEN`.
+    pub fn push(&self, bit: bool) -> Option<Self> {
+        if self.len < Self::MAX_LEN {
+            Some(Self::new((self.bits << 1) | bit as u128, self.len + 1))
+        } else {
+            None
+        }
+    }
+
+    /// The first `len` bits of the code.
+    pub fn prefix(&self, len: usize) -> Self {
+        match self.len - len {
+            0 => *self,
+            rest => Self::new(self.bits >> rest, len),
+        }
+    }
+
+    /// The code without its first `len` bits.
+    pub fn suffix(&self, len: usize) -> Self {
+        match self.len - len {
+            0 => Self::default(),
+            rest => Self::new(self.bits & (u128::MAX >> (Self::MAX_LEN - rest)), rest),
+        }
+    }
+}
+
+/// Bits packed 8 per byte, most significant bit first. Unused bits at the
+/// end of the last byte are always zero.
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct PackedBits {
+    bytes: Vec<u8>,
+    len: usize,
+}
+
+impl PackedBits {
+    /// Returns `None` unless `bytes` holds exactly the `len` bits (plus at most
+    /// 7 unused bits).
+    pub fn new(mut bytes: Vec<u8>, len: usize) -> Option<Self> {
+        if bytes.len() != len.div_ceil(8) {
+            return None;
+        }
+        if let Some(last) = bytes.last_mut() {
+            *last &= 0xffu8 << ((8 - len % 8) % 8);
+        }
+        Some(Self { bytes, len })
+    }
+
+    /// Every bit of `bytes`, without padding.
+    pub fn from_bytes(bytes: Vec<u8>) -> Self {
+        let len = bytes.len() * 8;
+
+        Self { bytes, len }
+    }
+
+    pub fn as_bytes(&self) -> &[u8] {
+        &self.bytes
+    }
+
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// Number of unused bits at the end of the last byte.
+    pub fn padding(&self) -> usize {
+        self.bytes.len() * 8 - self.len
+    }
+
+    pub fn reader(&self) -> BitReader<'_> {
+        BitReader::new(&self.bytes, self.len)
+    }
+}
+
+impl FromIterator<bool> for PackedBits {
+    fn from_iter<I: IntoIterator<Item = bool>>(iter: I) -> Self {
+        let mut writer = BitWriter::new();
+
+        iter.into_iter()
+            .for_each(|bit| writer.write_bits(bit as u64, 1));
+        writer.finish()
+    }
+}
+
+/// Packs bits MSB-first through a 64-bit accumulator, so writing a code costs
+/// a couple of shifts instead of one allocation per bit.
+#[derive(Debug, Default)]
+pub struct BitWriter {
+    bytes: Vec<u8>,
+    acc: u64,
+    acc_len: usize,
+}
+
+impl BitWriter {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Number of bits written so far.
+    pub fn len(&self) -> usize {
+        self.bytes.len() * 8 + self.acc_len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Writes the lowest `len` bits of `value`, most significant first.
+    pub fn write_bits(&mut self, value: u64, len: usize) {
+        debug_assert!(len <= 64);
+
+        if len == 0 {
+            return;
+        }
+        let value = value & (u64::MAX >> (64 - len));
+        let free = 64 - self.acc_len;
+
+        if len <= free {
+            self.acc |= value << (free - len);
+            self.acc_len += len;
+        } else {
+            let rest = len - free;
+
+            self.acc |= value >> rest;
+            self.acc_len = 64;
+            self.flush_full_bytes();
+            self.acc = value << (64 - rest);
+            self.acc_len = rest;
+        }
+        self.flush_full_bytes();
+    }
+
+    pub fn write_code(&mut self, code: &Code) {
+        if code.len > 64 {
+            self.write_bits((code.bits >> 64) as u64, code.len - 64);
+            self.write_bits(code.bits as u64, 64);
+        } else {
+            self.write_bits(code.bits as u64, code.len);
+        }
+    }
+
+    /// Writes every bit of `bits`, which need not start on a byte boundary.
+    pub fn write_packed(&mut self, bits: &PackedBits) {
+        let full_bytes = bits.len() / 8;
+        let tail = bits.len() % 8;
+
+        bits.bytes[..full_bytes]
+            .iter()
+            .for_each(|&byte| self.write_bits(byte as u64, 8));
+        if tail > 0 {
+            self.write_bits((bits.bytes[full_bytes] >> (8 - tail)) as u64, tail);
+        }
+    }
+
+    pub fn finish(mut self) -> PackedBits {
+        let len = self.len();
+
+        while self.acc_len > 0 {
+            self.bytes.push((self.acc >> 56) as u8);
+            self.acc <<= 8;
+            self.acc_len = self.acc_len.saturating_sub(8);
+        }
+        PackedBits {
+            bytes: self.bytes,
+            len,
+        }
+    }
+
+    fn flush_full_bytes(&mut self) {
+        while self.acc_len >= 8 {
+            self.bytes.push((self.acc >> 56) as u8);
+            self.acc <<= 8;
+            self.acc_len -= 8;
+        }
+    }
+}
+
+/// Reads packed bits MSB-first, refilling a 64-bit accumulator a byte at a
+/// time so up to 56 bits can be peeked at once.
+pub struct BitReader<'a> {
+    bytes: &'a [u8],
+    len: usize,
+    pos: usize,
+    next_byte: usize,
+    acc: u64,
+    acc_len: usize,
+}
+
+impl<'a> BitReader<'a> {
+    pub const MAX_PEEK: usize = 56;
+
+    pub fn new(bytes: &'a [u8], len: usize) -> Self {
+        Self {
+            bytes,
+            len: len.min(bytes.len() * 8),
+            pos: 0,
+            next_byte: 0,
+            acc: 0,
+            acc_len: 0,
+        }
+    }
+
+    /// Reads the `len` bits starting at bit `start` of `bytes`. Positions are
+    /// still counted from the start of `bytes`.
+    pub fn with_range(bytes: &'a [u8], start: usize, len: usize) -> Self {
+        let end = start.saturating_add(len).min(bytes.len() * 8);
+        let start = start.min(end);
+        let mut reader = Self {
+            bytes,
+            len: end,
+            pos: start - start % 8,
+            next_byte: start / 8,
+            acc: 0,
+            acc_len: 0,
+        };
+
+        reader.consume(start % 8);
+        reader
+    }
+
+    /// Number of bits left to read.
+    pub fn remaining(&self) -> usize {
+        self.len - self.pos
+    }
+
+    /// Position of the next bit from the start of the input.
+    pub fn position(&self) -> usize {
+        self.pos
+    }
+
+    /// Returns the next `len` bits without consuming them. Bits past
+    /// `remaining()` are not masked: they are the bits that follow in the
+    /// input, which for a `with_range` reader belong to whatever comes after
+    /// the range, and only read as zero past the end of the input.
+    pub fn peek(&mut self, len: usize) -> u64 {
+        debug_assert!(len > 0 && len <= Self::MAX_PEEK);
+
+        self.refill();
+        self.acc >> (64 - len)
+    }
+
+    /// Skips `len` bits, which must not be more than `remaining()`.
+    pub fn consume(&mut self, len: usize) {
+        debug_assert!(len <= self.remaining());
+
+        self.refill();
+        let len = len.min(self.acc_len);
+        self.acc = self.acc.checked_shl(len as u32).unwrap_or(0);
+        self.acc_len -= len;
+        self.pos += len;
+    }
+
+    pub fn read_bit(&mut self) -> Option<bool> {
+        if self.remaining() == 0 {
+            return None;
+        }
+        let bit = self.peek(1) == 1;
+        self.consume(1);
+        Some(bit)
+    }
+
+    fn refill(&mut self) {
+        while self.acc_len <= Self::MAX_PEEK && self.next_byte < self.bytes.len() {
+            self.acc |= (self.bytes[self.next_byte] as u64) << (56 - self.acc_len);
+            self.acc_len += 8;
+            self.next_byte += 1;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_code() {
+        let code = Code::default().push(true).unwrap().push(false).unwrap();
+
+        assert_eq!(code, Code::new(0b10, 2));
+        assert_eq!(code.push(true), Some(Code::new(0b101, 3)));
+        assert_eq!(Code::new(0b1101, 4).prefix(3), Code::new(0b110, 3));
+        assert_eq!(Code::new(0b1101, 4).suffix(1), Code::new(0b101, 3));
+        assert_eq!(Code::new(0b1101, 4).suffix(4), Code::default());
+        assert_eq!(Code::new(u128::MAX, Code::MAX_LEN).push(true), None);
+    }
+
+    #[test]
+    fn test_bit_writer() {
+        let mut writer = BitWriter::new();
+
+        writer.write_bits(0b010, 3);
+        writer.write_bits(0b01, 2);
+        writer.write_bits(0, 3);
+        writer.write_bits(0xff, 8);
+        writer.write_bits(0xabcd_ef01_2345_6789, 64);
+        writer.write_bits(0b1, 1);
+        assert_eq!(writer.len(), 81);
+
+        let bits = writer.finish();
+        assert_eq!(
+            bits.as_bytes(),
+            [0x48, 0xff, 0xab, 0xcd, 0xef, 0x01, 0x23, 0x45, 0x67, 0x89, 0x80]
+        );
+        assert_eq!(bits.len(), 81);
+        assert_eq!(bits.padding(), 7);
+    }
+
+    #[test]
+    fn test_write_packed() {
+        let bits = PackedBits::new(vec![0x48, 0xc0], 10).unwrap();
+        let mut writer = BitWriter::new();
+
+        writer.write_bits(0b101, 3);
+        writer.write_packed(&bits);
+        writer.write_packed(&PackedBits::default());
+        writer.write_packed(&bits);
+
+        let expected: PackedBits = [
+            true, false, true, false, true, false, false, true, false, false, false, true, true,
+            false, true, false, false, true, false, false, false, true, true,
+        ]
+        .into_iter()
+        .collect();
+        assert_eq!(writer.finish(), expected);
+    }
+
+    #[test]
+    fn test_write_code() {
+        let mut writer = BitWriter::new();
+
+        writer.write_code(&Code::new(0b1, 1));
+        writer.write_code(&Code::new(1 << 99, 100));
+        writer.write_code(&Code::new(0b11, 2));
+
+        let bits = writer.finish();
+        let mut reader = bits.reader();
+        let read: Vec<bool> = std::iter::from_fn(|| reader.read_bit()).collect();
+
+        assert_eq!(read.len(), 103);
+        assert_eq!(
+            read.iter()
+                .enumerate()
+                .filter(|(_, &bit)| bit)
+                .map(|(i, _)| i)
+                .collect::<Vec<_>>(),
+            vec![0, 1, 101, 102]
+        );
+    }
+
+    #[test]
+    fn test_bit_reader() {
+        let bits = PackedBits::new(vec![0x48, 0xff, 0x00, 0xa0], 27).unwrap();
+        let mut reader = bits.reader();
+
+        assert_eq!(reader.peek(3), 0b010);
+        reader.consume(3);
+        assert_eq!(reader.peek(5), 0b01000);
+        reader.consume(5);
+        assert_eq!(reader.peek(12), 0xff0);
+        reader.consume(16);
+        assert_eq!(reader.position(), 24);
+        assert_eq!(reader.remaining(), 3);
+        assert_eq!(reader.read_bit(), Some(true));
+        assert_eq!(reader.read_bit(), Some(false));
+        assert_eq!(reader.read_bit(), Some(true));
+        assert_eq!(reader.read_bit(), None);
+        assert_eq!(reader.peek(4), 0);
+
+        let mut reader = BitReader::with_range(bits.as_bytes(), 6, 5);
+        assert_eq!(reader.position(), 6);
+        assert_eq!(reader.remaining(), 5);
+        assert_eq!(reader.peek(5), 0b00111);
+        reader.consume(5);
+        assert_eq!(reader.remaining(), 0);
+        // Peeking past the range shows the bits after it.
+        let mut rest = BitReader::new(bits.as_bytes(), bits.len());
+        rest.consume(11);
+        assert_eq!(reader.peek(5), rest.peek(5));
+        assert_ne!(reader.peek(5), 0);
+        assert_eq!(
+            BitReader::with_range(bits.as_bytes(), 20, 100).remaining(),
+            12
+        );
+    }
+
+    #[test]
+    fn test_packed_bits() {
+        let t = true;
+        let f = false;
+        let bits: PackedBits = [f, t, f, f, t, f, f, f, t, t].into_iter().collect();
+
+        assert_eq!(bits.as_bytes(), [0x48, 0xc0]);
+        assert_eq!(bits.len(), 10);
+        assert_eq!(bits.padding(), 6);
+        assert_eq!(PackedBits::new(vec![0x48, 0xff], 10), Some(bits));
+        assert_eq!(PackedBits::new(vec![0x48, 0xff], 17), None);
+        assert_eq!(PackedBits::new(vec![0x48, 0xff], 8), None);
+        assert!(PackedBits::new(vec![], 0).unwrap().is_empty());
+        assert_eq!(PackedBits::from_bytes(vec![0x48, 0xff]).len(), 16);
+    }
+}
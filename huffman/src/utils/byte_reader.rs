@@ -0,0 +1,84 @@
+/// Sequential reader over a byte slice for the big-endian binary formats.
+/// Every read returns `None` instead of panicking when the input is too short.
+pub struct ByteReader<'a> {
+    input: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> ByteReader<'a> {
+    pub fn new(input: &'a [u8]) -> Self {
+        Self { input, pos: 0 }
+    }
+
+    pub fn read_bytes(&mut self, len: usize) -> Option<&'a [u8]> {
+        if len <= self.remaining() {
+            let bytes = &self.input[self.pos..self.pos + len];
+            self.pos += len;
+            Some(bytes)
+        } else {
+            None
+        }
+    }
+
+    pub fn read_u8(&mut self) -> Option<u8> {
+        self.read_bytes(1).map(|bytes| bytes[0])
+    }
+
+    pub fn read_u32(&mut self) -> Option<u32> {
+        self.read_bytes(4)
+            .and_then(|bytes| bytes.try_into().ok())
+            .map(u32::from_be_bytes)
+    }
+
+    pub fn read_u64(&mut self) -> Option<u64> {
+        self.read_bytes(8)
+            .and_then(|bytes| bytes.try_into().ok())
+            .map(u64::from_be_bytes)
+    }
+
+    pub fn read_to_end(&mut self) -> &'a [u8] {
+        let bytes = &self.input[self.pos..];
+        self.pos = self.input.len();
+        bytes
+    }
+
+    /// Number of bytes read so far.
+    pub fn position(&self) -> usize {
+        self.pos
+    }
+
+    pub fn remaining(&self) -> usize {
+        self.input.len() - self.pos
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_read_successful() {
+        let input = [
+            0x01, 0x00, 0x00, 0x01, 0x00, 0, 0, 0, 0, 0, 0, 0, 0x2a, 0xff,
+        ];
+        let mut reader = ByteReader::new(&input);
+
+        assert_eq!(reader.read_u8(), Some(1));
+        assert_eq!(reader.read_u32(), Some(256));
+        assert_eq!(reader.read_u64(), Some(42));
+        assert_eq!(reader.remaining(), 1);
+        assert_eq!(reader.read_to_end(), &[0xff]);
+        assert_eq!(reader.remaining(), 0);
+    }
+
+    #[test]
+    fn test_read_failed() {
+        let input = [0x01, 0x02, 0x03];
+        let mut reader = ByteReader::new(&input);
+
+        assert_eq!(reader.read_u32(), None);
+        assert_eq!(reader.read_bytes(4), None);
+        assert_eq!(reader.read_bytes(3), Some(&input[..]));
+        assert_eq!(reader.read_u8(), None);
+    }
+}
@@ -1 +1,4 @@
-pub mod type_converting;
+pub mod bit_io;
+pub(crate) mod byte_reader;
+pub mod checksum;
+pub(crate) mod type_converting;
@@ -1,7 +1,10 @@
 use super::file;
-use huffman;
+use huffman::{DecompressOptions, HuffmanDecoder, HuffmanEncoder, ParallelCoding, ParallelOptions};
 use log::{error, info};
-use std::{io::Error, str::FromStr};
+use std::{
+    io::{self, Error, Read, Write},
+    str::FromStr,
+};
 use structopt::StructOpt;
 
 #[derive(StructOpt, Debug)]
@@ -47,49 +50,208 @@ pub struct Opt {
     output_type: IOType,
     #[structopt(long)]
     output: String,
+    /// Codes the blocks on this many threads, holding the whole input in memory.
+    #[structopt(long)]
+    threads: Option<usize>,
+    /// Stops decompressing once the output would exceed this many bytes.
+    #[structopt(long)]
+    max_output: Option<usize>,
+    /// Stops decompressing once the output would exceed this many bytes per
+    /// byte of input.
+    #[structopt(long)]
+    max_ratio: Option<usize>,
+}
+
+/// Counts the bytes passing through a reader or writer.
+struct Counted<T> {
+    inner: T,
+    count: u64,
+}
+
+impl<T> Counted<T> {
+    fn new(inner: T) -> Self {
+        Self { inner, count: 0 }
+    }
+}
+
+impl<T: Read> Read for Counted<T> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let len = self.inner.read(buf)?;
+        self.count += len as u64;
+        Ok(len)
+    }
+}
+
+impl<T: Write> Write for Counted<T> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let len = self.inner.write(buf)?;
+        self.count += len as u64;
+        Ok(len)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.inner.flush()
+    }
 }
 
 impl Opt {
     pub fn handle(&self) {
-        let input = match self.input_type {
-            IOType::File => file::read_file_content(&self.input),
-            IOType::Text => Ok(self.input.clone()),
+        let result = match self.input_type {
+            IOType::File => match file::open_file(&self.input) {
+                Ok(reader) => self.handle_output(reader),
+                Err(e) => Err(e.into()),
+            },
+            IOType::Text => match self.command {
+                Command::Compress => self.handle_output(self.input.as_bytes()),
+                Command::Decompress => match Opt::from_hex(&self.input) {
+                    Ok(bytes) => self.handle_output(&bytes[..]),
+                    Err(e) => Err(e.into()),
+                },
+            },
         };
 
-        match input {
-            Ok(input) => {
-                let output = match self.command {
-                    Command::Compress => huffman::compress(&input),
-                    Command::Decompress => huffman::decompress(&input),
-                };
+        if let Err(e) = result {
+            Opt::print_error(&e);
+        }
+    }
 
-                match output {
-                    Ok(output) => match self.output_type {
-                        IOType::File => {
-                            let result = file::write_file(&self.output, &output);
-                            match result {
-                                Ok(_) => {
-                                    info!("Successfully!!!");
-                                    info!("Original length: {}", input.len());
-                                    info!("New length: {}", output.len());
-                                }
-                                Err(e) => Opt::print_error(&e.msg),
-                            }
+    fn handle_output<R: Read>(&self, reader: R) -> Result<(), huffman::Error> {
+        match self.output_type {
+            IOType::File => match file::create_file(&self.output) {
+                Ok(writer) => match self.process(reader, writer) {
+                    Ok((input_len, output_len, _)) => {
+                        info!("Successfully!!!");
+                        info!("Original length: {}", input_len);
+                        info!("New length: {}", output_len);
+                        Ok(())
+                    }
+                    Err(e) => Err(e.into()),
+                },
+                Err(e) => Err(e.into()),
+            },
+            IOType::Text => match self.process(reader, Vec::new()) {
+                Ok((input_len, output_len, output)) => {
+                    match self.command {
+                        Command::Compress => info!("Output: ```{}```", Opt::to_hex(&output)),
+                        Command::Decompress => {
+                            info!("Output: ```{:?}```", String::from_utf8_lossy(&output))
                         }
-                        IOType::Text => {
-                            info!("Output: ```{:?}```", output);
-                            info!("Original length: {}", input.len());
-                            info!("New length: {}", output.len());
-                        }
-                    },
-                    Err(e) => Opt::print_error(&e.msg),
+                    }
+                    info!("Original length: {}", input_len);
+                    info!("New length: {}", output_len);
+                    Ok(())
+                }
+                Err(e) => Err(e.into()),
+            },
+        }
+    }
+
+    /// Streams `reader` through the encoder or decoder into `writer`, block by
+    /// block, and returns the number of bytes read and written.
+    fn process<R: Read, W: Write>(&self, reader: R, writer: W) -> io::Result<(u64, u64, W)> {
+        let mut reader = Counted::new(reader);
+        let mut writer = Counted::new(writer);
+
+        match (&self.command, self.threads) {
+            (Command::Compress, None) => {
+                let mut encoder = HuffmanEncoder::new(&mut writer);
+                io::copy(&mut reader, &mut encoder)?;
+                encoder.finish()?;
+            }
+            (Command::Decompress, None) => {
+                let mut decoder = HuffmanDecoder::with_options(&mut reader, self.limits());
+                io::copy(&mut decoder, &mut writer)?;
+            }
+            (command, Some(threads)) => {
+                let options = ParallelOptions::new()
+                    .with_threads(threads)
+                    .with_decompress_options(self.limits());
+                let mut input = Vec::new();
+                reader.read_to_end(&mut input)?;
+
+                let output = match command {
+                    Command::Compress => ParallelCoding::compress(&input, &options),
+                    Command::Decompress => ParallelCoding::decompress(&input, &options),
+                };
+                match output {
+                    Ok(output) => writer.write_all(&output)?,
+                    Err(e) => return Err(io::Error::new(io::ErrorKind::InvalidData, e)),
                 }
             }
-            Err(e) => Opt::print_error(&e.msg),
         }
+        writer.flush()?;
+
+        Ok((reader.count, writer.count, writer.inner))
+    }
+
+    /// Compressed data is binary, so TEXT output prints it as hex, which is
+    /// what TEXT input to `decompress` expects back.
+    fn to_hex(bytes: &[u8]) -> String {
+        bytes.iter().map(|byte| format!("{:02x}", byte)).collect()
+    }
+
+    /// Parses the hex printed by `to_hex`, ignoring whitespace.
+    fn from_hex(hex: &str) -> io::Result<Vec<u8>> {
+        let digits: Option<Vec<u8>> = hex
+            .chars()
+            .filter(|c| !c.is_whitespace())
+            .map(|c| c.to_digit(16).map(|digit| digit as u8))
+            .collect();
+
+        match digits {
+            Some(digits) if digits.len().is_multiple_of(2) => Ok(digits
+                .chunks(2)
+                .map(|pair| (pair[0] << 4) | pair[1])
+                .collect()),
+            _ => Err(Error::new(io::ErrorKind::InvalidInput, "Invalid hex input")),
+        }
+    }
+
+    /// The decompression limits given on the command line.
+    fn limits(&self) -> DecompressOptions {
+        let options = DecompressOptions::new();
+        let options = match self.max_output {
+            Some(max_output) => options.with_max_output_len(max_output),
+            None => options,
+        };
+
+        match self.max_ratio {
+            Some(max_ratio) => options.with_max_expansion_ratio(max_ratio),
+            None => options,
+        }
+    }
+
+    /// Logs `e` followed by every error in its `source()` chain.
+    fn print_error(e: &dyn std::error::Error) {
+        error!("Error: {}", e);
+
+        let mut source = e.source();
+        while let Some(cause) = source {
+            error!("Caused by: {}", cause);
+            source = cause.source();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_hex_roundtrip() {
+        let compressed = huffman::compress_bytes(&[0x00, 0xff, 0x80, 0xc3, 0x28]).unwrap();
+        let hex = Opt::to_hex(&compressed);
+
+        assert!(hex.starts_with("8948554601"));
+        assert_eq!(Opt::from_hex(&hex).unwrap(), compressed);
+        assert_eq!(Opt::from_hex(" 89 48\n55 ").unwrap(), [0x89, 0x48, 0x55]);
     }
 
-    fn print_error(msg: &str) {
-        error!("{}", msg);
+    #[test]
+    fn test_from_hex_failed() {
+        assert!(Opt::from_hex("894").is_err());
+        assert!(Opt::from_hex("zz").is_err());
+        assert!(Opt::from_hex("+1").is_err());
+        assert!(Opt::from_hex("é0").is_err());
     }
 }
@@ -1,105 +1,78 @@
-use std::fmt;
 use std::{
-    fs::{remove_file, File},
-    io::{Read, Write},
+    fs::File,
+    io::{self, BufReader, BufWriter},
 };
 
-// use crate::errors::file_error::FileError;
+use huffman::FileError;
 
-pub fn read_file_content(path: &str) -> Result<String, FileError> {
+pub fn open_file(path: &str) -> Result<BufReader<File>, FileError> {
     match File::open(path) {
-        Ok(mut file) => {
-            let mut contents = String::new();
-            match file.read_to_string(&mut contents) {
-                Ok(_) => Ok(contents),
-                Err(_) => Err(FileError::cannot_read_file(path)),
-            }
-        }
-        Err(_) => Err(FileError::cannot_open_file(path)),
+        Ok(file) => Ok(BufReader::new(file)),
+        Err(e) => Err(FileError::cannot_open_file(path).with_source(e)),
     }
 }
 
-pub fn write_file(path: &str, content: &str) -> Result<(), FileError> {
+pub fn create_file(path: &str) -> Result<BufWriter<File>, FileError> {
     match File::create_new(path) {
-        Ok(mut file) => match file.write_all(content.as_bytes()) {
-            Ok(_) => Ok(()),
-            Err(_) => Err(FileError::cannot_write_file(path)),
+        Ok(file) => Ok(BufWriter::new(file)),
+        Err(e) => match e.kind() {
+            io::ErrorKind::AlreadyExists => {
+                Err(FileError::file_already_existed(path).with_source(e))
+            }
+            _ => Err(FileError::cannot_create_file(path).with_source(e)),
         },
-        Err(_) => Err(FileError::file_already_existed(path)),
-    }
-}
-
-#[derive(Debug, PartialEq)]
-pub struct FileError {
-    pub msg: String,
-}
-
-impl FileError {
-    pub fn cannot_open_file(path: &str) -> Self {
-        let msg: String = format!("There is an error when trying to open file: {}", path);
-        Self { msg: msg }
-    }
-
-    pub fn cannot_read_file(path: &str) -> Self {
-        let msg: String = format!("There is an error when trying to read file: {}", path);
-        Self { msg: msg }
-    }
-
-    pub fn cannot_write_file(path: &str) -> Self {
-        let msg: String = format!("There is an error when trying to write file: {}", path);
-        Self { msg: msg }
-    }
-
-    pub fn file_already_existed(path: &str) -> Self {
-        let msg: String = format!("This file has already existed: {}", path);
-        Self { msg: msg }
-    }
-}
-
-impl fmt::Display for FileError {
-    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        write!(f, "Error: {}", &self.msg)
     }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use std::fs::remove_file;
+    use std::io::{Read, Write};
 
     #[test]
-    fn test_read_file_content_successful() {
+    fn test_open_file_successful() {
         let path = "data/sample_1.txt".to_string();
-        let result = read_file_content(&path);
+        let result = open_file(&path);
 
         assert!(result.is_ok());
-        assert_eq!(result.unwrap(), "This is the sample 1.");
+        let mut content = Vec::new();
+        result.unwrap().read_to_end(&mut content).unwrap();
+        assert_eq!(content, b"This is the sample 1.");
     }
 
     #[test]
-    fn test_read_file_content_failed() {
+    fn test_open_file_failed() {
         let path = "data/invalid_sample_1.txt".to_string();
-        let result = read_file_content(&path);
+        let result = open_file(&path);
 
         assert!(result.is_err());
+        assert_eq!(result.unwrap_err(), FileError::cannot_open_file(&path));
     }
 
     #[test]
-    fn test_write_file_content_successful() {
+    fn test_create_file_successful() {
         let path = "data/output_1.txt".to_string();
-        let content = "This is the outout 1.";
+        let content = b"This is the outout 1.";
         let _ = remove_file(&path);
-        let result = write_file(&path, content);
+        let result = create_file(&path);
 
         assert!(result.is_ok());
+        assert!(result.unwrap().write_all(content).is_ok());
     }
 
     #[test]
-    fn test_write_file_content_failed() {
+    fn test_create_file_failed() {
         let path = "data/sample_1.txt".to_string();
-        let content = "This is the output 1.";
-        let result = write_file(&path, content);
+        let result = create_file(&path);
 
         assert!(result.is_err());
         assert_eq!(result.unwrap_err(), FileError::file_already_existed(&path));
+
+        let path = "data/missing_dir/output_1.txt".to_string();
+        let result = create_file(&path);
+
+        assert!(result.is_err());
+        assert_eq!(result.unwrap_err(), FileError::cannot_create_file(&path));
     }
 }